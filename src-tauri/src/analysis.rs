@@ -0,0 +1,384 @@
+// Content analysis with ffmpeg's detection filters. Each one logs what it
+// finds to stderr while the file decodes to the null muxer.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use tauri::Emitter;
+
+use crate::{resolve_ffmpeg, JobStarted, ProcessRegistry, JOB_CANCELLED_ERROR};
+
+// A span of the file, in seconds from its start
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct TimeRange {
+    pub start: f64,
+    pub end: f64,
+}
+
+// The analysed part of a file; no start/end means the whole file
+#[derive(Debug, Clone, Copy)]
+pub struct Window {
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl Window {
+    fn validate(&self) -> Result<(), String> {
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            if end <= start {
+                return Err(format!("Invalid range: end {} is not after start {}", end, start));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Decodes `window` of the file through `filter` ("-vf" or "-af") and returns
+// ffmpeg's log. Timestamps in it are relative to the window start.
+fn run_filter(ffmpeg_path: &Path, file_path: &str, window: Window, flag: &str, filter: &str) -> Result<String, String> {
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.args(["-hide_banner", "-nostats"]);
+    if let Some(start) = window.start {
+        cmd.args(["-ss", &start.to_string()]);
+    }
+    if let Some(end) = window.end {
+        cmd.args(["-t", &(end - window.start.unwrap_or(0.0)).to_string()]);
+    }
+    cmd.args(["-i", file_path]);
+    // Skip decoding the stream the filter doesn't look at
+    cmd.arg(if flag == "-vf" { "-an" } else { "-vn" });
+    cmd.args([flag, filter, "-f", "null", "-"]);
+
+    let output = cmd
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("FFmpeg analysis failed: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("FFmpeg could not analyse {}", file_path));
+    }
+    Ok(String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+// Value following `key` in a detection log line, e.g. "black_start:" -> 1.5
+fn field(line: &str, key: &str) -> Option<f64> {
+    let rest = &line[line.find(key)? + key.len()..];
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+// Minimum length, in seconds, of a black run worth reporting
+const DEFAULT_BLACK_MIN_DURATION: f64 = 0.1;
+// Share of the picture's pixels that has to be black, 0..1
+const DEFAULT_BLACK_PICTURE_THRESHOLD: f64 = 0.98;
+
+// Result of `detect_black`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlackDetection {
+    // Black runs, in seconds of the source file
+    pub ranges: Vec<TimeRange>,
+    // The analysed range with leading and trailing black removed, ready to be
+    // used as the clip's in/out points. None if it is black throughout.
+    pub trimmed: Option<TimeRange>,
+}
+
+pub fn detect_black_ranges(
+    ffmpeg_path: &Path,
+    file_path: &str,
+    window: Window,
+    min_duration: f64,
+    picture_threshold: f64,
+) -> Result<Vec<TimeRange>, String> {
+    let filter = format!("blackdetect=d={}:pic_th={}", min_duration, picture_threshold);
+    let log = run_filter(ffmpeg_path, file_path, window, "-vf", &filter)?;
+
+    // [blackdetect @ 0x...] black_start:0 black_end:1.52 black_duration:1.52
+    let offset = window.start.unwrap_or(0.0);
+    Ok(log
+        .lines()
+        .filter(|line| line.contains("blackdetect"))
+        .filter_map(|line| {
+            Some(TimeRange {
+                start: offset + field(line, "black_start:")?,
+                end: offset + field(line, "black_end:")?,
+            })
+        })
+        .collect())
+}
+
+// `span` minus any black run touching either end of it
+fn trim_edges(span: TimeRange, ranges: &[TimeRange]) -> Option<TimeRange> {
+    // Detected runs are only accurate to a frame, so allow some slack at the edges
+    const EDGE: f64 = 0.05;
+    let mut trimmed = span;
+    for range in ranges {
+        if range.start <= trimmed.start + EDGE {
+            trimmed.start = trimmed.start.max(range.end);
+        }
+    }
+    for range in ranges.iter().rev() {
+        if range.end >= trimmed.end - EDGE {
+            trimmed.end = trimmed.end.min(range.start);
+        }
+    }
+    (trimmed.end > trimmed.start).then_some(trimmed)
+}
+
+// Command to find black stretches of video in a file (or in start..end of it)
+// and the range left after cutting black off both ends
+#[tauri::command]
+pub async fn detect_black(
+    file_path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    min_duration: Option<f64>,
+    picture_threshold: Option<f64>,
+    app_handle: tauri::AppHandle,
+) -> Result<BlackDetection, String> {
+    let window = Window { start, end };
+    window.validate()?;
+    let picture_threshold = picture_threshold.unwrap_or(DEFAULT_BLACK_PICTURE_THRESHOLD);
+    if !(0.0..=1.0).contains(&picture_threshold) {
+        return Err(format!("picture_threshold must be between 0 and 1, got {}", picture_threshold));
+    }
+
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    let ranges = detect_black_ranges(
+        &ffmpeg_path,
+        &file_path,
+        window,
+        min_duration.unwrap_or(DEFAULT_BLACK_MIN_DURATION),
+        picture_threshold,
+    )?;
+
+    let span_end = match end {
+        Some(end) => end,
+        None => crate::probe::probe(&ffmpeg_path, &file_path)?
+            .duration
+            .ok_or("Could not determine the file's duration")?,
+    };
+    let span = TimeRange { start: start.unwrap_or(0.0), end: span_end };
+    let trimmed = trim_edges(span, &ranges);
+    Ok(BlackDetection { ranges, trimmed })
+}
+
+// Level below which audio counts as silent, in dBFS
+const DEFAULT_SILENCE_NOISE_DB: f64 = -40.0;
+// Minimum length, in seconds, of a silence worth reporting
+const DEFAULT_SILENCE_MIN_DURATION: f64 = 0.5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct SilenceInterval {
+    pub start: f64,
+    pub end: f64,
+    pub duration: f64,
+}
+
+pub fn detect_silence_intervals(
+    ffmpeg_path: &Path,
+    file_path: &str,
+    window: Window,
+    noise_db: f64,
+    min_duration: f64,
+) -> Result<Vec<SilenceInterval>, String> {
+    let filter = format!("silencedetect=noise={}dB:d={}", noise_db, min_duration);
+    let log = run_filter(ffmpeg_path, file_path, window, "-af", &filter)?;
+
+    // [silencedetect @ 0x...] silence_start: 1.23
+    // [silencedetect @ 0x...] silence_end: 2.5 | silence_duration: 1.27
+    let offset = window.start.unwrap_or(0.0);
+    let mut intervals = Vec::new();
+    let mut open_start = None;
+    for line in log.lines().filter(|line| line.contains("silencedetect")) {
+        if let Some(start) = field(line, "silence_start:") {
+            open_start = Some(offset + start.max(0.0));
+        } else if let Some(end) = field(line, "silence_end:") {
+            let start = open_start.take().unwrap_or(offset);
+            let end = offset + end;
+            intervals.push(SilenceInterval { start, end, duration: end - start });
+        }
+    }
+
+    // Older ffmpeg builds don't close a silence that runs to the end of input
+    if let Some(start) = open_start {
+        let end = match window.end {
+            Some(end) => Some(end),
+            None => crate::probe::probe(ffmpeg_path, file_path)?.duration,
+        };
+        if let Some(end) = end.filter(|end| *end > start) {
+            intervals.push(SilenceInterval { start, end, duration: end - start });
+        }
+    }
+    Ok(intervals)
+}
+
+// Command to find silent stretches of a file's audio (or of start..end of it).
+// `noise_db` is the level counted as silence and `min_duration` the shortest
+// silence reported.
+#[tauri::command]
+pub async fn detect_silence(
+    file_path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    noise_db: Option<f64>,
+    min_duration: Option<f64>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<SilenceInterval>, String> {
+    let window = Window { start, end };
+    window.validate()?;
+    let noise_db = noise_db.unwrap_or(DEFAULT_SILENCE_NOISE_DB);
+    if noise_db >= 0.0 {
+        return Err(format!("noise_db must be below 0 dBFS, got {}", noise_db));
+    }
+    let min_duration = min_duration.unwrap_or(DEFAULT_SILENCE_MIN_DURATION);
+    if min_duration <= 0.0 {
+        return Err(format!("min_duration must be positive, got {}", min_duration));
+    }
+
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    detect_silence_intervals(&ffmpeg_path, &file_path, window, noise_db, min_duration)
+}
+
+// Kept around detected speech when tightening a clip, so onsets and tails of
+// words aren't clipped
+const SPEECH_PADDING: f64 = 0.15;
+
+// start..end of a file narrowed to where its audio isn't silent, padded by
+// SPEECH_PADDING. Returns the range unchanged if it is silent throughout.
+pub fn non_silent_range(ffmpeg_path: &Path, file_path: &str, start: f64, end: f64) -> Result<(f64, f64), String> {
+    let window = Window { start: Some(start), end: Some(end) };
+    let intervals = detect_silence_intervals(
+        ffmpeg_path,
+        file_path,
+        window,
+        DEFAULT_SILENCE_NOISE_DB,
+        DEFAULT_SILENCE_MIN_DURATION,
+    )?;
+
+    // Same frame-level slack as for black runs
+    const EDGE: f64 = 0.05;
+    let mut trimmed = (start, end);
+    if let Some(first) = intervals.first().filter(|s| s.start <= start + EDGE) {
+        trimmed.0 = (first.end - SPEECH_PADDING).max(start);
+    }
+    if let Some(last) = intervals.last().filter(|s| s.end >= end - EDGE) {
+        trimmed.1 = (last.start + SPEECH_PADDING).min(end);
+    }
+    if trimmed.1 <= trimmed.0 {
+        return Ok((start, end));
+    }
+    Ok(trimmed)
+}
+
+// Seconds of video idet looks at; enough frames for a clear majority
+const INTERLACE_SAMPLE_SECONDS: f64 = 10.0;
+
+// Whether the start of the file looks interlaced to the `idet` filter: more
+// frames classed as top- or bottom-field-first than as progressive
+pub fn detect_interlacing(ffmpeg_path: &Path, file_path: &str) -> Result<bool, String> {
+    let window = Window { start: None, end: Some(INTERLACE_SAMPLE_SECONDS) };
+    let log = run_filter(ffmpeg_path, file_path, window, "-vf", "idet")?;
+
+    // [Parsed_idet_0 @ 0x...] Multi frame detection: TFF: 0 BFF: 0 Progressive: 250 Undetermined: 1
+    let line = log
+        .lines()
+        .find(|line| line.contains("Multi frame detection:"))
+        .ok_or("FFmpeg reported no interlacing statistics")?;
+    let count = |key| field(line, key).unwrap_or(0.0);
+    Ok(count("TFF:") + count("BFF:") > count("Progressive:"))
+}
+
+// Seconds decoded at each end of the file by a fast verify_media scan. Partial
+// downloads and truncated copies break at the end, bad headers at the start.
+const FAST_SCAN_SECONDS: f64 = 5.0;
+// Decode errors kept in a MediaHealth; a broken file can log thousands
+const MAX_HEALTH_ERRORS: usize = 50;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanMode {
+    // The first and last few seconds
+    Fast,
+    // Every frame; as slow as decoding the whole file
+    Full,
+}
+
+// Result of `verify_media`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaHealth {
+    // Decoded without a single error
+    pub ok: bool,
+    pub mode: ScanMode,
+    // ffmpeg's error lines, the first MAX_HEALTH_ERRORS of them
+    pub errors: Vec<String>,
+    // Lines beyond those in `errors`
+    pub more_errors: usize,
+}
+
+// Decodes the file (one end of it with `seek`) to the null muxer as part of
+// `job`, adding ffmpeg's errors to `health`
+fn scan_errors(
+    registry: &ProcessRegistry,
+    job: &str,
+    ffmpeg_path: &Path,
+    file_path: &str,
+    seek: &[&str],
+    health: &mut MediaHealth,
+) -> Result<(), String> {
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.args(["-hide_banner", "-nostats", "-v", "error"]);
+    cmd.args(seek);
+    cmd.args(["-i", file_path, "-f", "null", "-"]);
+    let status = registry.run_with_stderr(job, &mut cmd, |line| {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        if health.errors.len() < MAX_HEALTH_ERRORS {
+            health.errors.push(line.to_string());
+        } else {
+            health.more_errors += 1;
+        }
+    });
+    if registry.is_cancelled(job) {
+        return Err(JOB_CANCELLED_ERROR.to_string());
+    }
+    let status = status.map_err(|e| format!("FFmpeg media check failed: {}", e))?;
+    if !status.success() && health.errors.is_empty() {
+        health.errors.push(format!("FFmpeg could not decode {}", file_path));
+    }
+    Ok(())
+}
+
+// Command to check a file decodes cleanly, e.g. before importing a clip that
+// may be partially downloaded or corrupt. `mode` "fast" (the default) decodes
+// a few seconds at each end, "full" the whole file. `job-started` carries the
+// id for cancel_job, as a full scan of a long file takes a while.
+#[tauri::command]
+pub async fn verify_media(
+    file_path: String,
+    mode: Option<ScanMode>,
+    app_handle: tauri::AppHandle,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<MediaHealth, String> {
+    if !Path::new(&file_path).is_file() {
+        return Err(format!("File not found: {}", file_path));
+    }
+    let mode = mode.unwrap_or(ScanMode::Fast);
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+
+    let job_id = registry.new_job_id("verify");
+    let _job = registry.begin(&job_id)?;
+    let _ = app_handle.emit("job-started", JobStarted { job_id: job_id.clone(), kind: "verify".to_string() });
+
+    let mut health = MediaHealth { ok: false, mode, errors: Vec::new(), more_errors: 0 };
+    let (head, tail) = (FAST_SCAN_SECONDS.to_string(), (-FAST_SCAN_SECONDS).to_string());
+    let passes: Vec<Vec<&str>> = match mode {
+        ScanMode::Fast => vec![vec!["-t", &head], vec!["-sseof", &tail]],
+        ScanMode::Full => vec![Vec::new()],
+    };
+    for seek in passes {
+        scan_errors(&registry, &job_id, &ffmpeg_path, &file_path, &seek, &mut health)?;
+    }
+    health.ok = health.errors.is_empty();
+    Ok(health)
+}
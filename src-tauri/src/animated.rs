@@ -0,0 +1,277 @@
+// Animated images of one clip's range or a whole timeline, made in one pass.
+// WebP is a smaller and better looking alternative to GIF for the web: full
+// color instead of a 256-color palette, and lossy compression. APNG is
+// lossless and keeps alpha, for overlays and stickers.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+use tauri::Emitter;
+
+use crate::capabilities::{self, CapabilitiesCache};
+use crate::{
+    emit_warning, probe, resolve_ffmpeg, ClipData, FfmpegProgress, JobGuard, JobStarted, ProcessRegistry,
+    JOB_CANCELLED_ERROR,
+};
+
+const DEFAULT_FPS: u32 = 15;
+const MAX_FPS: u32 = 60;
+const DEFAULT_WIDTH: u32 = 480;
+const MAX_WIDTH: u32 = 1920;
+// libwebp's -q:v, 0 (smallest) to 100 (best)
+const DEFAULT_WEBP_QUALITY: u32 = 75;
+const MAX_WEBP_QUALITY: u32 = 100;
+// WebP and APNG both store the loop count in 16 bits; 0 loops forever
+const MAX_LOOPS: u32 = 65535;
+// APNG stores every frame losslessly, so beyond this it gets large fast
+const APNG_WARN_SECONDS: f64 = 10.0;
+
+// Payload of `webp-progress` and `apng-progress`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AnimatedProgress {
+    job_id: String,
+    percent: u8,
+}
+
+struct FrameSettings {
+    fps: u32,
+    width: u32,
+    // Every frame's height, so clips of other shapes can be joined
+    height: u32,
+    // Keep the sources' alpha, and letterbox with transparency
+    alpha: bool,
+}
+
+// Scales a clip into the output frame, letterboxed when its shape differs
+// from the first clip's
+fn clip_filter(input: usize, settings: &FrameSettings) -> String {
+    let (format, pad_color) = match settings.alpha {
+        true => ("format=rgba,", "black@0"),
+        false => ("", "black"),
+    };
+    format!(
+        "[{i}:v]fps={fps},{format}scale={w}:{h}:force_original_aspect_ratio=decrease:flags=lanczos,\
+         pad={w}:{h}:(ow-iw)/2:(oh-ih)/2:color={pad_color},setsar=1[v{i}]",
+        i = input,
+        fps = settings.fps,
+        format = format,
+        w = settings.width,
+        h = settings.height,
+        pad_color = pad_color
+    )
+}
+
+fn frames_filter(clip_count: usize, settings: &FrameSettings) -> String {
+    let mut graph: Vec<String> = (0..clip_count).map(|i| clip_filter(i, settings)).collect();
+    let inputs: String = (0..clip_count).map(|i| format!("[v{}]", i)).collect();
+    graph.push(format!("{}concat=n={}:v=1:a=0[out]", inputs, clip_count));
+    graph.join(";")
+}
+
+// Checks what every animated format shares; `extensions` are the accepted
+// output extensions, the first naming the format in errors
+fn validate(
+    clips: &[ClipData],
+    fps: u32,
+    width: u32,
+    loop_count: u32,
+    output_path: &str,
+    extensions: &[&str],
+) -> Result<(), String> {
+    let name = extensions[0].to_ascii_uppercase();
+    if clips.is_empty() {
+        return Err("No clips to export".to_string());
+    }
+    for clip in clips {
+        if !Path::new(&clip.file_path).is_file() {
+            return Err(format!("File not found: {}", clip.file_path));
+        }
+        if clip.start_time < 0.0 || clip.end_time <= clip.start_time {
+            return Err(format!("Invalid range {} to {} for {}", clip.start_time, clip.end_time, clip.file_path));
+        }
+    }
+    if !(1..=MAX_FPS).contains(&fps) {
+        return Err(format!("{} fps must be between 1 and {}, got {}", name, MAX_FPS, fps));
+    }
+    if !(16..=MAX_WIDTH).contains(&width) {
+        return Err(format!("{} width must be between 16 and {}, got {}", name, MAX_WIDTH, width));
+    }
+    if loop_count > MAX_LOOPS {
+        return Err(format!("{} loop_count must be at most {}, got {}", name, MAX_LOOPS, loop_count));
+    }
+    let extension = Path::new(output_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    if !extension.is_some_and(|e| extensions.contains(&e.as_str())) {
+        return Err(format!("{} output must be a .{} file, got {}", name, extensions.join(" or ."), output_path));
+    }
+    Ok(())
+}
+
+// The output frame: `width` wide, and as tall as the first clip's shape
+// makes it, rounded to even as 4:2:0 chroma needs
+fn frame_settings(
+    ffmpeg_path: &Path,
+    clips: &[ClipData],
+    fps: u32,
+    width: u32,
+    alpha: bool,
+) -> Result<FrameSettings, String> {
+    let (source_width, source_height) = probe::probe(ffmpeg_path, &clips[0].file_path)?
+        .first_video()
+        .and_then(|v| v.display_size())
+        .ok_or_else(|| format!("No video in {}", clips[0].file_path))?;
+    let height = ((width as f64 * source_height as f64 / source_width as f64 / 2.0).round() as u32 * 2).max(2);
+    Ok(FrameSettings { fps, width, height, alpha })
+}
+
+// An ffmpeg command reading each clip's source range and joining them into
+// [out]; the caller adds the encoder and output
+fn frames_command(ffmpeg_path: &Path, clips: &[ClipData], settings: &FrameSettings) -> Command {
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.args(["-y", "-progress", "pipe:1"]);
+    for clip in clips {
+        cmd.args([
+            "-ss", &clip.start_time.to_string(),
+            "-t", &(clip.end_time - clip.start_time).to_string(),
+            "-i", &clip.file_path,
+        ]);
+    }
+    cmd.args(["-filter_complex", &frames_filter(clips.len(), settings), "-map", "[out]"]);
+    cmd
+}
+
+// Registers a new `kind` job and announces its id with `job-started`
+fn start_job<'a>(
+    app_handle: &tauri::AppHandle,
+    registry: &'a ProcessRegistry,
+    kind: &str,
+) -> Result<(String, JobGuard<'a>), String> {
+    let job_id = registry.new_job_id(kind);
+    let job = registry.begin(&job_id)?;
+    let _ = app_handle.emit("job-started", JobStarted { job_id: job_id.clone(), kind: kind.to_string() });
+    Ok((job_id, job))
+}
+
+// Runs the encode of job `job_id`, followed by `{kind}-progress`. A cancelled
+// or failed encode leaves no file behind.
+fn run_encode(
+    app_handle: &tauri::AppHandle,
+    registry: &ProcessRegistry,
+    job_id: &str,
+    kind: &str,
+    mut cmd: Command,
+    total_duration: f64,
+    output_path: String,
+) -> Result<String, String> {
+    let name = kind.to_ascii_uppercase();
+    let event = format!("{}-progress", kind);
+    let emit_progress = |percent: u8| {
+        let _ = app_handle.emit(&event, AnimatedProgress { job_id: job_id.to_string(), percent });
+    };
+
+    let report = |progress: &FfmpegProgress| {
+        let fraction = progress.out_time.map_or(0.0, |t| (t / total_duration).min(1.0));
+        emit_progress((fraction * 99.0) as u8);
+    };
+    let status = registry.run_with_progress(job_id, &mut cmd, report, |_| {});
+
+    if registry.is_cancelled(job_id) {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(JOB_CANCELLED_ERROR.to_string());
+    }
+    let status = status.map_err(|e| format!("FFmpeg {} export failed: {}", name, e))?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(format!("FFmpeg {} export exited with error", name));
+    }
+    emit_progress(100);
+    Ok(output_path)
+}
+
+fn total_duration(clips: &[ClipData]) -> f64 {
+    clips.iter().map(|c| c.end_time - c.start_time).sum()
+}
+
+// Command to make an animated WebP of `clips`: one clip for a range of a
+// single file, or the timeline's clips played back to back. Only each clip's
+// source range is used, without speed changes or effects. `fps` defaults to
+// 15, `width` to 480 (the height follows the first clip's shape), `quality`
+// to 75 and `loop_count` to 0, looping forever. Needs an ffmpeg with libwebp.
+// `job-started` carries the id for cancel_job and `webp-progress` follows the
+// encode. Returns the output path.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_webp(
+    clips: Vec<ClipData>,
+    fps: Option<u32>,
+    width: Option<u32>,
+    quality: Option<u32>,
+    loop_count: Option<u32>,
+    output_path: String,
+    app_handle: tauri::AppHandle,
+    registry: tauri::State<'_, ProcessRegistry>,
+    capabilities: tauri::State<'_, CapabilitiesCache>,
+) -> Result<String, String> {
+    let fps = fps.unwrap_or(DEFAULT_FPS);
+    let width = width.unwrap_or(DEFAULT_WIDTH);
+    let quality = quality.unwrap_or(DEFAULT_WEBP_QUALITY);
+    let loop_count = loop_count.unwrap_or(0);
+    validate(&clips, fps, width, loop_count, &output_path, &["webp"])?;
+    if quality > MAX_WEBP_QUALITY {
+        return Err(format!("WebP quality must be between 0 and {}, got {}", MAX_WEBP_QUALITY, quality));
+    }
+    capabilities::require_encoder(&app_handle, &capabilities, "libwebp", "WebP export")?;
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+
+    let settings = frame_settings(&ffmpeg_path, &clips, fps, width, false)?;
+    let mut cmd = frames_command(&ffmpeg_path, &clips, &settings);
+    cmd.args(["-c:v", "libwebp", "-loop", &loop_count.to_string(), "-q:v", &quality.to_string()]);
+    cmd.arg(&output_path);
+
+    let (job_id, _job) = start_job(&app_handle, &registry, "webp")?;
+    run_encode(&app_handle, &registry, &job_id, "webp", cmd, total_duration(&clips), output_path)
+}
+
+// Command to make a lossless animated PNG of `clips`, like export_webp. Alpha
+// in the sources is kept, and clips of another shape are letterboxed with
+// transparency. `loop_count` 0 plays forever. The output is .apng or .png;
+// `export-warning` (under the job id) notes a long one will be large.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_apng(
+    clips: Vec<ClipData>,
+    fps: Option<u32>,
+    width: Option<u32>,
+    loop_count: Option<u32>,
+    output_path: String,
+    window: tauri::Window,
+    app_handle: tauri::AppHandle,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<String, String> {
+    let fps = fps.unwrap_or(DEFAULT_FPS);
+    let width = width.unwrap_or(DEFAULT_WIDTH);
+    let loop_count = loop_count.unwrap_or(0);
+    validate(&clips, fps, width, loop_count, &output_path, &["apng", "png"])?;
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+
+    let settings = frame_settings(&ffmpeg_path, &clips, fps, width, true)?;
+    let mut cmd = frames_command(&ffmpeg_path, &clips, &settings);
+    cmd.args(["-c:v", "apng", "-pix_fmt", "rgba", "-plays", &loop_count.to_string(), "-f", "apng"]);
+    cmd.arg(&output_path);
+
+    let (job_id, _job) = start_job(&app_handle, &registry, "apng")?;
+    let duration = total_duration(&clips);
+    if duration > APNG_WARN_SECONDS {
+        emit_warning(
+            &window,
+            &job_id,
+            format!(
+                "APNG is lossless, so {:.0} seconds at {}x{} will make a large file; WebP is far smaller",
+                duration, settings.width, settings.height
+            ),
+        );
+    }
+    run_encode(&app_handle, &registry, &job_id, "apng", cmd, duration, output_path)
+}
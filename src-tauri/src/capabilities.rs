@@ -0,0 +1,156 @@
+// What the bundled ffmpeg was built with. Optional encoders and protocols
+// (libwebp, SRT, ...) depend on the build, so features using them check here
+// first and fail with a clear message instead of an ffmpeg error mid-run.
+// Queried once per run of the app; the binary doesn't change underneath it.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::resolve_ffmpeg;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FfmpegCapabilities {
+    // First line of `ffmpeg -version`, e.g. "ffmpeg version 7.0.1 ..."
+    pub version: String,
+    pub encoders: Vec<String>,
+    // Output formats, e.g. "mp4" or "libndi_newtek"
+    pub muxers: Vec<String>,
+    // Protocols ffmpeg can write to
+    pub output_protocols: Vec<String>,
+}
+
+impl FfmpegCapabilities {
+    pub fn has_encoder(&self, name: &str) -> bool {
+        self.encoders.iter().any(|e| e == name)
+    }
+
+    pub fn has_muxer(&self, name: &str) -> bool {
+        self.muxers.iter().any(|m| m == name)
+    }
+
+    pub fn has_output_protocol(&self, name: &str) -> bool {
+        self.output_protocols.iter().any(|p| p == name)
+    }
+}
+
+#[derive(Default)]
+pub struct CapabilitiesCache(Mutex<Option<FfmpegCapabilities>>);
+
+fn ffmpeg_output(ffmpeg_path: &Path, arg: &str) -> Result<String, String> {
+    let output = Command::new(ffmpeg_path)
+        .args(["-hide_banner", arg])
+        .output()
+        .map_err(|e| format!("Failed to run FFmpeg {}: {}", arg, e))?;
+    if !output.status.success() {
+        return Err(format!("FFmpeg {} exited with error", arg));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Encoder names from `-encoders`, listed after a "------" line as
+// " V....D libx264   libx264 H.264 / AVC ..."
+fn parse_encoders(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
+
+// Muxer names from `-muxers`, listed after a " --" line as
+// "  E mp4             MP4 (MPEG-4 Part 14)"
+fn parse_muxers(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("--"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
+
+// Names under "Output:" in `-protocols`, one per indented line
+fn parse_output_protocols(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip_while(|line| line.trim() != "Output:")
+        .skip(1)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn detect(ffmpeg_path: &Path) -> Result<FfmpegCapabilities, String> {
+    Ok(FfmpegCapabilities {
+        version: ffmpeg_output(ffmpeg_path, "-version")?.lines().next().unwrap_or_default().to_string(),
+        encoders: parse_encoders(&ffmpeg_output(ffmpeg_path, "-encoders")?),
+        muxers: parse_muxers(&ffmpeg_output(ffmpeg_path, "-muxers")?),
+        output_protocols: parse_output_protocols(&ffmpeg_output(ffmpeg_path, "-protocols")?),
+    })
+}
+
+// The bundled ffmpeg's capabilities, detected on first use
+pub fn capabilities(app_handle: &tauri::AppHandle, cache: &CapabilitiesCache) -> Result<FfmpegCapabilities, String> {
+    let mut cached = cache.0.lock().unwrap();
+    if let Some(capabilities) = &*cached {
+        return Ok(capabilities.clone());
+    }
+    let capabilities = detect(&resolve_ffmpeg(app_handle)?)?;
+    *cached = Some(capabilities.clone());
+    Ok(capabilities)
+}
+
+// Fails unless the bundled ffmpeg has `encoder`, which `feature` needs
+pub fn require_encoder(
+    app_handle: &tauri::AppHandle,
+    cache: &CapabilitiesCache,
+    encoder: &str,
+    feature: &str,
+) -> Result<(), String> {
+    if !capabilities(app_handle, cache)?.has_encoder(encoder) {
+        return Err(format!("{} needs an FFmpeg built with {}, which the bundled one lacks", feature, encoder));
+    }
+    Ok(())
+}
+
+// Fails unless the bundled ffmpeg can write `muxer`, which `feature` needs
+pub fn require_muxer(
+    app_handle: &tauri::AppHandle,
+    cache: &CapabilitiesCache,
+    muxer: &str,
+    feature: &str,
+) -> Result<(), String> {
+    if !capabilities(app_handle, cache)?.has_muxer(muxer) {
+        return Err(format!("{} needs an FFmpeg built with {}, which the bundled one lacks", feature, muxer));
+    }
+    Ok(())
+}
+
+// Fails unless the bundled ffmpeg can write to `protocol`, which `feature`
+// needs
+pub fn require_output_protocol(
+    app_handle: &tauri::AppHandle,
+    cache: &CapabilitiesCache,
+    protocol: &str,
+    feature: &str,
+) -> Result<(), String> {
+    if !capabilities(app_handle, cache)?.has_output_protocol(protocol) {
+        return Err(format!("{} needs an FFmpeg built with {}, which the bundled one lacks", feature, protocol));
+    }
+    Ok(())
+}
+
+// Command to list what the bundled ffmpeg supports, so the UI can hide
+// features it can't run
+#[tauri::command]
+pub async fn get_ffmpeg_capabilities(
+    app_handle: tauri::AppHandle,
+    cache: tauri::State<'_, CapabilitiesCache>,
+) -> Result<FfmpegCapabilities, String> {
+    capabilities(&app_handle, &cache)
+}
@@ -0,0 +1,193 @@
+// Keeps the concat intermediates compatible. Each clip is trimmed with the
+// same codec, but the encoder keeps the source's size, frame rate, pixel
+// format and audio layout, and the concat demuxer silently produces a broken
+// file when the segments disagree. Clips that differ from the first one are
+// scaled, retimed and resampled to match it while they are trimmed.
+
+use crate::probe::{MediaInfo, StreamKind};
+
+// Fallbacks when the first clip doesn't say
+const DEFAULT_FPS: f64 = 30.0;
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+const DEFAULT_CHANNELS: &str = "stereo";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Format {
+    width: Option<u32>,
+    height: Option<u32>,
+    fps: Option<f64>,
+    pix_fmt: Option<String>,
+    sample_rate: Option<u32>,
+    channels: Option<String>,
+}
+
+impl Format {
+    fn of(info: &MediaInfo) -> Self {
+        let video = info.first_video();
+        let audio = info.streams.iter().find(|s| s.kind == StreamKind::Audio);
+        Format {
+            width: video.and_then(|v| v.width),
+            height: video.and_then(|v| v.height),
+            // Rounded so 29.97 reported as 29.970030 still matches
+            fps: video.and_then(|v| v.fps).map(|f| (f * 1000.0).round() / 1000.0),
+            pix_fmt: video.and_then(|v| v.pix_fmt.clone()),
+            sample_rate: audio.and_then(|a| a.sample_rate),
+            channels: audio.and_then(|a| a.channels.clone()),
+        }
+    }
+
+    fn video_matches(&self, other: &Format) -> bool {
+        self.width == other.width && self.height == other.height && self.fps == other.fps && self.pix_fmt == other.pix_fmt
+    }
+
+    fn audio_matches(&self, other: &Format) -> bool {
+        self.sample_rate == other.sample_rate && self.channels == other.channels
+    }
+}
+
+// How the clips that differ from the first are brought in line
+pub struct Conform {
+    target: Format,
+    // Per clip: whether its picture / audio needs converting
+    video: Vec<bool>,
+    audio: Vec<bool>,
+    // The export keeps an alpha channel, so letterboxing is transparent
+    alpha: bool,
+}
+
+impl Conform {
+    // None when every clip already matches the first. Audio is only compared
+    // when the clips' own audio goes into the export.
+    pub fn reconcile(sources: &[MediaInfo], with_audio: bool, alpha: bool) -> Option<Conform> {
+        let formats: Vec<Format> = sources.iter().map(Format::of).collect();
+        let target = formats.first()?.clone();
+        let video: Vec<bool> = formats.iter().map(|f| !f.video_matches(&target)).collect();
+        // Clips without audio get silence already in the target format
+        let audio: Vec<bool> = formats
+            .iter()
+            .map(|f| with_audio && f.sample_rate.is_some() && !f.audio_matches(&target))
+            .collect();
+        if !video.contains(&true) && !audio.contains(&true) {
+            return None;
+        }
+        Some(Conform { target, video, audio, alpha })
+    }
+
+    // Size every clip is conformed to, when the first clip reports one
+    pub fn frame_size(&self) -> Option<(u32, u32)> {
+        Some((self.target.width?, self.target.height?))
+    }
+
+    // Frame rate every clip plays at once conformed
+    pub fn fps(&self) -> f64 {
+        self.target.fps.unwrap_or(DEFAULT_FPS)
+    }
+
+    pub fn is_normalized(&self, clip: usize) -> bool {
+        self.video[clip] || self.audio[clip]
+    }
+
+    // Picture conversion for clip `clip`: letterboxed into the first clip's
+    // frame, at its frame rate and pixel format. Alpha exports letterbox with
+    // transparent bars and keep the alpha pixel format of their intermediates.
+    pub fn video_filter(&self, clip: usize) -> Option<String> {
+        if !self.video[clip] {
+            return None;
+        }
+        let mut filters = Vec::new();
+        if let (Some(w), Some(h)) = (self.target.width, self.target.height) {
+            let color = if self.alpha { ":color=black@0" } else { "" };
+            filters.push(format!(
+                "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2{color},setsar=1"
+            ));
+        }
+        filters.push(format!("fps={}", self.fps()));
+        let pix_fmt = if self.alpha { Some(crate::encode::ALPHA_PIX_FMT) } else { self.target.pix_fmt.as_deref() };
+        if let Some(pix_fmt) = pix_fmt {
+            filters.push(format!("format={}", pix_fmt));
+        }
+        Some(filters.join(","))
+    }
+
+    pub fn audio_filter(&self, clip: usize) -> Option<String> {
+        if !self.audio[clip] {
+            return None;
+        }
+        Some(format!(
+            "aresample={},aformat=channel_layouts={}",
+            self.target.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+            self.target.channels.as_deref().unwrap_or(DEFAULT_CHANNELS)
+        ))
+    }
+
+    // The `export-warning` text naming the converted clips
+    pub fn warning(&self, clips: &[crate::ClipData]) -> String {
+        let names: Vec<String> = clips
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_normalized(*i))
+            .map(|(i, clip)| {
+                let name = std::path::Path::new(&clip.file_path)
+                    .file_name()
+                    .map_or(clip.file_path.clone(), |n| n.to_string_lossy().into_owned());
+                format!("{} ({})", i + 1, name)
+            })
+            .collect();
+        let size = match (self.target.width, self.target.height) {
+            (Some(w), Some(h)) => format!("{}x{} ", w, h),
+            _ => String::new(),
+        };
+        format!(
+            "Clips with a different format were converted to match the first clip ({}{} fps): {}",
+            size,
+            self.fps(),
+            names.join(", ")
+        )
+    }
+}
+
+// lavfi source of the silence that stands in for a clip's missing audio, in
+// the format the other clips' audio is conformed to
+pub fn silence_source(sources: &[MediaInfo]) -> String {
+    let target = sources.first().map(Format::of);
+    format!(
+        "anullsrc=r={}:cl={}",
+        target.as_ref().and_then(|t| t.sample_rate).unwrap_or(DEFAULT_SAMPLE_RATE),
+        target.as_ref().and_then(|t| t.channels.as_deref()).unwrap_or(DEFAULT_CHANNELS)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conform(alpha: bool) -> Conform {
+        let target = Format {
+            width: Some(1920),
+            height: Some(1080),
+            fps: Some(25.0),
+            pix_fmt: Some("yuv420p".to_string()),
+            sample_rate: Some(48000),
+            channels: Some("stereo".to_string()),
+        };
+        Conform { target, video: vec![false, true], audio: vec![false, false], alpha }
+    }
+
+    #[test]
+    fn letterboxes_into_the_first_clip() {
+        let conform = conform(false);
+        assert_eq!(conform.video_filter(0), None);
+        assert_eq!(
+            conform.video_filter(1).unwrap(),
+            "scale=1920:1080:force_original_aspect_ratio=decrease,pad=1920:1080:(ow-iw)/2:(oh-ih)/2,setsar=1,\
+             fps=25,format=yuv420p"
+        );
+    }
+
+    #[test]
+    fn alpha_letterboxes_transparently() {
+        let filter = conform(true).video_filter(1).unwrap();
+        assert!(filter.contains("pad=1920:1080:(ow-iw)/2:(oh-ih)/2:color=black@0,"));
+        assert!(filter.ends_with(",format=yuva444p10le"));
+    }
+}
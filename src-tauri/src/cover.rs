@@ -0,0 +1,151 @@
+// Cover art embedded in an export, so file browsers and players show a proper
+// thumbnail. The image is either a file the user picked or a frame of the
+// export itself, grabbed from the trimmed intermediates (which already carry
+// every effect) before they are removed. It is added by a stream-copy remux
+// once the final encode is done, locally even when that encode ran remotely.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::encode::Container;
+use crate::{frame_command, ExportContext};
+
+// Largest width a grabbed frame is stored at; covers are shown small
+const COVER_SCALE: &str = "scale='min(1280,iw)':-2";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CoverArt {
+    // Image file to embed
+    #[serde(default)]
+    pub path: Option<String>,
+    // Or the frame this many seconds into the export
+    #[serde(default)]
+    pub timestamp: Option<f64>,
+}
+
+impl CoverArt {
+    pub fn validate(&self) -> Result<(), String> {
+        match (&self.path, self.timestamp) {
+            (Some(path), None) => {
+                if !Path::new(path).is_file() {
+                    return Err(format!("Cover image not found: {}", path));
+                }
+                Ok(())
+            }
+            (None, Some(timestamp)) if timestamp.is_finite() && timestamp >= 0.0 => Ok(()),
+            (None, Some(timestamp)) => Err(format!("Invalid cover timestamp: {}", timestamp)),
+            _ => Err("Cover art needs either an image path or a timestamp".to_string()),
+        }
+    }
+}
+
+// MP4 and MOV take an attached picture stream, Matroska an attachment.
+// WebM, MXF and the rest have no place for one.
+pub fn supports(container: Container) -> bool {
+    matches!(container, Container::Mp4 | Container::Mov | Container::Mkv)
+}
+
+// The intermediate showing timeline time `timestamp` and the offset into it.
+// After a transition join there is a single intermediate for the timeline.
+fn locate<'a>(timestamp: f64, lengths: &[f64], paths: &'a [PathBuf], timeline_length: f64) -> Option<(&'a Path, f64)> {
+    // A timestamp in a later loop pass shows the same frame as in the first
+    let time = if timeline_length > 0.0 { timestamp % timeline_length } else { 0.0 };
+    if paths.len() == 1 {
+        return Some((&paths[0], time));
+    }
+    let mut start = 0.0;
+    for (path, length) in paths.iter().zip(lengths) {
+        if time < start + length {
+            return Some((path, time - start));
+        }
+        start += length;
+    }
+    paths.last().zip(lengths.last()).map(|(path, length)| (path.as_path(), (length - 0.1).max(0.0)))
+}
+
+// The image to embed: the user's file, or the grabbed frame written into the
+// export's temp dir
+pub fn prepare(
+    cover: &CoverArt,
+    lengths: &[f64],
+    trimmed_paths: &[PathBuf],
+    ctx: &ExportContext,
+) -> Result<PathBuf, String> {
+    if let Some(path) = &cover.path {
+        return Ok(PathBuf::from(path));
+    }
+    let timestamp = cover.timestamp.unwrap_or(0.0);
+    let (source, offset) = locate(timestamp, lengths, trimmed_paths, ctx.timeline_length)
+        .ok_or("No clips to take the cover frame from")?;
+    let image = ctx.temp_dir.join("cover.png");
+    let mut cmd = frame_command(
+        ctx.ffmpeg_path,
+        source.to_str().ok_or("Invalid path")?,
+        offset,
+        image.to_str().ok_or("Invalid path")?,
+        COVER_SCALE,
+        &["-vcodec", "png"],
+    );
+    let status = ctx
+        .registry
+        .run(ctx.task_id, &mut cmd)
+        .map_err(|e| format!("FFmpeg cover frame failed: {}", e))?;
+    if !status.success() {
+        return Err(format!("FFmpeg couldn't grab the cover frame at {}s", timestamp));
+    }
+    Ok(image)
+}
+
+// Remuxes `output_path` with `image` as its cover art, replacing the file.
+// The remux is written next to the output so the final rename stays on one
+// volume.
+pub fn embed(ctx: &ExportContext, output_path: &str, image: &Path) -> Result<(), String> {
+    let output = Path::new(output_path);
+    let stem = output.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = output.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+    let partial = output.with_file_name(format!("{}.cover-partial.{}", stem, ext));
+    let image_path = image.to_str().ok_or("Invalid path")?;
+
+    let mut cmd = Command::new(ctx.ffmpeg_path);
+    cmd.args(["-y", "-i", output_path]);
+    match Container::from_path(output_path) {
+        Container::Mkv => {
+            let mimetype = match image.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase) {
+                Some(ext) if ext == "jpg" || ext == "jpeg" => "image/jpeg",
+                _ => "image/png",
+            };
+            cmd.args(["-attach", image_path, "-map", "0", "-c", "copy"]);
+            cmd.args(["-metadata:s:t".to_string(), format!("mimetype={}", mimetype)]);
+            // Players look for an attachment named "cover"
+            let name = format!("cover.{}", if mimetype == "image/jpeg" { "jpg" } else { "png" });
+            cmd.args(["-metadata:s:t".to_string(), format!("filename={}", name)]);
+        }
+        _ => {
+            // The picture is always the second video stream; re-encoded to PNG,
+            // which both MP4 and MOV accept as cover art
+            cmd.args(["-i", image_path, "-map", "0", "-map", "1:v:0", "-c", "copy"]);
+            cmd.args(["-c:v:1", "png", "-disposition:v:1", "attached_pic"]);
+            // A stream copy writes a new index, which belongs where the export put it
+            cmd.args(ctx.movflags());
+        }
+    }
+    cmd.arg(&partial);
+
+    let result = ctx
+        .registry
+        .run(ctx.task_id, &mut cmd)
+        .map_err(|e| format!("FFmpeg cover art failed: {}", e))
+        .and_then(|status| match status.success() {
+            true => Ok(()),
+            false => Err("FFmpeg cover art exited with error".to_string()),
+        })
+        .and_then(|()| {
+            std::fs::rename(&partial, output)
+                .map_err(|e| format!("Failed to replace the output with the covered copy: {}", e))
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
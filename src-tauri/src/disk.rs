@@ -0,0 +1,128 @@
+// Free space on the volumes an export writes to, so the UI can warn before a
+// render that is likely to run out of room part way through, and an export
+// that clearly won't fit refuses to start.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::encode::EncodePlan;
+
+// Start of the error an export fails with when it won't fit, for the UI to match
+pub const INSUFFICIENT_DISK_SPACE_ERROR: &str = "InsufficientDiskSpace";
+
+// The estimate is rough, so this much more than it has to be free
+const HEADROOM: f64 = 1.2;
+
+// 16-bit stereo PCM at 48kHz, the largest audio an export writes
+const AUDIO_BITS_PER_SECOND: f64 = 1_536_000.0;
+
+// Below this share of the volume left free, an export warns before starting
+const LOW_SPACE_FRACTION: f64 = 0.05;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct DiskSpace {
+    // Bytes this user can still write
+    pub free: u64,
+    pub total: u64,
+}
+
+impl DiskSpace {
+    pub fn is_low(&self) -> bool {
+        self.total > 0 && (self.free as f64) < self.total as f64 * LOW_SPACE_FRACTION
+    }
+}
+
+// The nearest part of `path` that exists. An output file (or a temp dir that
+// is created later) isn't there yet, but the volume holding it is.
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors().find(|p| !p.as_os_str().is_empty() && p.exists()).map(Path::to_path_buf)
+}
+
+// Space on the volume holding `path`, which needn't exist yet
+pub fn space_for(path: &Path) -> Result<DiskSpace, String> {
+    let existing = existing_ancestor(path)
+        .ok_or_else(|| format!("No existing directory found for {}", path.display()))?;
+    let free = fs2::available_space(&existing)
+        .map_err(|e| format!("Failed to read free space for {}: {}", existing.display(), e))?;
+    let total = fs2::total_space(&existing)
+        .map_err(|e| format!("Failed to read disk size for {}: {}", existing.display(), e))?;
+    Ok(DiskSpace { free, total })
+}
+
+// Command to read the free and total bytes of the volume holding `path`
+#[tauri::command]
+pub async fn get_free_space(path: String) -> Result<DiskSpace, String> {
+    space_for(Path::new(&path))
+}
+
+// Bytes an export is expected to write to each volume
+#[derive(Debug, Clone, Copy)]
+pub struct SpaceNeeded {
+    pub temp: u64,
+    pub output: u64,
+}
+
+impl SpaceNeeded {
+    // `pixel_rate` is pixels per second of the largest clip; `trimmed_length`
+    // is the summed clip lengths and `timeline_length` the exported length of
+    // one pass. Joining transitions writes a second copy of the intermediates.
+    pub fn estimate(
+        plan: &EncodePlan,
+        pixel_rate: f64,
+        trimmed_length: f64,
+        timeline_length: f64,
+        joined: bool,
+        loop_count: u32,
+    ) -> Self {
+        let bytes = |bits_per_pixel: f64, seconds: f64| {
+            ((pixel_rate * bits_per_pixel + AUDIO_BITS_PER_SECOND) * seconds / 8.0 * HEADROOM) as u64
+        };
+        let mut temp = bytes(plan.trim_bits_per_pixel, trimmed_length);
+        if joined {
+            temp += bytes(plan.trim_bits_per_pixel, timeline_length);
+        }
+        let output = bytes(plan.final_bits_per_pixel, timeline_length * loop_count as f64);
+        SpaceNeeded { temp, output }
+    }
+}
+
+fn gigabytes(bytes: u64) -> f64 {
+    bytes as f64 / 1e9
+}
+
+fn insufficient(role: &str, path: &Path, needed: u64, free: u64) -> String {
+    format!(
+        "{}: the export needs about {:.1} GB on the {} volume ({}) but only {:.1} GB is free",
+        INSUFFICIENT_DISK_SPACE_ERROR,
+        gigabytes(needed),
+        role,
+        path.display(),
+        gigabytes(free)
+    )
+}
+
+// Fails with INSUFFICIENT_DISK_SPACE_ERROR when either volume is short of
+// what `needed` says it takes. A volume whose space can't be read isn't
+// checked; the export then fails later if it really runs out.
+pub fn check_space(temp_dir: &Path, output_path: &Path, needed: SpaceNeeded) -> Result<(), String> {
+    let temp = space_for(temp_dir).ok();
+    let output = space_for(output_path).ok();
+    // There is no portable volume id, so matching figures are taken to
+    // mean both paths are on the same volume, which then has to hold both
+    if let (Some(temp), Some(output)) = (temp, output) {
+        if temp.free == output.free && temp.total == output.total {
+            let both = needed.temp + needed.output;
+            if temp.free < both {
+                return Err(insufficient("temp and output", output_path, both, temp.free));
+            }
+            return Ok(());
+        }
+    }
+    if let Some(space) = temp.filter(|s| s.free < needed.temp) {
+        return Err(insufficient("temp", temp_dir, needed.temp, space.free));
+    }
+    if let Some(space) = output.filter(|s| s.free < needed.output) {
+        return Err(insufficient("output", output_path, needed.output, space.free));
+    }
+    Ok(())
+}
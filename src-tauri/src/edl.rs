@@ -0,0 +1,572 @@
+// CMX3600 edit decision lists, so a timeline cut in rVJ can be conformed in
+// Premiere, Resolve or Avid. Each clip is one event on the V track, reel AX
+// with its file named in the comments (as NLEs expect for file-based media),
+// and the music, if given, one event on the stereo audio track. EDLs from
+// elsewhere (or a JSON list of clips) are read back into a timeline.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::effects;
+use crate::transitions::{Transition, TransitionType};
+use crate::{timeline_length, validate_clips, validate_transition_fit, ClipData};
+
+const MIN_EDL_FPS: f64 = 1.0;
+const MAX_EDL_FPS: f64 = 120.0;
+
+// Hour the record timecode of the first event reads; NLE timelines start
+// at one hour
+const RECORD_START_HOURS: u64 = 1;
+
+// Event numbers have three digits
+const MAX_EVENTS: usize = 999;
+
+// The auxiliary reel, for sources that aren't tapes
+const REEL: &str = "AX";
+
+const MAX_TITLE_CHARS: usize = 70;
+
+// Changes of speed smaller than this are treated as none
+const SPEED_EPSILON: f64 = 1e-6;
+
+// "HH:MM:SS:FF" labelling frame number `frames`, non-drop-frame: fractional
+// rates count frames at the nearest whole rate, as NDF timecode does
+fn frame_label(frames: u64, fps: f64) -> String {
+    let base = fps.round() as u64;
+    let (total_seconds, frame) = (frames / base, frames % base);
+    format!("{:02}:{:02}:{:02}:{:02}", total_seconds / 3600 % 24, total_seconds / 60 % 60, total_seconds % 60, frame)
+}
+
+fn frames(seconds: f64, fps: f64) -> u64 {
+    (seconds.max(0.0) * fps).round() as u64
+}
+
+// Timecode of the frame `seconds` into a source at `fps`
+fn timecode(seconds: f64, fps: f64) -> String {
+    frame_label(frames(seconds, fps), fps)
+}
+
+// Timecode of the frame `seconds` into the record timeline. The start hour
+// is a label, so it is counted in whole-rate frames: at 29.97 the first event
+// still reads 01:00:00:00.
+fn record_timecode(seconds: f64, fps: f64) -> String {
+    frame_label(RECORD_START_HOURS * 3600 * fps.round() as u64 + frames(seconds, fps), fps)
+}
+
+fn validate_fps(fps: Option<f64>) -> Result<f64, String> {
+    let fps = fps.ok_or("An EDL needs a frame rate for its timecodes")?;
+    if !(MIN_EDL_FPS..=MAX_EDL_FPS).contains(&fps) {
+        return Err(format!("EDL fps must be between {} and {}, got {}", MIN_EDL_FPS, MAX_EDL_FPS, fps));
+    }
+    Ok(fps)
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map_or(path.to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+// One line of an event, without its comments
+fn event_line(
+    number: usize,
+    track: &str,
+    kind: &str,
+    frames: Option<u64>,
+    source: (f64, f64),
+    record: (f64, f64),
+    fps: f64,
+) -> String {
+    format!(
+        "{:03}  {:<8} {:<5} {:<4} {:>3} {} {} {} {}\n",
+        number,
+        REEL,
+        track,
+        kind,
+        frames.map_or(String::new(), |f| format!("{:03}", f)),
+        timecode(source.0, fps),
+        timecode(source.1, fps),
+        record_timecode(record.0, fps),
+        record_timecode(record.1, fps)
+    )
+}
+
+// Where a clip's event sits: its source range and the record range it fills,
+// in seconds from the start of the timeline
+struct Placement {
+    source: (f64, f64),
+    record: (f64, f64),
+    // Source seconds played per record second; negative when reversed
+    speed: f64,
+}
+
+// Each clip's event on the record timeline. A transition starts the incoming
+// clip before the outgoing one ends; the outgoing event is cut where the
+// dissolve starts, as in an EDL the dissolve event carries the overlap.
+fn placements(clips: &[ClipData]) -> Vec<Placement> {
+    let mut starts = Vec::with_capacity(clips.len());
+    let mut position = 0.0;
+    for (i, clip) in clips.iter().enumerate() {
+        if let Some(transition) = clip.transition.filter(|_| i > 0) {
+            position -= transition.duration;
+        }
+        starts.push(position);
+        position += clip.timeline_duration();
+    }
+    clips
+        .iter()
+        .enumerate()
+        .map(|(i, clip)| {
+            let start = starts[i];
+            let end = match clips.get(i + 1).and_then(|next| next.transition) {
+                Some(_) => starts[i + 1],
+                None => start + clip.timeline_duration(),
+            };
+            // Ramps, ping-pong and freeze-frames don't exist in an EDL, so they
+            // come out as the constant speed that fills the same time
+            let speed = (clip.end_time - clip.start_time) / clip.timeline_duration();
+            Placement {
+                source: (clip.start_time, clip.start_time + (end - start) * speed),
+                record: (start, end),
+                speed: if clip.reverse { -speed } else { speed },
+            }
+        })
+        .collect()
+}
+
+// The EDL for `clips`, with `audio_path` laid under the whole timeline if
+// given
+fn edl(clips: &[ClipData], audio_path: Option<&str>, fps: f64, title: &str) -> Result<String, String> {
+    let events = clips.len() + usize::from(audio_path.is_some());
+    if events > MAX_EVENTS {
+        return Err(format!("An EDL holds at most {} events; this timeline needs {}", MAX_EVENTS, events));
+    }
+    let placements = placements(clips);
+    let mut edl = format!("TITLE: {}\nFCM: NON-DROP FRAME\n\n", title);
+    for (i, (clip, placement)) in clips.iter().zip(&placements).enumerate() {
+        let number = i + 1;
+        let name = file_name(&clip.file_path);
+        match clip.transition.filter(|_| i > 0) {
+            Some(transition) => {
+                // The outgoing clip held at the point the dissolve starts,
+                // then the dissolve into this one. Every style is written as a
+                // dissolve, as wipe codes differ between NLEs.
+                let previous = &placements[i - 1];
+                let from = (previous.source.1, previous.source.1);
+                edl.push_str(&event_line(number, "V", "C", None, from, (placement.record.0, placement.record.0), fps));
+                let frames = (transition.duration * fps).round() as u64;
+                edl.push_str(&event_line(number, "V", "D", Some(frames), placement.source, placement.record, fps));
+                edl.push_str(&format!("* FROM CLIP NAME: {}\n", file_name(&clips[i - 1].file_path)));
+                edl.push_str(&format!("* TO CLIP NAME: {}\n", name));
+            }
+            None => {
+                edl.push_str(&event_line(number, "V", "C", None, placement.source, placement.record, fps));
+                edl.push_str(&format!("* FROM CLIP NAME: {}\n", name));
+            }
+        }
+        edl.push_str(&format!("* SOURCE FILE: {}\n", clip.file_path));
+        if (placement.speed - 1.0).abs() >= SPEED_EPSILON {
+            edl.push_str(&format!(
+                "M2   {:<8}       {:05.1}                {}\n",
+                REEL,
+                placement.speed * fps,
+                timecode(placement.source.0, fps)
+            ));
+        }
+        if clip.speed_ramp.is_some() || clip.ping_pong || clip.freeze_start.is_some() || clip.freeze_end.is_some() {
+            edl.push_str("* RVJ: SPEED RAMP, PING-PONG AND FREEZE-FRAMES ARE SHOWN AS A CONSTANT SPEED\n");
+        }
+        edl.push('\n');
+    }
+    if let Some(audio_path) = audio_path {
+        let length = timeline_length(clips);
+        edl.push_str(&event_line(
+            clips.len() + 1,
+            "AA",
+            "C",
+            None,
+            (0.0, length),
+            (0.0, length),
+            fps,
+        ));
+        edl.push_str(&format!("* FROM CLIP NAME: {}\n* SOURCE FILE: {}\n", file_name(audio_path), audio_path));
+    }
+    Ok(edl)
+}
+
+// Command to write the timeline as a CMX3600 EDL at `output_path`, counted at
+// `fps`, with the music under it when `audio_path` is given
+#[tauri::command]
+pub async fn export_edl(
+    clips: Vec<ClipData>,
+    output_path: String,
+    fps: Option<f64>,
+    audio_path: Option<String>,
+    title: Option<String>,
+) -> Result<(), String> {
+    let fps = validate_fps(fps)?;
+    if clips.is_empty() {
+        return Err("No clips to export".to_string());
+    }
+    if let Some(clip) = clips.iter().find(|c| c.end_time <= c.start_time) {
+        return Err(format!("Clip {} ends before it starts", clip.file_path));
+    }
+    validate_clips(&clips)?;
+    validate_transition_fit(&clips)?;
+    let title = title.unwrap_or_else(|| "rVJ timeline".to_string());
+    if title.chars().count() > MAX_TITLE_CHARS || title.chars().any(char::is_control) {
+        return Err(format!("EDL titles must be at most {} characters, on one line", MAX_TITLE_CHARS));
+    }
+    let contents = edl(&clips, audio_path.as_deref(), fps, &title)?;
+    std::fs::write(&output_path, contents).map_err(|e| format!("Failed to write EDL {}: {}", output_path, e))
+}
+
+// Seconds from "HH:MM:SS:FF" at `fps`. Drop-frame timecode (";" before the
+// frames, or the whole list marked DROP FRAME) skips the first frame numbers
+// of every minute but each tenth, two at 29.97 and four at 59.94.
+fn parse_timecode(tc: &str, fps: f64, drop_frame: bool) -> Option<f64> {
+    let drop_frame = drop_frame || tc.contains(';');
+    let parts: Vec<u64> = tc.split([':', ';', '.']).map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let [hours, minutes, seconds, frames] = parts[..] else { return None };
+    let base = fps.round() as u64;
+    if minutes >= 60 || seconds >= 60 || frames >= base {
+        return None;
+    }
+    let mut number = ((hours * 60 + minutes) * 60 + seconds) * base + frames;
+    if drop_frame {
+        let dropped = (fps * 0.066666).round() as u64;
+        let total_minutes = hours * 60 + minutes;
+        number -= dropped * (total_minutes - total_minutes / 10);
+    }
+    Some(number as f64 / fps)
+}
+
+// An event as read; a dissolve's leading zero-length hold is skipped
+struct Event {
+    reel: String,
+    // Frames of the dissolve or wipe into it; None for a cut
+    transition_frames: Option<u64>,
+    source: (f64, f64),
+    record: (f64, f64),
+    // From M2; negative plays it backwards
+    speed: Option<f64>,
+    clip_name: Option<String>,
+    source_file: Option<String>,
+}
+
+// The video events of a CMX3600 EDL, in record order
+fn parse_edl(text: &str, fps: f64) -> Result<Vec<Event>, String> {
+    let mut drop_frame = false;
+    let mut events: Vec<Event> = Vec::new();
+    // Whether the last event line was a video one, which the comments and
+    // M2 lines after it belong to
+    let mut in_video = false;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(mode) = line.strip_prefix("FCM:") {
+            drop_frame = mode.trim().eq_ignore_ascii_case("DROP FRAME");
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('*') {
+            let Some(event) = events.last_mut().filter(|_| in_video) else { continue };
+            let comment = comment.trim();
+            // The incoming clip's name wins over the outgoing one's
+            if let Some(name) = comment.strip_prefix("TO CLIP NAME:") {
+                event.clip_name = Some(name.trim().to_string());
+            } else if let Some(name) = comment.strip_prefix("FROM CLIP NAME:") {
+                if event.transition_frames.is_none() {
+                    event.clip_name = Some(name.trim().to_string());
+                }
+            } else if let Some(file) = comment.strip_prefix("SOURCE FILE:") {
+                event.source_file = Some(file.trim().to_string());
+            }
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first() == Some(&"M2") {
+            if let (Some(event), Some(speed)) = (events.last_mut().filter(|_| in_video), fields.get(2)) {
+                let speed: f64 = speed.parse().map_err(|_| format!("Line {}: invalid M2 speed {}", n + 1, speed))?;
+                event.speed = Some(speed / fps);
+            }
+            continue;
+        }
+        if fields.len() < 8 || !fields[0].chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let track = fields[2];
+        in_video = track.starts_with('V') || track == "B";
+        if !in_video {
+            continue;
+        }
+        let kind = fields[3];
+        let timecodes = &fields[fields.len() - 4..];
+        let parse = |tc: &str| {
+            parse_timecode(tc, fps, drop_frame).ok_or_else(|| format!("Line {}: invalid timecode {}", n + 1, tc))
+        };
+        let source = (parse(timecodes[0])?, parse(timecodes[1])?);
+        let record = (parse(timecodes[2])?, parse(timecodes[3])?);
+        if record.1 <= record.0 {
+            // The hold before a dissolve, already covered by the outgoing event
+            in_video = false;
+            continue;
+        }
+        let transition_frames = match kind {
+            "C" => None,
+            _ => fields.get(4).and_then(|d| d.parse().ok()),
+        };
+        events.push(Event {
+            reel: fields[1].to_string(),
+            transition_frames,
+            source,
+            record,
+            speed: None,
+            clip_name: None,
+            source_file: None,
+        });
+    }
+    events.sort_by(|a, b| a.record.0.total_cmp(&b.record.0));
+    Ok(events)
+}
+
+// The file an event's source names: its own path if that exists, else the
+// name in `media_dir`, matched with or without its extension. Case is
+// ignored, as EDLs often come from other systems.
+fn resolve_source(names: &[&str], media_dir: Option<&Path>) -> Option<PathBuf> {
+    for name in names {
+        if Path::new(name).is_file() {
+            return Some(PathBuf::from(name));
+        }
+    }
+    let entries: Vec<PathBuf> = std::fs::read_dir(media_dir?).ok()?.flatten().map(|e| e.path()).collect();
+    names.iter().find_map(|name| {
+        let wanted = Path::new(name).file_name()?.to_string_lossy().to_lowercase();
+        entries
+            .iter()
+            .find(|path| {
+                let file = path.file_name().map(|n| n.to_string_lossy().to_lowercase());
+                let stem = path.file_stem().map(|n| n.to_string_lossy().to_lowercase());
+                path.is_file() && (file.as_deref() == Some(&wanted) || stem.as_deref() == Some(&wanted))
+            })
+            .cloned()
+    })
+}
+
+// The timeline the events describe. A dissolve or wipe becomes a crossfade
+// into the clip it leads to, with the outgoing clip run on through it, as
+// rVJ's clips overlap where an EDL cuts the outgoing event short.
+fn events_to_clips(events: &[Event], fps: f64) -> Vec<(ClipData, Vec<String>)> {
+    let mut clips: Vec<(ClipData, Vec<String>)> = Vec::with_capacity(events.len());
+    for event in events {
+        let record_length = event.record.1 - event.record.0;
+        let speed = event.speed.filter(|s| effects::validate_speed(s.abs()).is_ok());
+        let end = match speed {
+            Some(speed) => event.source.0 + record_length * speed.abs(),
+            None => event.source.1,
+        };
+        let names: Vec<String> = [event.source_file.clone(), event.clip_name.clone(), Some(event.reel.clone())]
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut clip = ClipData::new(names[0].clone(), event.source.0, end);
+        if let Some(speed) = speed.filter(|s| (s.abs() - 1.0).abs() >= SPEED_EPSILON) {
+            clip.speed = Some(speed.abs());
+        }
+        clip.reverse = speed.is_some_and(|s| s < 0.0);
+        let overlap = event.transition_frames.filter(|_| !clips.is_empty()).map(|frames| frames as f64 / fps);
+        if let (Some(duration), Some((previous, _))) = (overlap.filter(|d| *d > 0.0), clips.last_mut()) {
+            let previous_speed = previous.speed.unwrap_or(1.0);
+            previous.end_time += duration * previous_speed;
+            clip.transition = Some(Transition { kind: TransitionType::Fade, duration });
+        }
+        clips.push((clip, names));
+    }
+    clips
+}
+
+// The result of import_edl
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportedTimeline {
+    clips: Vec<ClipData>,
+    // Indices of the clips whose source wasn't found. Their file_path is the
+    // name the list gave, to relink by hand.
+    missing: Vec<usize>,
+}
+
+// A JSON timeline: the clips export_video takes, bare or under "clips"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonTimeline {
+    Clips(Vec<ClipData>),
+    Wrapped { clips: Vec<ClipData> },
+}
+
+// Command to read a CMX3600 EDL, or a .json list of clips, as a timeline.
+// Sources are looked for where the list says, then by name in `media_dir`;
+// ones that can't be found are flagged rather than failing the import. EDL
+// timecodes need the `fps` they were counted at, and source timecodes are
+// taken as time from the start of each file.
+#[tauri::command]
+pub async fn import_edl(path: String, media_dir: Option<String>, fps: Option<f64>) -> Result<ImportedTimeline, String> {
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let is_json = Path::new(&path).extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    let clips: Vec<(ClipData, Vec<String>)> = if is_json {
+        let timeline: JsonTimeline =
+            serde_json::from_str(&text).map_err(|e| format!("Invalid timeline file {}: {}", path, e))?;
+        let (JsonTimeline::Clips(clips) | JsonTimeline::Wrapped { clips }) = timeline;
+        clips
+            .into_iter()
+            .map(|clip| {
+                let names = vec![clip.file_path.clone()];
+                (clip, names)
+            })
+            .collect()
+    } else {
+        let fps = validate_fps(fps)?;
+        events_to_clips(&parse_edl(&text, fps)?, fps)
+    };
+    if clips.is_empty() {
+        return Err(format!("No video clips found in {}", path));
+    }
+
+    let media_dir = media_dir.as_deref().map(Path::new);
+    let mut missing = Vec::new();
+    let clips = clips
+        .into_iter()
+        .enumerate()
+        .map(|(i, (mut clip, names))| {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            match resolve_source(&names, media_dir) {
+                Some(file) => clip.file_path = file.to_string_lossy().into_owned(),
+                None => missing.push(i),
+            }
+            clip
+        })
+        .collect();
+    Ok(ImportedTimeline { clips, missing })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timecode_counts_frames_at_the_whole_rate() {
+        assert_eq!(timecode(0.0, 25.0), "00:00:00:00");
+        assert_eq!(timecode(1.0, 25.0), "00:00:01:00");
+        assert_eq!(timecode(61.48, 25.0), "00:01:01:12");
+        assert_eq!(timecode(3725.04, 25.0), "01:02:05:01");
+        // 30 frames of 29.97 read as one second of NDF timecode
+        assert_eq!(timecode(30.0 / 29.97, 29.97), "00:00:01:00");
+    }
+
+    #[test]
+    fn record_timecode_starts_at_one_hour_at_every_rate() {
+        for fps in [23.976, 24.0, 25.0, 29.97, 30.0, 59.94, 60.0] {
+            assert_eq!(record_timecode(0.0, fps), "01:00:00:00", "at {} fps", fps);
+        }
+        assert_eq!(record_timecode(2.0, 25.0), "01:00:02:00");
+        assert_eq!(record_timecode(48.0 / 23.976, 23.976), "01:00:02:00");
+    }
+
+    #[test]
+    fn parse_timecode_reads_non_drop_frame() {
+        assert_eq!(parse_timecode("01:00:00:00", 25.0, false), Some(3600.0));
+        assert_eq!(parse_timecode("00:00:01:12", 25.0, false), Some(1.48));
+        assert_eq!(parse_timecode("00:00:00:25", 25.0, false), None);
+        assert_eq!(parse_timecode("00:60:00:00", 25.0, false), None);
+        assert_eq!(parse_timecode("00:00:00", 25.0, false), None);
+    }
+
+    #[test]
+    fn parse_timecode_skips_dropped_frame_numbers() {
+        // The first frame of minute one is numbered 02, and is frame 1800
+        let seconds = parse_timecode("00:01:00;02", 29.97, false).unwrap();
+        assert!((seconds - 1800.0 / 29.97).abs() < 1e-9);
+        // Every tenth minute drops nothing, so ten minutes is 17982 frames
+        let seconds = parse_timecode("00:10:00:00", 29.97, true).unwrap();
+        assert!((seconds - 17982.0 / 29.97).abs() < 1e-9);
+        let seconds = parse_timecode("00:01:00;04", 59.94, false).unwrap();
+        assert!((seconds - 3600.0 / 59.94).abs() < 1e-9);
+    }
+
+    #[test]
+    fn timecodes_read_back_as_written() {
+        for fps in [24.0, 25.0, 29.97] {
+            for frame in [0, 1, 29, 1799, 86_399] {
+                let seconds = frame as f64 / fps;
+                let read = parse_timecode(&timecode(seconds, fps), fps, false).unwrap();
+                assert!((read - seconds).abs() < 1e-9, "frame {} at {} fps", frame, fps);
+            }
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn exported_timelines_import_as_the_same_clips() {
+        let mut clips = vec![
+            ClipData::new("/media/a.mov".to_string(), 2.0, 6.0),
+            ClipData::new("/media/b.mov".to_string(), 10.0, 13.0),
+            ClipData::new("/media/c.mov".to_string(), 0.0, 5.0),
+            ClipData::new("/media/d.mov".to_string(), 20.0, 24.0),
+        ];
+        clips[1].transition = Some(Transition { kind: TransitionType::WipeLeft, duration: 1.0 });
+        clips[3].speed = Some(2.0);
+        let text = edl(&clips, Some("/media/music.wav"), 25.0, "Set").unwrap();
+        let events = parse_edl(&text, 25.0).unwrap();
+        // The dissolve's hold and the music aren't clips of their own
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[1].transition_frames, Some(25));
+        assert_eq!(events[1].clip_name.as_deref(), Some("b.mov"));
+
+        let imported = events_to_clips(&events, 25.0);
+        assert_eq!(imported.len(), clips.len());
+        for ((clip, names), original) in imported.iter().zip(&clips) {
+            assert_eq!(clip.file_path, original.file_path);
+            assert_eq!(names[1], file_name(&original.file_path));
+            assert!(close(clip.start_time, original.start_time), "{} starts at {}", clip.file_path, clip.start_time);
+            assert!(close(clip.end_time, original.end_time), "{} ends at {}", clip.file_path, clip.end_time);
+            assert_eq!(clip.speed, original.speed);
+            assert!(!clip.reverse);
+        }
+        // Every style comes back as the crossfade it was written as
+        let transition = imported[1].0.transition.unwrap();
+        assert_eq!(transition.kind, TransitionType::Fade);
+        assert!(close(transition.duration, 1.0));
+        assert!(imported[2].0.transition.is_none());
+    }
+
+    #[test]
+    fn parse_edl_reads_events_and_skips_what_isnt_one() {
+        let text = "\
+TITLE: From elsewhere
+FCM: NON-DROP FRAME
+* A COMMENT BEFORE ANY EVENT
+
+001  TAPE01   V     C        00:00:10:00 00:00:12:00 01:00:00:00 01:00:02:00
+* FROM CLIP NAME: interview.mxf
+M2   TAPE01       -025.0                00:00:10:00
+002  TAPE01   A     C        00:00:10:00 00:00:12:00 01:00:00:00 01:00:02:00
+* FROM CLIP NAME: not this one
+not an event line
+003  TAPE02   V     C        00:00:05:00 00:00:09:00 01:00:02:00 01:00:06:00
+";
+        let events = parse_edl(text, 25.0).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].reel, "TAPE01");
+        assert_eq!(events[0].clip_name.as_deref(), Some("interview.mxf"));
+        assert_eq!(events[0].speed, Some(-1.0));
+        assert_eq!((events[0].source, events[0].record), ((10.0, 12.0), (3600.0, 3602.0)));
+        assert_eq!(events[1].clip_name, None);
+        assert_eq!(events[1].source_file, None);
+
+        let clips = events_to_clips(&events, 25.0);
+        assert!(clips[0].0.reverse);
+        assert_eq!(clips[0].0.speed, None);
+        // With no file named, the reel is all there is to relink by
+        assert_eq!(clips[1].0.file_path, "TAPE02");
+
+        let bad = "001  AX       V     C        00:00:10:00 00:00:12:99 01:00:00:00 01:00:02:00\n";
+        assert_eq!(parse_edl(bad, 25.0).err().unwrap(), "Line 1: invalid timecode 00:00:12:99");
+    }
+}
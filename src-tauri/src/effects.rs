@@ -0,0 +1,447 @@
+// Visual effects applied to the picture while clips are trimmed
+
+use serde::{Deserialize, Serialize};
+
+use crate::encode::ALPHA_PIX_FMT;
+
+// HDR to SDR: linearise, convert BT.2020 primaries to BT.709, compress
+// highlights with the Hable curve and re-encode to BT.709 limited range.
+// With `alpha` the picture goes through the float RGBA format instead and
+// comes out in the alpha intermediates' format, so transparency survives.
+pub fn tonemap_filter(alpha: bool) -> String {
+    let (float_fmt, out_fmt) = if alpha { ("gbrapf32le", ALPHA_PIX_FMT) } else { ("gbrpf32le", "yuv420p") };
+    format!(
+        "zscale=t=linear:npl=100,format={},zscale=p=bt709,\
+         tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format={}",
+        float_fmt, out_fmt
+    )
+}
+
+// Runs `filters` on the picture of an alpha export while leaving its alpha
+// plane alone. eq, vignette and unsharp only take formats without alpha, so
+// the plane is split off before them and merged back after. Written as a
+// small graph like PING_PONG_VIDEO_FILTER, with its pads named after `label`
+// so it can appear more than once in a chain.
+pub fn keep_alpha(label: &str, filters: &[String]) -> String {
+    format!(
+        "format={fmt},split[{l}_color][{l}_src];[{l}_src]alphaextract[{l}_alpha];\
+         [{l}_color]{filters}[{l}_done];[{l}_done][{l}_alpha]alphamerge,format={fmt}",
+        fmt = ALPHA_PIX_FMT,
+        l = label,
+        filters = filters.join(",")
+    )
+}
+
+// One progressive frame per interlaced frame; bwdif handles motion better
+// than yadif at a similar speed
+pub const DEINTERLACE_FILTER: &str = "bwdif=mode=send_frame:parity=auto:deint=all";
+
+// The clip followed by a reversed copy of itself. Written as a small graph
+// so it slots into a comma-separated chain: whatever precedes it feeds the
+// split, and whatever follows takes the concat's output.
+pub const PING_PONG_VIDEO_FILTER: &str =
+    "split[pp_fwd][pp_src];[pp_src]reverse[pp_rev];[pp_fwd][pp_rev]concat=n=2:v=1:a=0";
+pub const PING_PONG_AUDIO_FILTER: &str =
+    "asplit[pp_fwd][pp_src];[pp_src]areverse[pp_rev];[pp_fwd][pp_rev]concat=n=2:v=0:a=1";
+
+const MAX_FREEZE: f64 = 60.0;
+
+pub fn validate_freeze(seconds: f64) -> Result<(), String> {
+    if !(0.0..=MAX_FREEZE).contains(&seconds) {
+        return Err(format!("Freeze-frame must be between 0 and {} seconds, got {}", MAX_FREEZE, seconds));
+    }
+    Ok(())
+}
+
+// tpad holding the first and/or last frame; None without any freeze
+pub fn freeze_filter(start: Option<f64>, end: Option<f64>) -> Option<String> {
+    let mut options = Vec::new();
+    if let Some(start) = start.filter(|s| *s > 0.0) {
+        options.push(format!("start_mode=clone:start_duration={}", start));
+    }
+    if let Some(end) = end.filter(|e| *e > 0.0) {
+        options.push(format!("stop_mode=clone:stop_duration={}", end));
+    }
+    if options.is_empty() {
+        return None;
+    }
+    Some(format!("tpad={}", options.join(":")))
+}
+
+// Silence under the held frames, so the clip's audio stays in sync
+pub fn freeze_audio_filter(start: Option<f64>, end: Option<f64>) -> Option<String> {
+    let mut filters = Vec::new();
+    if let Some(start) = start.filter(|s| *s > 0.0) {
+        filters.push(format!("adelay=delays={}:all=1", (start * 1000.0).round()));
+    }
+    if let Some(end) = end.filter(|e| *e > 0.0) {
+        filters.push(format!("apad=pad_dur={}", end));
+    }
+    if filters.is_empty() {
+        return None;
+    }
+    Some(filters.join(","))
+}
+
+// eq's accepted ranges and the neutral value of each
+const BRIGHTNESS_RANGE: (f64, f64, f64) = (-1.0, 1.0, 0.0);
+const CONTRAST_RANGE: (f64, f64, f64) = (-1000.0, 1000.0, 1.0);
+const SATURATION_RANGE: (f64, f64, f64) = (0.0, 3.0, 1.0);
+const GAMMA_RANGE: (f64, f64, f64) = (0.1, 10.0, 1.0);
+
+// eq filter for a clip's color adjustments, with each value clamped to what
+// eq accepts. None when everything is neutral, so untouched clips skip it.
+pub fn color_filter(
+    brightness: Option<f64>,
+    contrast: Option<f64>,
+    saturation: Option<f64>,
+    gamma: Option<f64>,
+) -> Option<String> {
+    let options: Vec<String> = [
+        ("brightness", brightness, BRIGHTNESS_RANGE),
+        ("contrast", contrast, CONTRAST_RANGE),
+        ("saturation", saturation, SATURATION_RANGE),
+        ("gamma", gamma, GAMMA_RANGE),
+    ]
+    .into_iter()
+    .filter_map(|(name, value, (min, max, neutral))| {
+        let value = value.filter(|v| v.is_finite())?.clamp(min, max);
+        (value != neutral).then(|| format!("{}={}", name, value))
+    })
+    .collect();
+    if options.is_empty() {
+        return None;
+    }
+    Some(format!("eq={}", options.join(":")))
+}
+
+// Luma amount unsharp reaches at full strength; beyond this halos show
+const MAX_SHARPEN_AMOUNT: f64 = 2.0;
+
+// unsharp for a strength of 0..1: a 5x5 luma kernel with the amount scaled
+// by strength, chroma left alone so edges don't pick up color fringes.
+// None at strength 0.
+pub fn sharpen_filter(strength: f64) -> Option<String> {
+    let strength = if strength.is_finite() { strength.clamp(0.0, 1.0) } else { 0.0 };
+    if strength == 0.0 {
+        return None;
+    }
+    Some(format!("unsharp=5:5:{}:5:5:0", strength * MAX_SHARPEN_AMOUNT))
+}
+
+// vignette for a strength of 0..1, mapped onto the lens angle, which darkens
+// the edges more the wider it gets (up to PI/2). None at strength 0.
+pub fn vignette_filter(strength: f64) -> Option<String> {
+    let strength = if strength.is_finite() { strength.clamp(0.0, 1.0) } else { 0.0 };
+    if strength == 0.0 {
+        return None;
+    }
+    Some(format!("vignette=angle={}", strength * std::f64::consts::FRAC_PI_2))
+}
+
+// Flashes the picture on each beat, for club-style visuals
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StrobeOptions {
+    // Beat times in seconds of the exported timeline
+    pub beats: Vec<f64>,
+    // ffmpeg color: a name ("white") or hex ("#ff00ff")
+    #[serde(default = "default_strobe_color")]
+    pub color: String,
+    // Opacity of the flash at the beat, 0..1
+    #[serde(default = "default_strobe_intensity")]
+    pub intensity: f64,
+    // Seconds the flash takes to fade out
+    #[serde(default = "default_strobe_decay")]
+    pub decay: f64,
+}
+
+fn default_strobe_color() -> String {
+    "white".to_string()
+}
+
+fn default_strobe_intensity() -> f64 {
+    0.8
+}
+
+fn default_strobe_decay() -> f64 {
+    0.15
+}
+
+const MAX_STROBE_DECAY: f64 = 2.0;
+
+// The fade-out is drawn as this many boxes of falling opacity
+const STROBE_STEPS: usize = 4;
+
+// Names and hex values; anything else could break out of the filter option
+pub fn validate_color(color: &str) -> Result<(), String> {
+    let hex = color.strip_prefix('#').or_else(|| color.strip_prefix("0x"));
+    let valid = match hex {
+        Some(hex) => matches!(hex.len(), 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+    };
+    if !valid {
+        return Err(format!("Invalid color '{}', expected a name or #RRGGBB", color));
+    }
+    Ok(())
+}
+
+impl StrobeOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_color(&self.color)?;
+        if !(0.0..=1.0).contains(&self.intensity) {
+            return Err(format!("Strobe intensity must be between 0 and 1, got {}", self.intensity));
+        }
+        if !(self.decay > 0.0 && self.decay <= MAX_STROBE_DECAY) {
+            return Err(format!("Strobe decay must be between 0 and {} seconds, got {}", MAX_STROBE_DECAY, self.decay));
+        }
+        if self.beats.iter().any(|b| !b.is_finite() || *b < 0.0) {
+            return Err("Strobe beats must be non-negative times in seconds".to_string());
+        }
+        Ok(())
+    }
+
+    // drawbox chain flashing the beats that fall in a clip placed at
+    // `clip_start` on the timeline. Times in the filter are clip-relative.
+    // None if no flash reaches into the clip.
+    pub fn filter(&self, clip_start: f64, duration: f64) -> Option<String> {
+        let beats: Vec<f64> = self
+            .beats
+            .iter()
+            .map(|b| b - clip_start)
+            .filter(|b| *b + self.decay > 0.0 && *b < duration)
+            .collect();
+        if beats.is_empty() || self.intensity <= 0.0 {
+            return None;
+        }
+
+        let step = self.decay / STROBE_STEPS as f64;
+        let boxes: Vec<String> = (0..STROBE_STEPS)
+            .map(|k| {
+                let opacity = self.intensity * (1.0 - k as f64 / STROBE_STEPS as f64);
+                let enable = beats
+                    .iter()
+                    .map(|b| format!("between(t,{},{})", b + k as f64 * step, b + (k + 1) as f64 * step))
+                    .collect::<Vec<_>>()
+                    .join("+");
+                format!(
+                    "drawbox=x=0:y=0:w=iw:h=ih:color={}@{}:t=fill:enable='{}'",
+                    self.color, opacity, enable
+                )
+            })
+            .collect();
+        Some(boxes.join(","))
+    }
+}
+
+// A point on a clip's speed ramp; speed changes linearly between points
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct SpeedKeyframe {
+    // Seconds from the clip's in-point, in source time
+    pub time: f64,
+    // Playback rate; 0.5 is half speed
+    pub speed: f64,
+}
+
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 10.0;
+
+pub fn validate_speed(speed: f64) -> Result<(), String> {
+    if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        return Err(format!("Speed must be between {} and {}, got {}", MIN_SPEED, MAX_SPEED, speed));
+    }
+    Ok(())
+}
+
+// Rate every clip's audio is brought to before asetrate retimes it
+const SPEED_SAMPLE_RATE: u32 = 48000;
+
+// Audio filter matching a constant clip speed. atempo keeps the pitch; older
+// ffmpeg builds only take factors of 0.5-2 per instance, so larger changes are
+// chained. asetrate plays the samples faster or slower, shifting the pitch
+// with the tempo, and has to be resampled afterwards or every later filter
+// and the encoder would see a nonstandard rate.
+pub fn audio_speed_filter(speed: f64, preserve_pitch: bool) -> String {
+    if !preserve_pitch {
+        return format!(
+            "aresample={rate},asetrate={rate}*{},aresample={rate}",
+            speed,
+            rate = SPEED_SAMPLE_RATE
+        );
+    }
+    let mut stages = Vec::new();
+    let mut remaining = speed;
+    while remaining > 2.0 {
+        stages.push("atempo=2.0".to_string());
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        stages.push("atempo=0.5".to_string());
+        remaining /= 0.5;
+    }
+    stages.push(format!("atempo={}", remaining));
+    stages.join(",")
+}
+
+pub fn validate_speed_ramp(keyframes: &[SpeedKeyframe], duration: f64) -> Result<(), String> {
+    if keyframes.is_empty() {
+        return Err("Speed ramp needs at least one keyframe".to_string());
+    }
+    for key in keyframes {
+        validate_speed(key.speed)?;
+        if !(0.0..=duration).contains(&key.time) {
+            return Err(format!("Speed keyframe at {}s is outside the clip (0-{}s)", key.time, duration));
+        }
+        if keyframes.iter().filter(|other| other.time == key.time).count() > 1 {
+            return Err(format!("More than one speed keyframe at {}s", key.time));
+        }
+    }
+    Ok(())
+}
+
+// The ramp as (time, speed) points covering 0..duration, sorted by time
+fn ramp_points(keyframes: &[SpeedKeyframe], duration: f64) -> Vec<(f64, f64)> {
+    let mut points: Vec<(f64, f64)> = keyframes.iter().map(|k| (k.time, k.speed)).collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // Hold the first and last speed out to the clip edges
+    if points[0].0 > 0.0 {
+        points.insert(0, (0.0, points[0].1));
+    }
+    let last = points[points.len() - 1];
+    if last.0 < duration {
+        points.push((duration, last.1));
+    }
+    points
+}
+
+// Output seconds taken to play source time t0..t1 while the speed moves
+// linearly from s0 to s1: the integral of 1/speed
+fn segment_length(t0: f64, s0: f64, t1: f64, s1: f64) -> f64 {
+    if (s1 - s0).abs() < 1e-9 {
+        return (t1 - t0) / s0;
+    }
+    let slope = (s1 - s0) / (t1 - t0);
+    (s1.ln() - s0.ln()) / slope
+}
+
+// Length of a ramped clip on the timeline
+pub fn ramp_output_duration(keyframes: &[SpeedKeyframe], duration: f64) -> f64 {
+    ramp_points(keyframes, duration)
+        .windows(2)
+        .map(|w| segment_length(w[0].0, w[0].1, w[1].0, w[1].1))
+        .sum()
+}
+
+// (timeline time, source time) at each point of the ramp, from the clip's
+// start to its end
+pub fn ramp_time_points(keyframes: &[SpeedKeyframe], duration: f64) -> Vec<(f64, f64)> {
+    let points = ramp_points(keyframes, duration);
+    let mut elapsed = 0.0;
+    let mut times = vec![(0.0, points[0].0)];
+    for w in points.windows(2) {
+        elapsed += segment_length(w[0].0, w[0].1, w[1].0, w[1].1);
+        times.push((elapsed, w[1].0));
+    }
+    times
+}
+
+// setpts filter retiming a clip by its ramp. Each segment maps source time T
+// to the output time reached at its start plus the integral of 1/speed
+// across it.
+pub fn ramp_setpts_filter(keyframes: &[SpeedKeyframe], duration: f64) -> String {
+    let points = ramp_points(keyframes, duration);
+    let mut expr = String::new();
+    let mut elapsed = 0.0;
+    let segments = points.windows(2).count();
+    for (i, w) in points.windows(2).enumerate() {
+        let ((t0, s0), (t1, s1)) = (w[0], w[1]);
+        let segment = if (s1 - s0).abs() < 1e-9 {
+            format!("{}+(T-{})/{}", elapsed, t0, s0)
+        } else {
+            let slope = (s1 - s0) / (t1 - t0);
+            format!("{}+(log({}+({})*(T-{}))-log({}))/({})", elapsed, s0, slope, t0, s0, slope)
+        };
+        if i + 1 < segments {
+            expr.push_str(&format!("if(lt(T,{}),{},", t1, segment));
+        } else {
+            expr.push_str(&segment);
+        }
+        elapsed += segment_length(t0, s0, t1, s1);
+    }
+    expr.push_str(&")".repeat(segments - 1));
+    format!("setpts='({})/TB'", expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(points: &[(f64, f64)]) -> Vec<SpeedKeyframe> {
+        points.iter().map(|&(time, speed)| SpeedKeyframe { time, speed }).collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn alpha_tonemap_keeps_the_alpha_plane() {
+        assert!(tonemap_filter(false).ends_with(",format=yuv420p"));
+        let filter = tonemap_filter(true);
+        assert!(filter.contains("format=gbrapf32le,"));
+        assert!(!filter.contains("gbrpf32le"));
+        assert!(filter.ends_with(",format=yuva444p10le"));
+    }
+
+    #[test]
+    fn keep_alpha_grades_around_the_alpha_plane() {
+        let graph = keep_alpha("grade", &["eq=contrast=1.2".to_string(), "vignette=angle=0.5".to_string()]);
+        assert_eq!(
+            graph,
+            "format=yuva444p10le,split[grade_color][grade_src];[grade_src]alphaextract[grade_alpha];\
+             [grade_color]eq=contrast=1.2,vignette=angle=0.5[grade_done];[grade_done][grade_alpha]alphamerge,\
+             format=yuva444p10le"
+        );
+    }
+
+    #[test]
+    fn ramp_time_points_follow_the_ramp() {
+        let ramp = [SpeedKeyframe { time: 5.0, speed: 2.0 }];
+        assert_eq!(ramp_time_points(&ramp, 10.0), vec![(0.0, 0.0), (2.5, 5.0), (5.0, 10.0)]);
+    }
+
+    #[test]
+    fn atempo_is_chained_past_its_range() {
+        assert_eq!(audio_speed_filter(1.5, true), "atempo=1.5");
+        assert_eq!(audio_speed_filter(4.0, true), "atempo=2.0,atempo=2");
+        assert_eq!(audio_speed_filter(5.0, true), "atempo=2.0,atempo=2.0,atempo=1.25");
+        assert_eq!(audio_speed_filter(0.25, true), "atempo=0.5,atempo=0.5");
+        assert_eq!(audio_speed_filter(0.1, true), "atempo=0.5,atempo=0.5,atempo=0.5,atempo=0.8");
+    }
+
+    #[test]
+    fn unpitched_speed_resamples() {
+        assert_eq!(audio_speed_filter(2.0, false), "aresample=48000,asetrate=48000*2,aresample=48000");
+    }
+
+    #[test]
+    fn constant_ramp_lasts_duration_over_speed() {
+        assert!(close(ramp_output_duration(&keys(&[(0.0, 2.0)]), 10.0), 5.0));
+        assert!(close(ramp_output_duration(&keys(&[(4.0, 0.5)]), 10.0), 20.0));
+    }
+
+    #[test]
+    fn linear_ramp_integrates_one_over_speed() {
+        // The integral of 1/(1 + t/10) over 0..10
+        let duration = ramp_output_duration(&keys(&[(0.0, 1.0), (10.0, 2.0)]), 10.0);
+        assert!(close(duration, 10.0 * 2f64.ln()));
+    }
+
+    #[test]
+    fn ramp_setpts_holds_speed_out_to_the_edges() {
+        assert_eq!(ramp_setpts_filter(&keys(&[(0.0, 2.0)]), 10.0), "setpts='(0+(T-0)/2)/TB'");
+        assert_eq!(
+            ramp_setpts_filter(&keys(&[(5.0, 2.0)]), 10.0),
+            "setpts='(if(lt(T,5),0+(T-0)/2,2.5+(T-5)/2))/TB'"
+        );
+    }
+}
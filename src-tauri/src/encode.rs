@@ -0,0 +1,570 @@
+// How an export is encoded: the intermediate each clip is trimmed to, and the
+// codec arguments for the final concat. Chosen from the export options and
+// the output container, and validated before any ffmpeg work starts.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::ExportOptions;
+
+// Codec family of the exported video
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    // Apple ProRes in .mov, for handing an edit to another NLE
+    Prores,
+    // Avid DNxHR in .mov or .mxf, for Avid/Resolve workflows
+    Dnxhr,
+}
+
+// prores_ks profiles, lightest to heaviest (4444 is used for alpha exports)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProResProfile {
+    Proxy,
+    Lt,
+    Standard,
+    #[default]
+    Hq,
+}
+
+impl ProResProfile {
+    fn name(self) -> &'static str {
+        match self {
+            ProResProfile::Proxy => "proxy",
+            ProResProfile::Lt => "lt",
+            ProResProfile::Standard => "standard",
+            ProResProfile::Hq => "hq",
+        }
+    }
+
+    // Apple's published 1080p30 data rates, per pixel of each frame
+    fn bits_per_pixel(self) -> f64 {
+        match self {
+            ProResProfile::Proxy => 0.75,
+            ProResProfile::Lt => 1.7,
+            ProResProfile::Standard => 2.4,
+            ProResProfile::Hq => 3.6,
+        }
+    }
+}
+
+// DNxHR profiles of ffmpeg's dnxhd encoder. Unlike legacy DNxHD these don't
+// tie the bitrate to a fixed resolution/fps, but each needs its own pixel format.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DnxhrProfile {
+    Lb,
+    Sq,
+    #[default]
+    Hq,
+    Hqx,
+    #[serde(rename = "444")]
+    Dnx444,
+}
+
+impl DnxhrProfile {
+    fn name(self) -> &'static str {
+        match self {
+            DnxhrProfile::Lb => "dnxhr_lb",
+            DnxhrProfile::Sq => "dnxhr_sq",
+            DnxhrProfile::Hq => "dnxhr_hq",
+            DnxhrProfile::Hqx => "dnxhr_hqx",
+            DnxhrProfile::Dnx444 => "dnxhr_444",
+        }
+    }
+
+    fn pix_fmt(self) -> &'static str {
+        match self {
+            DnxhrProfile::Lb | DnxhrProfile::Sq | DnxhrProfile::Hq => "yuv422p",
+            DnxhrProfile::Hqx => "yuv422p10le",
+            DnxhrProfile::Dnx444 => "yuv444p10le",
+        }
+    }
+
+    // Avid's published 1080p30 data rates, per pixel of each frame
+    fn bits_per_pixel(self) -> f64 {
+        match self {
+            DnxhrProfile::Lb => 0.75,
+            DnxhrProfile::Sq => 2.4,
+            DnxhrProfile::Hq | DnxhrProfile::Hqx => 3.6,
+            DnxhrProfile::Dnx444 => 7.2,
+        }
+    }
+}
+
+// Pixel format of alpha exports' ProRes 4444 intermediates, which every
+// filter in their trims keeps to
+pub const ALPHA_PIX_FMT: &str = "yuva444p10le";
+
+// Shown when a DNxHR request can't be satisfied
+const DNXHR_COMBINATIONS: &str = "dnxhr_lb, dnxhr_sq, dnxhr_hq (8-bit 4:2:2), \
+dnxhr_hqx (10-bit 4:2:2) or dnxhr_444 (10-bit 4:4:4), each in a .mov or .mxf \
+output, without alpha";
+
+// Codec of the final lossy audio encode
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    Aac,
+    // Better quality per bit than AAC, especially at low bitrates
+    Opus,
+    // Lossless, for archival masters; MKV only
+    Flac,
+}
+
+// Output container, from the output path's extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Container {
+    Mp4,
+    Mov,
+    Mkv,
+    Webm,
+    Mxf,
+    Other,
+}
+
+impl Container {
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("mp4") | Some("m4v") => Container::Mp4,
+            Some("mov") => Container::Mov,
+            Some("mkv") => Container::Mkv,
+            Some("webm") => Container::Webm,
+            Some("mxf") => Container::Mxf,
+            _ => Container::Other,
+        }
+    }
+}
+
+// Outputs estimated above this keep the index at the end under
+// MoovPlacement::Auto, as moving it means rewriting the whole file once more
+const FASTSTART_MAX_BYTES: u64 = 8_000_000_000;
+
+// Where an MP4/MOV output's index (the moov atom) goes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MoovPlacement {
+    // Front, unless the output is estimated to be very large
+    #[default]
+    Auto,
+    // At the front (+faststart), so the file plays while it downloads
+    Front,
+    // At the end, where the muxer writes it; the file is done right away
+    End,
+}
+
+impl MoovPlacement {
+    // Whether the output should be written with +faststart, given its
+    // estimated size. Only MP4 and MOV have a moov atom.
+    pub fn faststart(self, container: Container, estimated_bytes: u64) -> bool {
+        if !matches!(container, Container::Mp4 | Container::Mov) {
+            return false;
+        }
+        match self {
+            MoovPlacement::Auto => estimated_bytes <= FASTSTART_MAX_BYTES,
+            MoovPlacement::Front => true,
+            MoovPlacement::End => false,
+        }
+    }
+}
+
+pub struct EncodePlan {
+    // Extension and muxer of the per-clip intermediates
+    pub intermediate_ext: &'static str,
+    pub intermediate_format: &'static str,
+    // Codec arguments for trimming each clip
+    pub trim_video: Vec<String>,
+    pub trim_audio: Vec<String>,
+    // Codec arguments for the final concat
+    pub final_video: Vec<String>,
+    pub final_audio: Vec<String>,
+    // Non-fatal notes for the user, sent as `export-warning`
+    pub warnings: Vec<String>,
+    // Rough upper bounds on the picture's size in the intermediates and the
+    // final file, in bits per pixel of each frame, for the disk space check
+    pub trim_bits_per_pixel: f64,
+    pub final_bits_per_pixel: f64,
+}
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+// Seconds between keyframes of a low-latency encode: where a viewer joining
+// the stream, or one recovering from a dropped packet, can start decoding
+const LOW_LATENCY_KEYFRAME_SECONDS: f64 = 1.0;
+
+// MPEG-TS and FLV, the containers live output is read from as it is written
+// (a pipe, or a streaming server)
+fn is_live_output(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("ts") || e.eq_ignore_ascii_case("flv"))
+}
+
+// libx264 for live output at `fps`. zerolatency drops B-frames and lookahead
+// so each frame goes out as soon as it is encoded, and a short, fixed GOP
+// lets viewers join quickly. That costs quality: at the same bitrate the
+// picture is noticeably blockier than a file export's, so this is only used
+// where latency matters.
+pub fn low_latency_video(fps: f64) -> Vec<String> {
+    let gop = ((fps * LOW_LATENCY_KEYFRAME_SECONDS).round() as u32).max(1).to_string();
+    let mut video = args(&["-c:v", "libx264", "-preset", "veryfast", "-tune", "zerolatency"]);
+    video.extend(args(&["-g", &gop, "-keyint_min", &gop, "-sc_threshold", "0", "-pix_fmt", "yuv420p"]));
+    video
+}
+
+// libx264's own limit on -threads
+pub const MAX_X264_THREADS: u32 = 128;
+
+// Caps (or raises) the threads of a libx264 encode; other codecs are left to
+// pick their own
+fn x264_threads(mut codec_args: Vec<String>, threads: u32) -> Vec<String> {
+    if codec_args.iter().any(|arg| arg == "libx264") {
+        codec_args.extend(["-threads".to_string(), threads.to_string()]);
+    }
+    codec_args
+}
+
+pub const DEFAULT_AUDIO_BITRATE: &str = "192k";
+
+// Accepts "192k" or plain bits per second ("192000") between 32 and 512 kb/s
+pub fn validate_audio_bitrate(bitrate: &str) -> Result<(), String> {
+    let kbps = match bitrate.strip_suffix(['k', 'K']) {
+        Some(kilo) => kilo.parse::<u32>().ok(),
+        None => bitrate.parse::<u32>().ok().map(|bps| bps / 1000),
+    };
+    match kbps {
+        Some(32..=512) => Ok(()),
+        _ => Err(format!(
+            "Invalid audio bitrate '{}': expected 32k to 512k, e.g. \"192k\"",
+            bitrate
+        )),
+    }
+}
+
+// Lossy audio codec arguments at the requested bitrate
+fn lossy_audio(codec: &str, bitrate: &str) -> Vec<String> {
+    args(&["-c:a", codec, "-b:a", bitrate])
+}
+
+// Final audio: the requested codec where the container can carry it,
+// otherwise Opus for WebM/MKV and AAC for everything else
+fn pick_final_audio(
+    container: Container,
+    options: &ExportOptions,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<String>, String> {
+    let codec = match (options.audio_codec, container) {
+        (Some(AudioCodec::Aac), Container::Webm) => {
+            return Err("WebM can't carry AAC audio; use opus".to_string())
+        }
+        (Some(AudioCodec::Opus), Container::Mp4 | Container::Mov) => {
+            warnings.push("Opus isn't used in MP4/MOV outputs; encoding the audio as AAC".to_string());
+            "aac"
+        }
+        // Lossless, so the bitrate doesn't apply. Other containers were
+        // rejected up front.
+        (Some(AudioCodec::Flac), _) => return Ok(args(&["-c:a", "flac"])),
+        (Some(AudioCodec::Opus), _) | (None, Container::Webm | Container::Mkv) => "libopus",
+        (Some(AudioCodec::Aac), _) | (None, _) => "aac",
+    };
+    Ok(lossy_audio(codec, &options.audio_bitrate))
+}
+
+impl EncodePlan {
+    pub fn new(options: &ExportOptions, output_path: &str) -> Result<Self, String> {
+        let container = Container::from_path(output_path);
+        if options.audio_codec == Some(AudioCodec::Flac) && container != Container::Mkv {
+            return Err("FLAC audio needs an .mkv output; MP4, MOV and WebM can't carry it".to_string());
+        }
+        let plain_h264 = !options.lossless && !options.alpha && options.video_codec == VideoCodec::H264;
+        if options.gpu_pipeline.is_some() && !plain_h264 {
+            return Err(
+                "The GPU pipeline only encodes H.264; it can't be combined with ProRes, DNxHR, alpha or lossless"
+                    .to_string(),
+            );
+        }
+        if options.low_latency {
+            if !plain_h264 || options.gpu_pipeline.is_some() {
+                return Err("low_latency encodes with libx264; it can't be combined with other codecs, alpha, \
+                            lossless or the GPU pipeline"
+                    .to_string());
+            }
+            if !is_live_output(output_path) {
+                return Err(format!(
+                    "low_latency is for live output to a .ts or .flv stream, not file exports, got {}",
+                    output_path
+                ));
+            }
+        }
+        if options.lossless && (options.video_codec != VideoCodec::H264 || options.alpha) {
+            return Err("Lossless export can't be combined with ProRes, DNxHR or alpha".to_string());
+        }
+        let mut plan = match options.video_codec {
+            _ if options.lossless => Self::lossless(container),
+            // ProRes with alpha is the 4444 profile
+            VideoCodec::Prores if options.alpha => Self::alpha(container, options),
+            VideoCodec::Prores => Self::prores(container, options.prores_profile),
+            VideoCodec::Dnxhr if options.alpha => Err(format!(
+                "DNxHR can't carry alpha. Valid DNxHR exports: {}",
+                DNXHR_COMBINATIONS
+            )),
+            VideoCodec::Dnxhr => Self::dnxhr(container, options.dnxhr_profile),
+            VideoCodec::H264 if options.alpha => Self::alpha(container, options),
+            VideoCodec::H264 => Self::h264(container, options),
+        }?;
+        if let Some(threads) = options.threads {
+            plan.trim_video = x264_threads(plan.trim_video, threads);
+            plan.final_video = x264_threads(plan.final_video, threads);
+        }
+        Ok(plan)
+    }
+
+    // Switches the final encode of a low_latency export to low_latency_video,
+    // once the timeline's frame rate is known
+    pub fn set_low_latency(&mut self, fps: f64, threads: Option<u32>) {
+        self.final_video = low_latency_video(fps);
+        if let Some(threads) = threads {
+            self.final_video = x264_threads(std::mem::take(&mut self.final_video), threads);
+        }
+        // zerolatency gives up most of x264's compression tools
+        self.final_bits_per_pixel = 0.3;
+    }
+
+    // Fast H.264 intermediates in MPEG-TS (easy to concatenate), re-encoded
+    // once more at a better preset for the final file
+    fn h264(container: Container, options: &ExportOptions) -> Result<Self, String> {
+        let mut warnings = Vec::new();
+        let final_audio = pick_final_audio(container, options, &mut warnings)?;
+        Ok(EncodePlan {
+            intermediate_ext: "ts",
+            intermediate_format: "mpegts",
+            trim_video: args(&["-c:v", "libx264", "-preset", "ultrafast"]),
+            trim_audio: args(&["-c:a", "aac"]),
+            final_video: args(&["-c:v", "libx264", "-preset", "medium"]),
+            final_audio,
+            warnings,
+            // ultrafast spends far more bits than medium at the same quality
+            trim_bits_per_pixel: 0.3,
+            final_bits_per_pixel: 0.15,
+        })
+    }
+
+    // Each clip is trimmed straight to ProRes in a .mov segment, and the final
+    // concat copies the segments, so the picture is only encoded once
+    fn prores(container: Container, profile: ProResProfile) -> Result<Self, String> {
+        if container != Container::Mov {
+            return Err("ProRes export needs a .mov output".to_string());
+        }
+        Ok(EncodePlan {
+            intermediate_ext: "mov",
+            intermediate_format: "mov",
+            trim_video: args(&["-c:v", "prores_ks", "-profile:v", profile.name(), "-pix_fmt", "yuv422p10le"]),
+            trim_audio: args(&["-c:a", "pcm_s16le"]),
+            final_video: args(&["-c:v", "copy"]),
+            final_audio: args(&["-c:a", "pcm_s16le"]),
+            warnings: Vec::new(),
+            trim_bits_per_pixel: profile.bits_per_pixel(),
+            final_bits_per_pixel: profile.bits_per_pixel(),
+        })
+    }
+
+    // Same single-encode layout as ProRes. MXF only takes 48kHz PCM audio.
+    fn dnxhr(container: Container, profile: DnxhrProfile) -> Result<Self, String> {
+        let final_audio = match container {
+            Container::Mov => args(&["-c:a", "pcm_s16le"]),
+            Container::Mxf => args(&["-c:a", "pcm_s16le", "-ar", "48000"]),
+            _ => {
+                return Err(format!(
+                    "DNxHR export needs a .mov or .mxf output. Valid DNxHR exports: {}",
+                    DNXHR_COMBINATIONS
+                ))
+            }
+        };
+        Ok(EncodePlan {
+            intermediate_ext: "mov",
+            intermediate_format: "mov",
+            trim_video: args(&["-c:v", "dnxhd", "-profile:v", profile.name(), "-pix_fmt", profile.pix_fmt()]),
+            trim_audio: args(&["-c:a", "pcm_s16le"]),
+            final_video: args(&["-c:v", "copy"]),
+            final_audio,
+            warnings: Vec::new(),
+            trim_bits_per_pixel: profile.bits_per_pixel(),
+            final_bits_per_pixel: profile.bits_per_pixel(),
+        })
+    }
+
+    // Archival master. MKV gets FFV1 + FLAC; other containers get H.264 at
+    // qp 0 with PCM (.mov) or ALAC (.mp4, which takes neither FLAC nor PCM).
+    // Intermediates are MKV so no lossy step sneaks in before the final copy.
+    // WebM, MXF and other containers take neither pairing.
+    fn lossless(container: Container) -> Result<Self, String> {
+        let (trim_video, final_audio) = match container {
+            Container::Mkv => (args(&["-c:v", "ffv1", "-level", "3"]), args(&["-c:a", "flac"])),
+            Container::Mp4 => (
+                args(&["-c:v", "libx264", "-preset", "ultrafast", "-qp", "0"]),
+                args(&["-c:a", "alac"]),
+            ),
+            Container::Mov => (
+                args(&["-c:v", "libx264", "-preset", "ultrafast", "-qp", "0"]),
+                args(&["-c:a", "pcm_s16le"]),
+            ),
+            Container::Webm | Container::Mxf | Container::Other => {
+                return Err("Lossless export needs an .mkv (FFV1), .mp4 or .mov (H.264 qp 0) output".to_string())
+            }
+        };
+        Ok(EncodePlan {
+            intermediate_ext: "mkv",
+            intermediate_format: "matroska",
+            trim_video,
+            trim_audio: args(&["-c:a", "flac"]),
+            final_video: args(&["-c:v", "copy"]),
+            final_audio,
+            warnings: vec![
+                "Lossless export: expect files many times larger than a normal export".to_string(),
+            ],
+            // Lossless codecs reach about half of raw 10-bit 4:2:2
+            trim_bits_per_pixel: 10.0,
+            final_bits_per_pixel: 10.0,
+        })
+    }
+
+    // Keeps the alpha channel end to end. Clips are trimmed to ProRes 4444,
+    // which carries alpha and is near-lossless, so the final pass can copy it
+    // (.mov) or encode VP9 with alpha from it (.webm). Only sources that
+    // already have transparency, e.g. keyed footage, produce transparent pixels.
+    fn alpha(container: Container, options: &ExportOptions) -> Result<Self, String> {
+        // ProRes 4444 at Apple's 1080p30 rate; VP9 at crf 30 is far smaller
+        const PRORES_4444_BITS_PER_PIXEL: f64 = 5.4;
+        let (final_video, final_bits_per_pixel) = match container {
+            Container::Mov => (args(&["-c:v", "copy"]), PRORES_4444_BITS_PER_PIXEL),
+            Container::Webm => (
+                args(&["-c:v", "libvpx-vp9", "-pix_fmt", "yuva420p", "-b:v", "0", "-crf", "30"]),
+                0.2,
+            ),
+            _ => {
+                return Err(
+                    "Alpha export needs a .mov (ProRes 4444) or .webm (VP9) output".to_string(),
+                )
+            }
+        };
+        let mut warnings = Vec::new();
+        let final_audio = pick_final_audio(container, options, &mut warnings)?;
+        Ok(EncodePlan {
+            intermediate_ext: "mov",
+            intermediate_format: "mov",
+            trim_video: args(&["-c:v", "prores_ks", "-profile:v", "4444", "-pix_fmt", ALPHA_PIX_FMT]),
+            trim_audio: args(&["-c:a", "pcm_s16le"]),
+            final_video,
+            final_audio,
+            warnings,
+            trim_bits_per_pixel: PRORES_4444_BITS_PER_PIXEL,
+            final_bits_per_pixel,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(options: ExportOptions, output: &str) -> Result<EncodePlan, String> {
+        EncodePlan::new(&options, output)
+    }
+
+    fn error(options: ExportOptions, output: &str) -> String {
+        plan(options, output).err().expect("the plan should be refused")
+    }
+
+    fn has(codec_args: &[String], wanted: &[&str]) -> bool {
+        codec_args.windows(wanted.len()).any(|w| w.iter().zip(wanted).all(|(a, b)| a == b))
+    }
+
+    #[test]
+    fn h264_picks_audio_from_the_container() {
+        let mp4 = plan(ExportOptions::default(), "out.mp4").unwrap();
+        assert_eq!((mp4.intermediate_ext, mp4.intermediate_format), ("ts", "mpegts"));
+        assert!(has(&mp4.final_video, &["-c:v", "libx264", "-preset", "medium"]));
+        assert_eq!(mp4.final_audio, args(&["-c:a", "aac", "-b:a", "192k"]));
+        let webm = plan(ExportOptions::default(), "out.webm").unwrap();
+        assert_eq!(webm.final_audio, args(&["-c:a", "libopus", "-b:a", "192k"]));
+    }
+
+    #[test]
+    fn alpha_needs_mov_or_webm() {
+        let alpha = || ExportOptions { alpha: true, ..Default::default() };
+        let mov = plan(alpha(), "out.mov").unwrap();
+        assert!(has(&mov.trim_video, &["-profile:v", "4444", "-pix_fmt", ALPHA_PIX_FMT]));
+        assert_eq!(mov.final_video, args(&["-c:v", "copy"]));
+        let webm = plan(alpha(), "out.webm").unwrap();
+        assert!(has(&webm.final_video, &["-c:v", "libvpx-vp9", "-pix_fmt", "yuva420p"]));
+        assert!(error(alpha(), "out.mp4").starts_with("Alpha export needs"));
+        let prores = ExportOptions { video_codec: VideoCodec::Prores, ..alpha() };
+        assert!(has(&plan(prores, "out.mov").unwrap().trim_video, &["-profile:v", "4444"]));
+    }
+
+    #[test]
+    fn prores_and_dnxhr_check_their_containers() {
+        let prores = || ExportOptions { video_codec: VideoCodec::Prores, ..Default::default() };
+        let mov = plan(ExportOptions { prores_profile: ProResProfile::Lt, ..prores() }, "out.mov").unwrap();
+        assert!(has(&mov.trim_video, &["-c:v", "prores_ks", "-profile:v", "lt"]));
+        assert!(plan(prores(), "out.mp4").is_err());
+
+        let dnxhr = || ExportOptions { video_codec: VideoCodec::Dnxhr, ..Default::default() };
+        let mxf = plan(ExportOptions { dnxhr_profile: DnxhrProfile::Hqx, ..dnxhr() }, "out.mxf").unwrap();
+        assert!(has(&mxf.trim_video, &["-profile:v", "dnxhr_hqx", "-pix_fmt", "yuv422p10le"]));
+        assert_eq!(mxf.final_audio, args(&["-c:a", "pcm_s16le", "-ar", "48000"]));
+        assert!(error(dnxhr(), "out.mp4").contains(DNXHR_COMBINATIONS));
+        assert!(error(ExportOptions { alpha: true, ..dnxhr() }, "out.mov").contains(DNXHR_COMBINATIONS));
+    }
+
+    #[test]
+    fn lossless_rejects_containers_that_cant_hold_it() {
+        let lossless = || ExportOptions { lossless: true, ..Default::default() };
+        let mkv = plan(lossless(), "out.mkv").unwrap();
+        assert!(has(&mkv.trim_video, &["-c:v", "ffv1"]));
+        assert_eq!(mkv.final_audio, args(&["-c:a", "flac"]));
+        assert_eq!(plan(lossless(), "out.mp4").unwrap().final_audio, args(&["-c:a", "alac"]));
+        let mov = plan(lossless(), "out.mov").unwrap();
+        assert!(has(&mov.trim_video, &["-qp", "0"]));
+        assert_eq!(mov.final_audio, args(&["-c:a", "pcm_s16le"]));
+        assert!(!mov.warnings.is_empty());
+        for output in ["out.webm", "out.mxf", "out.avi"] {
+            assert!(error(lossless(), output).starts_with("Lossless export needs"), "{}", output);
+        }
+        let prores = ExportOptions { video_codec: VideoCodec::Prores, ..lossless() };
+        assert!(plan(prores, "out.mov").is_err());
+        assert!(plan(ExportOptions { alpha: true, ..lossless() }, "out.mov").is_err());
+    }
+
+    #[test]
+    fn audio_codec_and_bitrate() {
+        let bitrate = ExportOptions { audio_bitrate: "320k".to_string(), ..Default::default() };
+        assert_eq!(plan(bitrate, "out.mp4").unwrap().final_audio, args(&["-c:a", "aac", "-b:a", "320k"]));
+        assert!(validate_audio_bitrate("192k").is_ok());
+        assert!(validate_audio_bitrate("128000").is_ok());
+        assert!(validate_audio_bitrate("8k").is_err());
+        assert!(validate_audio_bitrate("lots").is_err());
+
+        let opus = || ExportOptions { audio_codec: Some(AudioCodec::Opus), ..Default::default() };
+        assert_eq!(plan(opus(), "out.mkv").unwrap().final_audio, args(&["-c:a", "libopus", "-b:a", "192k"]));
+        let mp4 = plan(opus(), "out.mp4").unwrap();
+        assert_eq!(mp4.final_audio[1], "aac");
+        assert_eq!(mp4.warnings.len(), 1);
+        let aac = ExportOptions { audio_codec: Some(AudioCodec::Aac), ..Default::default() };
+        assert!(plan(aac, "out.webm").is_err());
+
+        let flac = || ExportOptions { audio_codec: Some(AudioCodec::Flac), ..Default::default() };
+        assert_eq!(plan(flac(), "out.mkv").unwrap().final_audio, args(&["-c:a", "flac"]));
+        assert!(error(flac(), "out.mp4").starts_with("FLAC audio needs an .mkv output"));
+    }
+}
@@ -0,0 +1,396 @@
+// Final Cut Pro XML (FCPXML 1.9), which Final Cut Pro and DaVinci Resolve
+// both import, for finishing a timeline cut in rVJ. Clips go on the primary
+// storyline with their in-points, speed changes (as time maps) and
+// transitions, sources referenced by path; the music is connected under them.
+
+use std::path::Path;
+
+use crate::probe::{self, MediaInfo};
+use crate::{effects, resolve_ffmpeg, timeline_length, validate_clips, validate_transition_fit, ClipData};
+
+const FCPXML_VERSION: &str = "1.9";
+
+const MIN_FPS: f64 = 1.0;
+const MAX_FPS: f64 = 120.0;
+
+// Used when neither fps is given nor the first clip's can be read
+const DEFAULT_FPS: f64 = 30.0;
+// Used when the first clip's frame size can't be read
+const DEFAULT_FRAME_SIZE: (u32, u32) = (1920, 1080);
+
+// Final Cut's built-in Cross Dissolve. Every transition style is written as
+// one, as the others have no match there.
+const CROSS_DISSOLVE_UID: &str = "FxPlug:4731E73A-8DAC-4113-9A30-AE85B1761265";
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Length of one frame as num/den seconds. NTSC rates (29.97 and the like)
+// are 1001/30000 and so on, as FCPXML writes them; other rates are exact
+// fractions, e.g. 2/25 for 12.5.
+struct Timebase {
+    num: u64,
+    den: u64,
+}
+
+impl Timebase {
+    // Errors for a rate that isn't NTSC and isn't given to the thousandth
+    // of a frame, whose frames couldn't be written exactly
+    fn new(fps: f64) -> Result<Self, String> {
+        let whole = fps.round();
+        if (fps - whole).abs() > 1e-3 && (fps - whole * 1000.0 / 1001.0).abs() < 0.01 {
+            return Ok(Timebase { num: 1001, den: whole as u64 * 1000 });
+        }
+        let millis = (fps * 1000.0).round();
+        if millis < 1.0 || (fps * 1000.0 - millis).abs() > 1e-6 {
+            return Err(format!("FCPXML can't represent {} fps exactly; give the rate to three decimals at most", fps));
+        }
+        let divisor = gcd(1000, millis as u64);
+        Ok(Timebase { num: 1000 / divisor, den: millis as u64 / divisor })
+    }
+
+    fn fps(&self) -> f64 {
+        self.den as f64 / self.num as f64
+    }
+
+    // "N/Ds" for `seconds`, on a whole frame
+    fn time(&self, seconds: f64) -> String {
+        let frames = (seconds * self.fps()).round().max(0.0) as u64;
+        let numerator = frames * self.num;
+        if numerator == 0 {
+            return "0s".to_string();
+        }
+        let divisor = gcd(numerator, self.den);
+        match self.den / divisor {
+            1 => format!("{}s", numerator / divisor),
+            den => format!("{}/{}s", numerator / divisor, den),
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// file:// URL of a local path, with everything outside the unreserved set
+// percent-encoded
+fn file_url(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut url = String::from(if path.starts_with('/') { "file://" } else { "file:///" });
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => url.push(byte as char),
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map_or(path.to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+// (clip time, source time) points mapping the clip's time on the timeline to
+// its source, in the order the export applies them: speed or ramp, reverse,
+// ping-pong, then freeze-frames. None for a clip that plays as recorded.
+fn time_map(clip: &ClipData) -> Option<Vec<(f64, f64)>> {
+    let freeze_start = clip.freeze_start.unwrap_or(0.0);
+    let freeze_end = clip.freeze_end.unwrap_or(0.0);
+    if clip.speed_ramp.is_none()
+        && clip.speed.is_none()
+        && !clip.reverse
+        && !clip.ping_pong
+        && freeze_start <= 0.0
+        && freeze_end <= 0.0
+    {
+        return None;
+    }
+    let duration = clip.end_time - clip.start_time;
+    let mut pass: Vec<(f64, f64)> = match (&clip.speed_ramp, clip.speed) {
+        (Some(ramp), _) => effects::ramp_time_points(ramp, duration)
+            .into_iter()
+            .map(|(time, source)| (time, clip.start_time + source))
+            .collect(),
+        (None, speed) => vec![(0.0, clip.start_time), (duration / speed.unwrap_or(1.0), clip.end_time)],
+    };
+    let played = pass.last().map_or(0.0, |point| point.0);
+    if clip.reverse {
+        pass = pass.iter().rev().map(|(time, source)| (played - time, *source)).collect();
+    }
+    if clip.ping_pong {
+        let back: Vec<_> = pass.iter().rev().skip(1).map(|(time, source)| (2.0 * played - time, *source)).collect();
+        pass.extend(back);
+    }
+    let mut points = Vec::with_capacity(pass.len() + 2);
+    if freeze_start > 0.0 {
+        points.push((0.0, pass[0].1));
+    }
+    points.extend(pass.iter().map(|(time, source)| (time + freeze_start, *source)));
+    if freeze_end > 0.0 {
+        let (time, source) = points[points.len() - 1];
+        points.push((time + freeze_end, source));
+    }
+    Some(points)
+}
+
+// rVJ's color and detail settings, which FCPXML has no plain equivalent for,
+// kept as the clip's note
+fn effects_note(clip: &ClipData) -> Option<String> {
+    let settings: Vec<String> = [
+        ("brightness", clip.brightness),
+        ("contrast", clip.contrast),
+        ("saturation", clip.saturation),
+        ("gamma", clip.gamma),
+        ("vignette", clip.vignette),
+        ("sharpen", clip.sharpen),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.map(|v| format!("{} {}", name, v)))
+    .collect();
+    (!settings.is_empty()).then(|| format!("rVJ: {}", settings.join(", ")))
+}
+
+// A source file and what probing it found, if it could be read
+struct Asset {
+    id: String,
+    path: String,
+    info: Option<MediaInfo>,
+    // Furthest the timeline reaches into it, for when its length is unknown
+    used_until: f64,
+}
+
+fn asset_element(asset: &Asset, timebase: &Timebase, has_video: bool) -> String {
+    let duration = asset.info.as_ref().and_then(|info| info.duration).unwrap_or(asset.used_until);
+    let has_audio = asset.info.as_ref().is_none_or(|info| info.first_audio().is_some());
+    format!(
+        "    <asset id=\"{}\" name=\"{}\" start=\"0s\" duration=\"{}\" hasVideo=\"{}\" hasAudio=\"{}\">\n      \
+<media-rep kind=\"original-media\" src=\"{}\"/>\n    </asset>\n",
+        asset.id,
+        escape(&file_name(&asset.path)),
+        timebase.time(duration),
+        u8::from(has_video),
+        u8::from(has_audio),
+        escape(&file_url(&asset.path))
+    )
+}
+
+// The FCPXML document for `clips`, counted in frames of `timebase`, with `audio_path` under
+// the whole timeline if given. `assets` are the clips' distinct sources.
+fn fcpxml(
+    clips: &[ClipData],
+    assets: &[Asset],
+    audio: Option<&Asset>,
+    timebase: &Timebase,
+    frame: (u32, u32),
+    title: &str,
+) -> String {
+    let has_transitions = clips.iter().skip(1).any(|c| c.transition.is_some());
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE fcpxml>\n<fcpxml version=\"{}\">\n  <resources>\n",
+        FCPXML_VERSION
+    );
+    xml.push_str(&format!(
+        "    <format id=\"r1\" frameDuration=\"{}/{}s\" width=\"{}\" height=\"{}\"/>\n",
+        timebase.num, timebase.den, frame.0, frame.1
+    ));
+    if has_transitions {
+        xml.push_str(&format!("    <effect id=\"r2\" name=\"Cross Dissolve\" uid=\"{}\"/>\n", CROSS_DISSOLVE_UID));
+    }
+    for asset in assets {
+        xml.push_str(&asset_element(asset, timebase, true));
+    }
+    if let Some(audio) = audio {
+        xml.push_str(&asset_element(audio, timebase, false));
+    }
+    xml.push_str("  </resources>\n");
+
+    let length = timeline_length(clips);
+    let title = escape(title);
+    xml.push_str(&format!(
+        "  <library>\n    <event name=\"{title}\">\n      <project name=\"{title}\">\n        \
+<sequence format=\"r1\" duration=\"{}\" tcStart=\"0s\" tcFormat=\"NDF\" \
+audioLayout=\"stereo\" audioRate=\"48k\">\n          \
+<spine>\n",
+        timebase.time(length)
+    ));
+
+    // A transition is centred on its cut: each clip gives up half of every
+    // overlap it has with a neighbour, and the transition plays over the
+    // media either side
+    let mut offset = 0.0;
+    for (i, clip) in clips.iter().enumerate() {
+        let incoming = clip.transition.filter(|_| i > 0).map_or(0.0, |t| t.duration);
+        let outgoing = clips.get(i + 1).and_then(|next| next.transition).map_or(0.0, |t| t.duration);
+        if incoming > 0.0 {
+            xml.push_str(&format!(
+                "            <transition name=\"Cross Dissolve\" offset=\"{}\" duration=\"{}\">\n              \
+<filter-video ref=\"r2\" name=\"Cross Dissolve\"/>\n            </transition>\n",
+                timebase.time(offset - incoming / 2.0),
+                timebase.time(incoming)
+            ));
+        }
+        let visible = clip.timeline_duration() - incoming / 2.0 - outgoing / 2.0;
+        let map = time_map(clip);
+        // With a time map, start is in the clip's own retimed time
+        let start = match map {
+            Some(_) => incoming / 2.0,
+            None => clip.start_time + incoming / 2.0,
+        };
+        let asset = assets.iter().find(|a| a.path == clip.file_path).expect("every clip has an asset");
+        xml.push_str(&format!(
+            "            <asset-clip ref=\"{}\" offset=\"{}\" name=\"{}\" start=\"{}\" duration=\"{}\" \
+format=\"r1\">\n",
+            asset.id,
+            timebase.time(offset),
+            escape(&file_name(&clip.file_path)),
+            timebase.time(start),
+            timebase.time(visible)
+        ));
+        if let Some(note) = effects_note(clip) {
+            xml.push_str(&format!("              <note>{}</note>\n", escape(&note)));
+        }
+        if let Some(points) = map {
+            xml.push_str("              <timeMap>\n");
+            for (time, source) in points {
+                xml.push_str(&format!(
+                    "                <timept time=\"{}\" value=\"{}\" interp=\"linear\"/>\n",
+                    timebase.time(time),
+                    timebase.time(source)
+                ));
+            }
+            xml.push_str("              </timeMap>\n");
+        }
+        // The music hangs off the first clip, below it, from the clip's start
+        if let Some(audio) = audio.filter(|_| i == 0) {
+            xml.push_str(&format!(
+                "              <asset-clip ref=\"{}\" lane=\"-1\" offset=\"{}\" name=\"{}\" start=\"0s\" \
+duration=\"{}\"/>\n",
+                audio.id,
+                timebase.time(start),
+                escape(&file_name(&audio.path)),
+                timebase.time(length)
+            ));
+        }
+        xml.push_str("            </asset-clip>\n");
+        offset += visible;
+    }
+    xml.push_str("          </spine>\n        </sequence>\n      </project>\n    </event>\n  </library>\n</fcpxml>\n");
+    xml
+}
+
+// Command to write the timeline as FCPXML at `output_path`, with the music
+// under it when `audio_path` is given. `fps` is the project's frame rate;
+// by default the first clip's.
+#[tauri::command]
+pub async fn export_fcpxml(
+    clips: Vec<ClipData>,
+    output_path: String,
+    fps: Option<f64>,
+    audio_path: Option<String>,
+    title: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    if clips.is_empty() {
+        return Err("No clips to export".to_string());
+    }
+    if let Some(clip) = clips.iter().find(|c| c.end_time <= c.start_time) {
+        return Err(format!("Clip {} ends before it starts", clip.file_path));
+    }
+    validate_clips(&clips)?;
+    validate_transition_fit(&clips)?;
+    if let Some(fps) = fps {
+        if !(MIN_FPS..=MAX_FPS).contains(&fps) {
+            return Err(format!("fps must be between {} and {}, got {}", MIN_FPS, MAX_FPS, fps));
+        }
+    }
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+
+    // Each source is probed once; one that can't be read is still referenced
+    let mut assets: Vec<Asset> = Vec::new();
+    for clip in &clips {
+        match assets.iter_mut().find(|a| a.path == clip.file_path) {
+            Some(asset) => asset.used_until = asset.used_until.max(clip.end_time),
+            None => assets.push(Asset {
+                id: format!("r{}", assets.len() + 3),
+                path: clip.file_path.clone(),
+                info: probe::probe(&ffmpeg_path, &clip.file_path).ok(),
+                used_until: clip.end_time,
+            }),
+        }
+    }
+    let audio = audio_path.map(|path| Asset {
+        id: format!("r{}", assets.len() + 3),
+        info: probe::probe(&ffmpeg_path, &path).ok(),
+        path,
+        used_until: timeline_length(&clips),
+    });
+
+    let first_video = assets[0].info.as_ref().and_then(|info| info.first_video());
+    let fps = fps.or_else(|| first_video.and_then(|v| v.fps)).unwrap_or(DEFAULT_FPS).clamp(MIN_FPS, MAX_FPS);
+    let frame = first_video.and_then(|v| v.display_size()).unwrap_or(DEFAULT_FRAME_SIZE);
+    let title = title.unwrap_or_else(|| "rVJ timeline".to_string());
+    if title.chars().any(char::is_control) {
+        return Err("The title can't contain control characters".to_string());
+    }
+    let timebase = Timebase::new(fps)?;
+    let contents = fcpxml(&clips, &assets, audio.as_ref(), &timebase, frame, &title);
+    std::fs::write(&output_path, contents).map_err(|e| format!("Failed to write FCPXML {}: {}", output_path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timebase_uses_ntsc_fractions() {
+        for (fps, num, den) in [(25.0, 1, 25), (30.0, 1, 30), (29.97, 1001, 30000), (23.976, 1001, 24000)] {
+            let timebase = Timebase::new(fps).unwrap();
+            assert_eq!((timebase.num, timebase.den), (num, den), "at {} fps", fps);
+        }
+    }
+
+    #[test]
+    fn timebase_is_exact_at_other_fractional_rates() {
+        for (fps, num, den) in [(12.5, 2, 25), (47.952, 1001, 48000), (7.5, 2, 15), (23.5, 2, 47)] {
+            let timebase = Timebase::new(fps).unwrap();
+            assert_eq!((timebase.num, timebase.den), (num, den), "at {} fps", fps);
+        }
+        assert_eq!(Timebase::new(12.5).unwrap().time(2.0), "2s");
+        assert_eq!(Timebase::new(12.5).unwrap().time(0.16), "4/25s");
+        assert!(Timebase::new(14.123456).is_err());
+    }
+
+    #[test]
+    fn time_is_reduced_and_on_a_frame() {
+        let pal = Timebase::new(25.0).unwrap();
+        assert_eq!(pal.time(0.0), "0s");
+        assert_eq!(pal.time(2.0), "2s");
+        assert_eq!(pal.time(0.04), "1/25s");
+        // Rounded to the nearest frame
+        assert_eq!(pal.time(0.05), "1/25s");
+        assert_eq!(pal.time(-1.0), "0s");
+        let ntsc = Timebase::new(29.97).unwrap();
+        assert_eq!(ntsc.time(1001.0 / 30000.0), "1001/30000s");
+        assert_eq!(ntsc.time(1.0), "1001/1000s");
+    }
+
+    #[test]
+    fn constant_speed_maps_the_clip_to_its_source() {
+        let mut clip = ClipData::new("a.mp4".to_string(), 2.0, 6.0);
+        assert_eq!(time_map(&clip), None);
+        clip.speed = Some(2.0);
+        assert_eq!(time_map(&clip), Some(vec![(0.0, 2.0), (2.0, 6.0)]));
+        clip.reverse = true;
+        assert_eq!(time_map(&clip), Some(vec![(0.0, 6.0), (2.0, 2.0)]));
+    }
+}
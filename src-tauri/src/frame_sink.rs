@@ -0,0 +1,140 @@
+// Live outputs fed by the preview: an ffmpeg that reads the preview's MJPEG
+// frames on stdin and writes them somewhere other apps can pick them up (NDI,
+// a virtual camera), or converts them for rVJ to hand over itself. Whatever
+// the preview plays goes out, and between previews the output stays open
+// with nothing new to show.
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+
+// How long a stopped output gets to end cleanly before it is killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Input options for the preview's frames on stdin. They are stamped as they
+// arrive, so the output keeps the preview's pace whatever its fps.
+pub const MJPEG_STDIN_ARGS: [&str; 6] = ["-use_wallclock_as_timestamps", "1", "-f", "mjpeg", "-i", "pipe:0"];
+
+// Payload of an output's `{kind}-stopped`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SinkStopped {
+    name: String,
+    // Why it stopped when nobody asked it to, e.g. ffmpeg exiting
+    error: Option<String>,
+}
+
+type OutputReader = Box<dyn FnOnce(ChildStdout) + Send>;
+
+struct FrameSink {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    // The thread taking ffmpeg's stdout, for outputs that have one
+    reader: Option<JoinHandle<()>>,
+}
+
+impl FrameSink {
+    // Closing stdin ends ffmpeg's input, so it flushes and closes the output
+    // itself; it is only killed if it hangs. The output is fully released
+    // once this returns: a reader sees the end of stdout when ffmpeg exits.
+    fn shut_down(self) {
+        let FrameSink { mut child, stdin, reader, .. } = self;
+        drop(stdin);
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while Instant::now() < deadline && matches!(child.try_wait(), Ok(None)) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        if matches!(child.try_wait(), Ok(None)) {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if let Some(reader) = reader {
+            let _ = reader.join();
+        }
+    }
+}
+
+// One kind of output, with the sink currently running, if any. `kind` names
+// its events, `label` its errors.
+pub struct SinkSlot {
+    kind: &'static str,
+    label: &'static str,
+    sink: Mutex<Option<FrameSink>>,
+}
+
+impl SinkSlot {
+    pub const fn new(kind: &'static str, label: &'static str) -> Self {
+        SinkSlot { kind, label, sink: Mutex::new(None) }
+    }
+
+    // Starts `cmd`, which reads the frames from stdin, as this output called
+    // `name`, replacing the one running
+    pub fn start(&self, cmd: Command, name: String) -> Result<(), String> {
+        self.spawn(cmd, name, None)
+    }
+
+    // Like start, for an output ffmpeg can't write itself: `cmd` writes the
+    // frames to stdout, which `read_output` takes on a thread of its own. It
+    // sees the end of the stream once the output is stopped.
+    #[cfg(target_os = "windows")]
+    pub fn start_with_output(
+        &self,
+        cmd: Command,
+        name: String,
+        read_output: impl FnOnce(ChildStdout) + Send + 'static,
+    ) -> Result<(), String> {
+        self.spawn(cmd, name, Some(Box::new(read_output)))
+    }
+
+    fn spawn(&self, mut cmd: Command, name: String, read_output: Option<OutputReader>) -> Result<(), String> {
+        let mut sink = self.sink.lock().unwrap();
+        if let Some(previous) = sink.take() {
+            previous.shut_down();
+        }
+        let stdout = if read_output.is_some() { Stdio::piped() } else { Stdio::null() };
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(stdout)
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start {} output: {}", self.label, e))?;
+        let (Some(stdin), stdout) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("Failed to start {} output: no stdin", self.label));
+        };
+        let reader =
+            read_output.zip(stdout).map(|(read_output, stdout)| std::thread::spawn(move || read_output(stdout)));
+        *sink = Some(FrameSink { name, child, stdin, reader });
+        Ok(())
+    }
+
+    // Stops the running output; false if there was none
+    pub fn stop(&self) -> bool {
+        match self.sink.lock().unwrap().take() {
+            Some(sink) => {
+                sink.shut_down();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Sends one preview frame, when the output is running. One whose ffmpeg
+    // has gone away is dropped, with `{kind}-stopped`.
+    pub fn send_frame(&self, jpeg: &[u8], app_handle: &tauri::AppHandle) {
+        let mut sink = self.sink.lock().unwrap();
+        let Some(running) = sink.as_mut() else { return };
+        if let Err(e) = running.stdin.write_all(jpeg) {
+            let running = sink.take().unwrap();
+            let name = running.name.clone();
+            running.shut_down();
+            let error = Some(format!("{} output stopped: {}", self.label, e));
+            let _ = app_handle.emit(&format!("{}-stopped", self.kind), SinkStopped { name, error });
+        }
+    }
+}
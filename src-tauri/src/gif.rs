@@ -0,0 +1,224 @@
+// Animated GIFs of a stretch of one source file, e.g. a reaction grabbed from
+// raw footage without building a timeline. Encoded in two passes: palettegen
+// picks the 256 colors that suit the segment best, then paletteuse maps every
+// frame onto them, which looks far better than GIF's default palette.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+use tauri::{Emitter, Manager};
+
+use crate::temp::TempDirConfig;
+use crate::{resolve_ffmpeg, FfmpegProgress, JobStarted, ProcessRegistry, JOB_CANCELLED_ERROR};
+
+// GIF has no inter-frame compression, so a long one quickly runs to hundreds
+// of MB
+const MAX_GIF_SECONDS: f64 = 30.0;
+
+const DEFAULT_GIF_FPS: u32 = 15;
+const MAX_GIF_FPS: u32 = 50;
+const DEFAULT_GIF_WIDTH: u32 = 480;
+const MAX_GIF_WIDTH: u32 = 1920;
+
+// Upper bound of palettegen's max_colors; GIF can't index more
+const MAX_GIF_COLORS: u32 = 256;
+
+// How paletteuse approximates colors the palette lacks
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Dither {
+    // Ordered pattern; compresses well and doesn't shimmer between frames
+    Bayer,
+    // Error diffusion; smoothest gradients, but noisier and larger files
+    Sierra2,
+    // Nearest color only; gradients band, flat artwork stays crisp
+    None,
+}
+
+// Which pixels palettegen counts when picking colors
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsMode {
+    // Only what changes between frames, favoring the moving subject
+    Diff,
+    // Every pixel of every frame, favoring a static background
+    Full,
+}
+
+// Palette settings of a GIF export. Every field has a default.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GifPalette {
+    // Palette size, 2..=256; fewer colors, smaller file
+    pub colors: u32,
+    pub dither: Dither,
+    pub stats_mode: StatsMode,
+}
+
+impl Default for GifPalette {
+    fn default() -> Self {
+        GifPalette { colors: MAX_GIF_COLORS, dither: Dither::Bayer, stats_mode: StatsMode::Diff }
+    }
+}
+
+impl GifPalette {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(2..=MAX_GIF_COLORS).contains(&self.colors) {
+            return Err(format!("GIF colors must be between 2 and {}, got {}", MAX_GIF_COLORS, self.colors));
+        }
+        Ok(())
+    }
+}
+
+// Payload of `gif-progress`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct GifProgress {
+    job_id: String,
+    percent: u8,
+}
+
+// Frame rate and size every GIF frame is reduced to before either pass
+fn frame_filter(fps: u32, width: u32) -> String {
+    format!("fps={},scale={}:-2:flags=lanczos", fps, width)
+}
+
+// First pass: the segment's palette, of at most `colors` colors
+fn palettegen_filter(fps: u32, width: u32, palette: &GifPalette) -> String {
+    let stats_mode = match palette.stats_mode {
+        StatsMode::Diff => "diff",
+        StatsMode::Full => "full",
+    };
+    format!(
+        "{},palettegen=max_colors={}:stats_mode={}",
+        frame_filter(fps, width),
+        palette.colors,
+        stats_mode
+    )
+}
+
+// Second pass: the frames (input 0) mapped onto the palette (input 1). Only
+// the changed rectangle of each frame is re-dithered, which keeps the file
+// small and still areas free of crawling dither.
+fn paletteuse_filter(fps: u32, width: u32, palette: &GifPalette) -> String {
+    let dither = match palette.dither {
+        // Scale 5 is the coarsest, least visible pattern
+        Dither::Bayer => "bayer:bayer_scale=5",
+        Dither::Sierra2 => "sierra2",
+        Dither::None => "none",
+    };
+    format!(
+        "[0:v]{}[frames];[frames][1:v]paletteuse=dither={}:diff_mode=rectangle",
+        frame_filter(fps, width),
+        dither
+    )
+}
+
+fn validate(file_path: &str, start: f64, end: f64, fps: u32, width: u32, output_path: &str) -> Result<(), String> {
+    if !Path::new(file_path).is_file() {
+        return Err(format!("File not found: {}", file_path));
+    }
+    if !start.is_finite() || !end.is_finite() || start < 0.0 || end <= start {
+        return Err(format!("Invalid GIF range {} to {}", start, end));
+    }
+    if end - start > MAX_GIF_SECONDS {
+        return Err(format!("GIFs are limited to {} seconds, got {:.1}", MAX_GIF_SECONDS, end - start));
+    }
+    if !(1..=MAX_GIF_FPS).contains(&fps) {
+        return Err(format!("GIF fps must be between 1 and {}, got {}", MAX_GIF_FPS, fps));
+    }
+    if !(16..=MAX_GIF_WIDTH).contains(&width) {
+        return Err(format!("GIF width must be between 16 and {}, got {}", MAX_GIF_WIDTH, width));
+    }
+    let is_gif = Path::new(output_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+    if !is_gif {
+        return Err(format!("GIF output must be a .gif file, got {}", output_path));
+    }
+    Ok(())
+}
+
+// Command to make a GIF of `file_path` from `start` to `end` seconds, at `fps`
+// (default 15) and `width` pixels wide (default 480, height keeps the aspect).
+// `job-started` carries the id for cancel_job, and `gif-progress` follows the
+// encode: the palette pass is the first half, the GIF itself the second.
+// `palette` tunes the colors and dithering. Returns the output path.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_clip_gif(
+    file_path: String,
+    start: f64,
+    end: f64,
+    fps: Option<u32>,
+    width: Option<u32>,
+    palette: Option<GifPalette>,
+    output_path: String,
+    app_handle: tauri::AppHandle,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<String, String> {
+    let fps = fps.unwrap_or(DEFAULT_GIF_FPS);
+    let width = width.unwrap_or(DEFAULT_GIF_WIDTH);
+    validate(&file_path, start, end, fps, width, &output_path)?;
+    let palette_options = palette.unwrap_or_default();
+    palette_options.validate()?;
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    let duration = end - start;
+
+    let job_id = registry.new_job_id("gif");
+    let _job = registry.begin(&job_id)?;
+    let _ = app_handle.emit("job-started", JobStarted { job_id: job_id.clone(), kind: "gif".to_string() });
+    let emit_progress = |percent: u8| {
+        let _ = app_handle.emit("gif-progress", GifProgress { job_id: job_id.clone(), percent });
+    };
+
+    let palette_path = app_handle
+        .state::<TempDirConfig>()
+        .root()
+        .join(format!("rvj_{}-{}-palette.png", job_id, std::process::id()));
+    let palette = palette_path.to_str().ok_or("Invalid path")?;
+    let (start_arg, duration_arg) = (start.to_string(), duration.to_string());
+
+    // palettegen only writes its one frame at the very end, so this pass
+    // reports nothing until it is done
+    let mut cmd = Command::new(&ffmpeg_path);
+    cmd.args(["-y", "-ss", &start_arg, "-t", &duration_arg, "-i", &file_path]);
+    cmd.args(["-vf", &palettegen_filter(fps, width, &palette_options), "-frames:v", "1", palette]);
+    let result = registry
+        .run(&job_id, &mut cmd)
+        .map_err(|e| format!("FFmpeg GIF palette failed: {}", e))
+        .and_then(|status| match status.success() {
+            true => Ok(()),
+            false => Err("FFmpeg GIF palette exited with error".to_string()),
+        })
+        .and_then(|()| {
+            emit_progress(50);
+            let mut cmd = Command::new(&ffmpeg_path);
+            cmd.args(["-y", "-progress", "pipe:1"]);
+            cmd.args(["-ss", &start_arg, "-t", &duration_arg, "-i", &file_path, "-i", palette]);
+            cmd.args(["-filter_complex", &paletteuse_filter(fps, width, &palette_options), &output_path]);
+            let report = |progress: &FfmpegProgress| {
+                let fraction = progress.out_time.map_or(0.0, |t| (t / duration).min(1.0));
+                emit_progress((50.0 + fraction * 49.0) as u8);
+            };
+            registry
+                .run_with_progress(&job_id, &mut cmd, report, |_| {})
+                .map_err(|e| format!("FFmpeg GIF encode failed: {}", e))
+        })
+        .and_then(|status| match status.success() {
+            true => Ok(()),
+            false => Err("FFmpeg GIF encode exited with error".to_string()),
+        });
+    let _ = std::fs::remove_file(&palette_path);
+
+    if registry.is_cancelled(&job_id) {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(JOB_CANCELLED_ERROR.to_string());
+    }
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(e);
+    }
+    emit_progress(100);
+    Ok(output_path)
+}
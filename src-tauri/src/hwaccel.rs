@@ -0,0 +1,172 @@
+// Hardware decoding that keeps frames in GPU memory. Decoded frames are
+// normally copied back to system memory for the CPU filters, which for
+// high-res footage costs more than the decode saves. With these hwaccels the
+// frames stay on the GPU and are scaled there, so only the smaller scaled
+// frames are downloaded, if at all. An export's GPU pipeline goes further and
+// encodes them there too.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// Frames downloaded from the GPU are 8-bit 4:2:0, which every encoder takes
+pub const DOWNLOAD_FILTER: &str = "hwdownload,format=nv12";
+
+// Same quality target as libx264's default crf
+const GPU_QUALITY: &str = "23";
+
+// A hwaccel whose frames can stay on the GPU, with its scale filter and the
+// H.264 encoder on the same hardware
+#[derive(Debug, Clone, Copy)]
+pub struct GpuFrames {
+    hwaccel: &'static str,
+    scale: &'static str,
+    encoder: &'static str,
+}
+
+const CUDA: GpuFrames = GpuFrames { hwaccel: "cuda", scale: "scale_cuda", encoder: "h264_nvenc" };
+const QSV: GpuFrames = GpuFrames { hwaccel: "qsv", scale: "scale_qsv", encoder: "h264_qsv" };
+const VAAPI: GpuFrames = GpuFrames { hwaccel: "vaapi", scale: "scale_vaapi", encoder: "h264_vaapi" };
+
+// CUDA, QSV and VAAPI have GPU scale filters; the other hwaccels (and "auto",
+// which may pick any) hand frames back in system memory
+pub fn gpu_frames(hwaccel: &str) -> Option<GpuFrames> {
+    match hwaccel {
+        "cuda" => Some(CUDA),
+        "qsv" => Some(QSV),
+        "vaapi" => Some(VAAPI),
+        _ => None,
+    }
+}
+
+// Export option running each trim's decode, scale and encode on one GPU
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuPipeline {
+    // NVIDIA: NVDEC, scale_cuda and NVENC
+    Cuda,
+    // Intel Quick Sync
+    Qsv,
+    // VAAPI, mostly Intel and AMD on Linux
+    Vaapi,
+}
+
+impl GpuPipeline {
+    pub fn frames(self) -> GpuFrames {
+        match self {
+            GpuPipeline::Cuda => CUDA,
+            GpuPipeline::Qsv => QSV,
+            GpuPipeline::Vaapi => VAAPI,
+        }
+    }
+}
+
+impl GpuFrames {
+    // Input options decoding onto the GPU and leaving the frames there. VAAPI
+    // also names its device, which the upload filter needs.
+    pub fn decode_args(&self) -> Vec<&'static str> {
+        let mut args = Vec::new();
+        if self.hwaccel == "vaapi" {
+            args.extend(["-vaapi_device", "/dev/dri/renderD128"]);
+        }
+        args.extend(["-hwaccel", self.hwaccel, "-hwaccel_output_format", self.hwaccel]);
+        args
+    }
+
+    // Scales GPU frames to exactly `width`x`height`, converting to 8-bit
+    // 4:2:0 on the way so 10-bit sources download like any other
+    pub fn scale_filter(&self, width: u32, height: u32) -> String {
+        format!("{}=w={}:h={}:format=nv12", self.scale, width, height)
+    }
+
+    // The 8-bit 4:2:0 conversion alone, keeping the size; the hardware
+    // encoders don't all take 10-bit H.264 input
+    fn convert_filter(&self) -> String {
+        format!("{}=format=nv12", self.scale)
+    }
+
+    // A trim's video filters for frames decoded by this hwaccel. Retiming
+    // (setpts) works on GPU frames, so a clip needing nothing else never
+    // leaves the GPU. Any other filter runs on the CPU: the frames are
+    // downloaded first, and uploaded again for VAAPI, whose encoder only
+    // reads GPU frames.
+    pub fn wrap_filters(&self, mut filters: Vec<String>) -> Vec<String> {
+        if filters.iter().all(|f| f.starts_with("setpts=")) {
+            filters.push(self.convert_filter());
+            return filters;
+        }
+        filters.insert(0, self.download_filter());
+        filters.extend(self.upload_filter().map(str::to_string));
+        filters
+    }
+
+    // Converts and downloads GPU frames for CPU filters
+    pub fn download_filter(&self) -> String {
+        format!("{},{}", self.convert_filter(), DOWNLOAD_FILTER)
+    }
+
+    // Back onto the GPU after CPU filters, when the encoder needs it
+    pub fn upload_filter(&self) -> Option<&'static str> {
+        (self.hwaccel == "vaapi").then_some("format=nv12,hwupload")
+    }
+
+    // Fast encode for the intermediates, as ultrafast is for libx264
+    pub fn trim_video_args(&self) -> Vec<String> {
+        let mut args = vec!["-c:v".to_string(), self.encoder.to_string()];
+        args.extend(self.quality_args(true));
+        args
+    }
+
+    // Encoder arguments for the final encode, which reads the intermediates
+    // in system memory. None for VAAPI, whose encoder can't take those
+    // without an upload the final encode doesn't have.
+    pub fn final_video_args(&self) -> Option<Vec<String>> {
+        if self.upload_filter().is_some() {
+            return None;
+        }
+        let mut args = vec!["-c:v".to_string(), self.encoder.to_string()];
+        args.extend(self.quality_args(false));
+        Some(args)
+    }
+
+    fn quality_args(&self, fast: bool) -> Vec<String> {
+        let args: &[&str] = match (self.hwaccel, fast) {
+            ("cuda", true) => &["-preset", "p1", "-rc", "vbr", "-cq", GPU_QUALITY, "-b:v", "0"],
+            ("cuda", false) => &["-preset", "p5", "-rc", "vbr", "-cq", GPU_QUALITY, "-b:v", "0"],
+            ("qsv", true) => &["-preset", "veryfast", "-global_quality", GPU_QUALITY],
+            ("qsv", false) => &["-preset", "medium", "-global_quality", GPU_QUALITY],
+            _ => &["-rc_mode", "CQP", "-qp", GPU_QUALITY],
+        };
+        args.iter().map(|s| s.to_string()).collect()
+    }
+}
+
+fn succeeds(cmd: &mut Command) -> bool {
+    cmd.stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok_and(|s| s.success())
+}
+
+// Whether a moment of `file_path` at `start` goes through the whole chain:
+// decoded onto the GPU, converted there and encoded by the GPU encoder. Any
+// stage failing to start (no device, no driver, a codec the decoder lacks)
+// fails it.
+pub fn chain_works(ffmpeg_path: &Path, gpu: GpuFrames, file_path: &str, start: f64) -> bool {
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.args(["-v", "error"]);
+    cmd.args(gpu.decode_args());
+    cmd.args(["-ss", &start.to_string(), "-i", file_path, "-frames:v", "5", "-an"]);
+    cmd.args(["-vf", &gpu.convert_filter()]);
+    cmd.args(gpu.trim_video_args());
+    cmd.args(["-f", "null", "-"]);
+    succeeds(&mut cmd)
+}
+
+// Whether the GPU encoder takes frames from system memory, as the final
+// encode gives it
+pub fn encoder_works(ffmpeg_path: &Path, final_video: &[String]) -> bool {
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.args(["-v", "error", "-f", "lavfi", "-i", "color=black:size=256x256:duration=0.2"]);
+    cmd.args(["-pix_fmt", "nv12"]);
+    cmd.args(final_video);
+    cmd.args(["-f", "null", "-"]);
+    succeeds(&mut cmd)
+}
@@ -3,123 +3,1957 @@
 
 use tauri::Manager;
 use tauri::Emitter;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+use tauri_plugin_shell::ShellExt;
+use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::io::{BufRead, BufReader, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 use std::fs::File as StdFile;
+use tokio::sync::Semaphore;
+
+mod analysis;
+mod animated;
+mod capabilities;
+mod conform;
+mod cover;
+mod disk;
+mod edl;
+mod effects;
+mod encode;
+mod fcpxml;
+mod frame_sink;
+mod gif;
+mod hwaccel;
+mod markers;
+mod midi;
+mod ndi;
+mod osc;
+mod overlay;
+mod preview;
+mod probe;
+mod proxy;
+mod reactive;
+mod remote;
+mod render_cache;
+mod spectrum;
+mod stream;
+mod subtitles;
+mod tasks;
+mod tempo;
+mod temp;
+mod texture_share;
+mod thumbnail_cache;
+mod transitions;
+mod virtual_camera;
+mod warnings;
+
+use encode::{AudioCodec, DnxhrProfile, EncodePlan, ProResProfile, VideoCodec};
+use overlay::{OverlayPosition, TimecodeBase};
+use remote::RemoteConfig;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct ClipData {
     file_path: String,
     start_time: f64,
     end_time: f64,
+    // Tighten the trim to the part of the range that isn't silent
+    #[serde(default)]
+    trim_silence: bool,
+    // Deinterlace while trimming; None does so when the file's field order
+    // says it is interlaced
+    #[serde(default)]
+    deinterlace: Option<bool>,
+    // Varies the playback speed across the clip. Clip audio is muted, as
+    // retiming it would warble the pitch.
+    #[serde(default)]
+    speed_ramp: Option<Vec<effects::SpeedKeyframe>>,
+    // Constant playback speed, e.g. 2.0 for double speed
+    #[serde(default)]
+    speed: Option<f64>,
+    // With `speed`, keep the audio's pitch (atempo) rather than shifting it
+    // along with the tempo (asetrate)
+    #[serde(default = "default_preserve_pitch")]
+    preserve_pitch: bool,
+    // Quick color fixes; None leaves the picture as shot
+    #[serde(default)]
+    brightness: Option<f64>,
+    #[serde(default)]
+    contrast: Option<f64>,
+    #[serde(default)]
+    saturation: Option<f64>,
+    #[serde(default)]
+    gamma: Option<f64>,
+    // Darkened edges, 0..1
+    #[serde(default)]
+    vignette: Option<f64>,
+    // Sharpening for soft or upscaled footage, 0..1
+    #[serde(default)]
+    sharpen: Option<f64>,
+    // Transition from the previous clip into this one; ignored on the first
+    #[serde(default)]
+    transition: Option<transitions::Transition>,
+    // Play the clip backwards. The reverse filters hold the whole clip in
+    // memory, so long clips need a lot of it.
+    #[serde(default)]
+    reverse: bool,
+    // Boomerang: play the clip forwards, then backwards, for twice its length
+    #[serde(default)]
+    ping_pong: bool,
+    // Seconds to hold the first / last frame for, with silence under it
+    #[serde(default)]
+    freeze_start: Option<f64>,
+    #[serde(default)]
+    freeze_end: Option<f64>,
+    // Which of the file's audio streams to use, counting audio streams only
+    // (`0:a:{index}`); None lets ffmpeg pick
+    #[serde(default)]
+    audio_stream_index: Option<u32>,
+    // Likewise for video (`0:v:{index}`), e.g. the second angle of a
+    // dual-camera recording; None uses the first video stream
+    #[serde(default)]
+    video_stream_index: Option<u32>,
+}
+
+fn default_preserve_pitch() -> bool {
+    true
+}
+
+impl ClipData {
+    // `start_time..end_time` of `file_path`, with every effect at its default
+    fn new(file_path: String, start_time: f64, end_time: f64) -> Self {
+        ClipData {
+            file_path,
+            start_time,
+            end_time,
+            trim_silence: false,
+            deinterlace: None,
+            speed_ramp: None,
+            speed: None,
+            preserve_pitch: default_preserve_pitch(),
+            brightness: None,
+            contrast: None,
+            saturation: None,
+            gamma: None,
+            vignette: None,
+            sharpen: None,
+            transition: None,
+            reverse: false,
+            ping_pong: false,
+            freeze_start: None,
+            freeze_end: None,
+            audio_stream_index: None,
+            video_stream_index: None,
+        }
+    }
+
+    // Length of the clip once placed on the timeline, after any speed ramp,
+    // ping-pong and freeze-frames
+    fn timeline_duration(&self) -> f64 {
+        let duration = self.end_time - self.start_time;
+        let played = match (&self.speed_ramp, self.speed) {
+            (Some(ramp), _) => effects::ramp_output_duration(ramp, duration),
+            (None, Some(speed)) => duration / speed,
+            (None, None) => duration,
+        };
+        let played = if self.ping_pong { played * 2.0 } else { played };
+        played + self.freeze_start.unwrap_or(0.0) + self.freeze_end.unwrap_or(0.0)
+    }
+}
+
+// The timeline played back to front: clips in reverse order, each with its
+// content reversed (so a clip already set to reverse plays forwards), and each
+// transition kept on the cut it was on
+fn reverse_timeline_clips(mut clips: Vec<ClipData>) -> Vec<ClipData> {
+    let transitions: Vec<_> = clips.iter().map(|c| c.transition).collect();
+    let count = clips.len();
+    clips.reverse();
+    for (j, clip) in clips.iter_mut().enumerate() {
+        clip.reverse = !clip.reverse;
+        clip.transition = if j == 0 { None } else { transitions[count - j] };
+    }
+    clips
+}
+
+// Length of the exported timeline: the clips end to end, less the time
+// each transition overlaps two of them
+fn timeline_length(clips: &[ClipData]) -> f64 {
+    let overlaps: f64 = clips.iter().skip(1).filter_map(|c| c.transition).map(|t| t.duration).sum();
+    clips.iter().map(ClipData::timeline_duration).sum::<f64>() - overlaps
 }
 
+// Checks each clip's speed or ramp, transition and freeze-frames
+fn validate_clips(clips: &[ClipData]) -> Result<(), String> {
+    for clip in clips {
+        if let Some(ramp) = &clip.speed_ramp {
+            effects::validate_speed_ramp(ramp, clip.end_time - clip.start_time)?;
+        }
+        if let Some(speed) = clip.speed {
+            if clip.speed_ramp.is_some() {
+                return Err(format!("Clip {} has both a speed and a speed ramp", clip.file_path));
+            }
+            effects::validate_speed(speed)?;
+        }
+        if let Some(transition) = &clip.transition {
+            transition.validate()?;
+        }
+        for freeze in [clip.freeze_start, clip.freeze_end].into_iter().flatten() {
+            effects::validate_freeze(freeze)?;
+        }
+    }
+    Ok(())
+}
+
+// Checks every transition fits the clips it joins, at their timeline lengths
+fn validate_transition_fit(clips: &[ClipData]) -> Result<(), String> {
+    transitions::validate_fit(
+        &clips.iter().map(ClipData::timeline_duration).collect::<Vec<_>>(),
+        &clips.iter().map(|c| c.transition).collect::<Vec<_>>(),
+    )
+}
+
+// Applies each clip's trim_silence, so both the video and audio trims use the
+// tightened range
+fn tighten_silent_clips(ffmpeg_path: &Path, mut clips: Vec<ClipData>) -> Result<Vec<ClipData>, String> {
+    for clip in clips.iter_mut().filter(|c| c.trim_silence) {
+        let (start, end) = analysis::non_silent_range(ffmpeg_path, &clip.file_path, clip.start_time, clip.end_time)?;
+        clip.start_time = start;
+        clip.end_time = end;
+    }
+    Ok(clips)
+}
+
+// Optional export settings. Every field has a default, so callers can omit
+// `options` entirely or pass only what they change.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct ExportOptions {
+    // Keep each clip's own audio, mixed under the external track
+    keep_clip_audio: bool,
+    // Length of the short fade applied at both ends of each clip's audio so
+    // kept clip audio doesn't click at the cuts; 0 disables it
+    declick_ms: f64,
+    // Preserve the alpha channel; needs a .mov or .webm output
+    alpha: bool,
+    video_codec: VideoCodec,
+    // Used when video_codec is prores
+    prores_profile: ProResProfile,
+    // Used when video_codec is dnxhr
+    dnxhr_profile: DnxhrProfile,
+    // Lossless master (FFV1 in .mkv, otherwise H.264 qp 0); very large files
+    lossless: bool,
+    // Bitrate of the final lossy audio encode, e.g. "192k"
+    audio_bitrate: String,
+    // Final audio codec; None picks one from the container
+    audio_codec: Option<AudioCodec>,
+    // Burn a running timecode into the picture, for review copies
+    timecode_overlay: bool,
+    timecode_position: OverlayPosition,
+    // Count from the start of the export, or from each clip's source in-point
+    timecode_base: TimecodeBase,
+    // Bring each kept clip audio to clip_loudness_lufs before mixing, so quiet
+    // and loud sources sit at a similar level
+    normalize_clip_audio: bool,
+    clip_loudness_lufs: f64,
+    // Flash the picture on the given beats
+    strobe: Option<effects::StrobeOptions>,
+    // Brightness, zoom and watermark opacity following bands of the music
+    audio_reactive: Vec<reactive::ReactiveMapping>,
+    // Tone-map HDR (PQ/HLG) clips to SDR. None does so for every clip probed
+    // as HDR; false keeps HDR clips as they are
+    tonemap: Option<bool>,
+    // Image laid over the picture, e.g. a logo
+    watermark: Option<overlay::Watermark>,
+    // Caption burned into every clip; see overlay::TextOverlay for its tokens
+    text_overlay: Option<overlay::TextOverlay>,
+    // Burn `subtitles` into the picture, or mux `subtitle_tracks` as
+    // switchable streams
+    subtitle_mode: subtitles::SubtitleMode,
+    // Subtitle file burned into the picture
+    subtitles: Option<subtitles::BurnedSubtitles>,
+    // Soft subtitles, one stream per language; .mkv, .mp4 or .mov only
+    subtitle_tracks: Vec<subtitles::SubtitleTrack>,
+    // Trim with -copyts, so a clip's audio keeps its exact offset from the
+    // video instead of each stream restarting at 0 on its own, which drifts
+    // out of sync over many cuts. false trims the old way.
+    preserve_timestamps: bool,
+    // Cover art for players and file browsers; .mp4, .mov and .mkv only
+    cover: Option<cover::CoverArt>,
+    // Also write export_video's markers to a file next to the output. They
+    // go to a .json one regardless when the output can't hold chapters.
+    marker_sidecar: Option<markers::SidecarFormat>,
+    // MP4/MOV index at the front (faststart) or the end
+    moov_placement: encode::MoovPlacement,
+    // Decode, scale and encode on this GPU; H.264 only. Falls back to the
+    // CPU for any clip (or the final encode) the GPU can't handle.
+    gpu_pipeline: Option<hwaccel::GpuPipeline>,
+    // Threads of the libx264 trim and final encodes; None lets ffmpeg use
+    // every core. Fewer keeps the machine usable during a render.
+    threads: Option<u32>,
+    // Encode for live output (a .ts or .flv stream) rather than quality: see
+    // encode::low_latency_video. Refused for other outputs.
+    low_latency: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            keep_clip_audio: false,
+            declick_ms: DEFAULT_DECLICK_MS,
+            alpha: false,
+            video_codec: VideoCodec::H264,
+            prores_profile: ProResProfile::Hq,
+            dnxhr_profile: DnxhrProfile::Hq,
+            lossless: false,
+            audio_bitrate: encode::DEFAULT_AUDIO_BITRATE.to_string(),
+            audio_codec: None,
+            timecode_overlay: false,
+            timecode_position: OverlayPosition::BottomRight,
+            timecode_base: TimecodeBase::Timeline,
+            normalize_clip_audio: false,
+            clip_loudness_lufs: DEFAULT_CLIP_LOUDNESS_LUFS,
+            strobe: None,
+            audio_reactive: Vec::new(),
+            tonemap: None,
+            watermark: None,
+            text_overlay: None,
+            subtitle_mode: subtitles::SubtitleMode::Burn,
+            subtitles: None,
+            subtitle_tracks: Vec::new(),
+            preserve_timestamps: true,
+            cover: None,
+            marker_sidecar: None,
+            moov_placement: encode::MoovPlacement::Auto,
+            gpu_pipeline: None,
+            threads: None,
+            low_latency: false,
+        }
+    }
+}
+
+impl ExportOptions {
+    fn validate(&self) -> Result<(), String> {
+        validate_declick_ms(self.declick_ms)?;
+        if !(MIN_LOUDNESS_LUFS..=MAX_LOUDNESS_LUFS).contains(&self.clip_loudness_lufs) {
+            return Err(format!(
+                "clip_loudness_lufs must be between {} and {}, got {}",
+                MIN_LOUDNESS_LUFS, MAX_LOUDNESS_LUFS, self.clip_loudness_lufs
+            ));
+        }
+        if let Some(strobe) = &self.strobe {
+            strobe.validate()?;
+        }
+        reactive::validate_mappings(&self.audio_reactive, self.watermark.is_some())?;
+        if let Some(watermark) = &self.watermark {
+            watermark.validate()?;
+        }
+        if let Some(text) = &self.text_overlay {
+            text.validate()?;
+        }
+        if let Some(subtitles) = &self.subtitles {
+            subtitles.validate()?;
+        }
+        match self.subtitle_mode {
+            subtitles::SubtitleMode::Burn if !self.subtitle_tracks.is_empty() => {
+                return Err("subtitle_tracks need subtitle_mode \"soft\"".to_string());
+            }
+            subtitles::SubtitleMode::Soft if self.subtitles.is_some() => {
+                return Err("Burned subtitles need subtitle_mode \"burn\"".to_string());
+            }
+            _ => {}
+        }
+        for track in &self.subtitle_tracks {
+            track.validate()?;
+        }
+        if let Some(cover) = &self.cover {
+            cover.validate()?;
+        }
+        if let Some(threads) = self.threads.filter(|t| !(1..=encode::MAX_X264_THREADS).contains(t)) {
+            return Err(format!("threads must be between 1 and {}, got {}", encode::MAX_X264_THREADS, threads));
+        }
+        encode::validate_audio_bitrate(&self.audio_bitrate)
+    }
+}
+
+const DEFAULT_DECLICK_MS: f64 = 8.0;
+const MAX_DECLICK_MS: f64 = 100.0;
+
+fn validate_declick_ms(declick_ms: f64) -> Result<(), String> {
+    if !(0.0..=MAX_DECLICK_MS).contains(&declick_ms) {
+        return Err(format!("declick_ms must be between 0 and {}, got {}", MAX_DECLICK_MS, declick_ms));
+    }
+    Ok(())
+}
+
+// Integrated loudness clip audio is normalized to; -16 LUFS is the common
+// target for online video
+const DEFAULT_CLIP_LOUDNESS_LUFS: f64 = -16.0;
+// Range loudnorm accepts for its integrated target
+const MIN_LOUDNESS_LUFS: f64 = -70.0;
+const MAX_LOUDNESS_LUFS: f64 = -5.0;
+
+// Single-pass `loudnorm` to `lufs`. loudnorm upsamples to 192 kHz internally,
+// so resample back for the intermediate.
+fn loudnorm_filter(lufs: f64) -> String {
+    format!("loudnorm=I={}:TP=-1.5:LRA=11,aresample=48000", lufs)
+}
+
+// `afade` in/out at the edges of a clip's audio to remove the pop a hard cut
+// makes. None when disabled or the clip is too short to fade both ends.
+fn declick_filter(duration: f64, declick_ms: f64) -> Option<String> {
+    let fade = declick_ms / 1000.0;
+    if fade <= 0.0 || duration <= fade * 2.0 {
+        return None;
+    }
+    Some(format!(
+        "afade=t=in:st=0:d={fade},afade=t=out:st={}:d={fade}",
+        duration - fade,
+        fade = fade
+    ))
+}
+
+// Payload of `export-progress`. The detail fields are filled in while the
+// final encode runs and ffmpeg reports them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 struct ExportProgress {
+    // The export task reporting; None for audio-only exports
+    task_id: Option<String>,
     percent: u8,
+    // Frames written by the current encode
+    frame: Option<u64>,
+    // Seconds of output written by the current encode
+    time: Option<f64>,
+    fps: Option<f64>,
+    // Encode speed as a multiple of real time
+    speed: Option<f64>,
+    // Frames of the output written so far, out of total_frames (from the
+    // output's length and the first clip's frame rate)
+    frames_done: Option<u64>,
+    total_frames: Option<u64>,
+    // Size of the output so far; stops growing when the encode stalls
+    bytes_written: Option<u64>,
+}
+
+impl ExportProgress {
+    fn from_percent(percent: u8) -> Self {
+        ExportProgress { percent, ..Default::default() }
+    }
+}
+
+// One block of ffmpeg's `-progress` output. Keys arrive one per line and a
+// `progress=continue|end` line closes the block.
+#[derive(Debug, Clone, Default)]
+struct FfmpegProgress {
+    frame: Option<u64>,
+    out_time: Option<f64>,
+    fps: Option<f64>,
+    speed: Option<f64>,
+    // Bytes muxed so far
+    total_size: Option<u64>,
+    // Output bitrate so far, in kb/s
+    bitrate: Option<f64>,
+}
+
+impl FfmpegProgress {
+    // Applies one `key=value` line; true when it completed a block. Values are
+    // "N/A" until ffmpeg knows them, which leaves the field unchanged.
+    fn update(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "frame" => self.frame = value.parse().ok().or(self.frame),
+            "fps" => self.fps = value.parse().ok().or(self.fps),
+            // out_time_ms is in microseconds too, despite the name
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(us) = value.parse::<i64>() {
+                    self.out_time = Some(us.max(0) as f64 / 1_000_000.0);
+                }
+            }
+            "speed" => self.speed = value.trim_end_matches('x').trim().parse().ok().or(self.speed),
+            "total_size" => self.total_size = value.parse().ok().or(self.total_size),
+            "bitrate" => self.bitrate = value.trim_end_matches("kbits/s").trim().parse().ok().or(self.bitrate),
+            "progress" => return true,
+            _ => {}
+        }
+        false
+    }
+}
+
+// A line of child process output and the pipe it came from
+enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+// Forwards the lines read from `pipe` until it closes. ffmpeg ends its stats
+// lines with '\r', so both separators split lines.
+fn spawn_line_reader(
+    pipe: impl std::io::Read + Send + 'static,
+    tx: mpsc::Sender<OutputLine>,
+    wrap: fn(String) -> OutputLine,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        for chunk in BufReader::new(pipe).split(b'\r') {
+            let Ok(chunk) = chunk else { break };
+            for line in String::from_utf8_lossy(&chunk).lines() {
+                if tx.send(wrap(line.to_string())).is_err() {
+                    return;
+                }
+            }
+        }
+    })
+}
+
+// Payload of `export-warning`: something the user should know about that
+// doesn't stop the export
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ExportWarning {
+    task_id: String,
+    message: String,
+}
+
+fn emit_warning(window: &tauri::Window, task_id: &str, message: String) {
+    let _ = window.emit("export-warning", ExportWarning { task_id: task_id.to_string(), message });
+}
+
+// Emits an export-warning when the volume holding `path` is nearly full.
+// Not being able to read the free space isn't worth failing over.
+fn warn_if_low_space(window: &tauri::Window, task_id: &str, path: &Path, role: &str) {
+    if let Ok(space) = disk::space_for(path) {
+        if space.is_low() {
+            let message = format!(
+                "Only {:.1} GB free on the {} volume ({})",
+                space.free as f64 / 1e9,
+                role,
+                path.display()
+            );
+            emit_warning(window, task_id, message);
+        }
+    }
+}
+
+// Job id of the audio-only export; video exports run under their task id
+const EXPORT_JOB: &str = "export";
+
+const CANCELLED_ERROR: &str = "Export cancelled";
+
+// What a job's ffmpeg run fails with once `cancel_job` has stopped it
+const JOB_CANCELLED_ERROR: &str = "Job cancelled";
+
+// Payload of `job-started`, which carries the id `cancel_job` takes
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct JobStarted {
+    job_id: String,
+    kind: String,
+}
+
+// System-wide shortcut that aborts the running export, even when unfocused
+const DEFAULT_CANCEL_SHORTCUT: &str = "Ctrl+Shift+Escape";
+
+// The cancel shortcut, as the user typed it, and why it couldn't be
+// registered if it couldn't
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CancelShortcutState {
+    shortcut: String,
+    error: Option<String>,
+}
+
+struct CancelShortcut(Mutex<CancelShortcutState>);
+
+// A running job and the ffmpeg processes it is currently waiting on. Batch jobs
+// run several at once, keyed by a token from ProcessRegistry::next_token.
+#[derive(Default)]
+struct RunningJob {
+    cancelled: bool,
+    children: HashMap<u64, Child>,
+}
+
+// Tracks spawned ffmpeg children so they can be killed and reaped on cancel or
+// app exit. An orphaned ffmpeg keeps its output files open, which on Windows
+// stops the temp dir from being removed.
+#[derive(Default)]
+struct ProcessRegistry {
+    jobs: Mutex<HashMap<String, RunningJob>>,
+    next_token: AtomicU64,
+}
+
+impl ProcessRegistry {
+    // A fresh job id such as "proxy-3"
+    fn new_job_id(&self, kind: &str) -> String {
+        format!("{}-{}", kind, self.next_token.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn begin(&self, job: &str) -> Result<JobGuard<'_>, String> {
+        self.reserve(job)?;
+        Ok(self.adopt(job))
+    }
+
+    // Registers `job` ahead of the thread that will run it, so a cancel sent
+    // as soon as its id is known isn't lost. That thread takes it over with
+    // `adopt`.
+    fn reserve(&self, job: &str) -> Result<(), String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.contains_key(job) {
+            return Err(format!("Job '{}' is already running", job));
+        }
+        jobs.insert(job.to_string(), RunningJob::default());
+        Ok(())
+    }
+
+    // The guard of a job registered with `reserve`, cancelled or not
+    fn adopt(&self, job: &str) -> JobGuard<'_> {
+        JobGuard { registry: self, job: job.to_string() }
+    }
+
+    // Spawns `cmd` as part of `job` and waits for it, polling so that a cancel
+    // from another thread can kill the child in the meantime.
+    fn run(&self, job: &str, cmd: &mut Command) -> Result<ExitStatus, String> {
+        self.run_with_stderr(job, cmd, |_| {})
+    }
+
+    // Like `run`, but hands each stderr line to `on_line` as it arrives
+    fn run_with_stderr(
+        &self,
+        job: &str,
+        cmd: &mut Command,
+        mut on_line: impl FnMut(&str),
+    ) -> Result<ExitStatus, String> {
+        self.run_with_output(job, cmd, |line| {
+            if let OutputLine::Stderr(line) = line {
+                on_line(line);
+            }
+        })
+    }
+
+    // Runs an ffmpeg that was given `-progress pipe:1` and reports each
+    // progress block. Falls back to the `time=` of the stderr stats when the
+    // pipe stays silent, e.g. on an ffmpeg built without it. Every stderr line
+    // also goes to `on_stderr`.
+    fn run_with_progress(
+        &self,
+        job: &str,
+        cmd: &mut Command,
+        mut on_progress: impl FnMut(&FfmpegProgress),
+        mut on_stderr: impl FnMut(&str),
+    ) -> Result<ExitStatus, String> {
+        let mut progress = FfmpegProgress::default();
+        let mut from_pipe = false;
+        self.run_with_output(job, cmd, |line| match line {
+            OutputLine::Stdout(line) => {
+                if let Some((key, value)) = line.split_once('=') {
+                    from_pipe = true;
+                    if progress.update(key.trim(), value.trim()) {
+                        on_progress(&progress);
+                    }
+                }
+            }
+            OutputLine::Stderr(line) => {
+                on_stderr(line);
+                if !from_pipe {
+                    if let Some(seconds) = parse_ffmpeg_time(line) {
+                        on_progress(&FfmpegProgress { out_time: Some(seconds), ..Default::default() });
+                    }
+                }
+            }
+        })
+    }
+
+    // Like `run`, but hands each line of stdout and stderr to `on_line` as it
+    // arrives
+    fn run_with_output(
+        &self,
+        job: &str,
+        cmd: &mut Command,
+        mut on_line: impl FnMut(&OutputLine),
+    ) -> Result<ExitStatus, String> {
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+        let (tx, rx) = mpsc::channel::<OutputLine>();
+        let mut readers = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(spawn_line_reader(stdout, tx.clone(), OutputLine::Stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(spawn_line_reader(stderr, tx, OutputLine::Stderr));
+        }
+
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let entry = jobs.entry(job.to_string()).or_default();
+            entry.children.insert(token, child);
+            if entry.cancelled {
+                kill_and_reap(entry);
+                return Err(JOB_CANCELLED_ERROR.to_string());
+            }
+        }
+
+        let status = loop {
+            for line in rx.try_iter() {
+                on_line(&line);
+            }
+            {
+                let mut jobs = self.jobs.lock().unwrap();
+                let entry = jobs.entry(job.to_string()).or_default();
+                if entry.cancelled {
+                    kill_and_reap(entry);
+                    return Err(JOB_CANCELLED_ERROR.to_string());
+                }
+                match entry.children.get_mut(&token).map(|c| c.try_wait()) {
+                    Some(Ok(Some(status))) => {
+                        entry.children.remove(&token);
+                        break status;
+                    }
+                    Some(Ok(None)) => {}
+                    Some(Err(e)) => {
+                        if let Some(mut child) = entry.children.remove(&token) {
+                            let _ = child.kill();
+                            let _ = child.wait();
+                        }
+                        return Err(format!("Failed to wait on FFmpeg: {}", e));
+                    }
+                    None => return Err(JOB_CANCELLED_ERROR.to_string()),
+                }
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+
+        // The process has exited; collect whatever it printed last
+        for reader in readers {
+            let _ = reader.join();
+        }
+        for line in rx.try_iter() {
+            on_line(&line);
+        }
+        Ok(status)
+    }
+
+    // Marks the job cancelled and kills its current children. Returns false if
+    // no such job is running.
+    fn cancel(&self, job: &str) -> bool {
+        let mut jobs = self.jobs.lock().unwrap();
+        match jobs.get_mut(job) {
+            Some(entry) => {
+                entry.cancelled = true;
+                kill_and_reap(entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn is_cancelled(&self, job: &str) -> bool {
+        self.jobs.lock().unwrap().get(job).is_some_and(|j| j.cancelled)
+    }
+
+    fn kill_all(&self) {
+        let mut jobs = self.jobs.lock().unwrap();
+        for entry in jobs.values_mut() {
+            entry.cancelled = true;
+            kill_and_reap(entry);
+        }
+    }
+}
+
+fn kill_and_reap(entry: &mut RunningJob) {
+    for (_, mut child) in entry.children.drain() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+// Removes the job from the registry when the command returns, on any path
+struct JobGuard<'a> {
+    registry: &'a ProcessRegistry,
+    job: String,
+}
+
+impl Drop for JobGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut jobs) = self.registry.jobs.lock() {
+            if let Some(mut entry) = jobs.remove(&self.job) {
+                kill_and_reap(&mut entry);
+            }
+        }
+    }
 }
 
-// Command to export video using native FFmpeg
+// Locates the bundled FFmpeg binary
+fn resolve_ffmpeg(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let ffmpeg_path = app_handle
+        .path()
+        .resolve("bin/ffmpeg.exe", tauri::path::BaseDirectory::Resource)
+        .map_err(|e| format!("Failed to resolve FFmpeg path: {}", e))?;
+
+    if !ffmpeg_path.exists() {
+        return Err(format!("FFmpeg binary not found at {:?}", ffmpeg_path));
+    }
+    Ok(ffmpeg_path)
+}
+
+// Parses the `time=HH:MM:SS.xx` field of an ffmpeg stats line into seconds
+fn parse_ffmpeg_time(line: &str) -> Option<f64> {
+    let rest = &line[line.find("time=")? + 5..];
+    let stamp = rest.split_whitespace().next()?;
+    let mut seconds = 0.0;
+    for part in stamp.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+// Command to export video using native FFmpeg. The request is checked here;
+// the render then runs as a background task whose id is returned at once (see
+// tasks.rs). Unchanged clips come from the render cache; `previous_clip_hashes`,
+// the clip_hashes of an earlier export, limits that to the clips it rendered.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn export_video(
     clips: Vec<ClipData>,
     audio_path: String,
     output_path: String,
+    options: Option<ExportOptions>,
+    loop_count: Option<u32>,
+    reverse_timeline: Option<bool>,
+    previous_clip_hashes: Option<Vec<String>>,
+    markers: Option<Vec<markers::Marker>>,
     window: tauri::Window,
     app_handle: tauri::AppHandle,
+    tasks: tauri::State<'_, tasks::TaskStore>,
 ) -> Result<String, String> {
+    let options = options.unwrap_or_default();
+    options.validate()?;
+    let markers = markers.unwrap_or_default();
+    markers::validate(&markers)?;
+    let loop_count = loop_count.unwrap_or(1);
+    if !(1..=MAX_LOOP_COUNT).contains(&loop_count) {
+        return Err(format!("loop_count must be between 1 and {}, got {}", MAX_LOOP_COUNT, loop_count));
+    }
+    validate_clips(&clips)?;
+    let plan = EncodePlan::new(&options, &output_path)?;
+    if !options.subtitle_tracks.is_empty() {
+        subtitles::soft_subtitle_codec(encode::Container::from_path(&output_path))?;
+    }
+    let task_id = tasks.create("export");
+    let request = ExportRequest {
+        clips,
+        audio_path,
+        output_path,
+        options,
+        plan,
+        loop_count,
+        reverse_timeline: reverse_timeline.unwrap_or(false),
+        previous_clip_hashes,
+        markers,
+    };
+    app_handle.state::<ProcessRegistry>().reserve(&task_id)?;
+    let task = task_id.clone();
+    std::thread::spawn(move || {
+        let result = render_export(request, &task, &window, &app_handle);
+        tasks::complete(&app_handle, &task, result);
+    });
+    Ok(task_id)
+}
+
+// A checked export_video request, as handed to its task
+struct ExportRequest {
+    clips: Vec<ClipData>,
+    audio_path: String,
+    output_path: String,
+    options: ExportOptions,
+    plan: EncodePlan,
+    loop_count: u32,
+    reverse_timeline: bool,
+    previous_clip_hashes: Option<Vec<String>>,
+    // Cue markers, in output time
+    markers: Vec<markers::Marker>,
+}
+
+// The body of an export task
+fn render_export(
+    request: ExportRequest,
+    task_id: &str,
+    window: &tauri::Window,
+    app_handle: &tauri::AppHandle,
+) -> Result<ExportResult, String> {
+    let ExportRequest {
+        clips,
+        audio_path,
+        output_path,
+        options,
+        mut plan,
+        loop_count,
+        reverse_timeline,
+        previous_clip_hashes,
+        markers,
+    } = request;
+    let registry = app_handle.state::<ProcessRegistry>();
+    // export_video registered the job before starting this thread, so a
+    // cancel sent right after it returned, or while the export waits for a
+    // slot, isn't lost; the export then ends as soon as it gets one
+    let _job = registry.adopt(task_id);
+    for message in &plan.warnings {
+        emit_warning(window, task_id, message.clone());
+    }
+    let slots = app_handle.state::<tasks::ExportSlots>().0.semaphore();
+    let _slot = tauri::async_runtime::block_on(slots.acquire_owned())
+        .map_err(|e| format!("Failed to wait for an export slot: {}", e))?;
+    if registry.is_cancelled(task_id) {
+        let _ = window.emit("export-cancelled", task_id);
+        return Err(CANCELLED_ERROR.to_string());
+    }
+    app_handle.state::<tasks::TaskStore>().set_phase(task_id, tasks::TaskPhase::Preparing);
+    // Rendering time, not counting the wait for a slot
+    let started = Instant::now();
+    let remote_target = app_handle.state::<RemoteConfig>().0.lock().unwrap().clone();
+
     // 1. Resolve FFmpeg path
-    let ffmpeg_path = app_handle
-        .path()
-        .resolve("bin/ffmpeg.exe", tauri::path::BaseDirectory::Resource)
-        .map_err(|e| format!("Failed to resolve FFmpeg path: {}", e))?;
+    let ffmpeg_path = resolve_ffmpeg(app_handle)?;
+    // A remote final encode runs on a machine that may have no such GPU
+    if let Some(gpu) = options.gpu_pipeline.filter(|_| remote_target.is_none()).map(hwaccel::GpuPipeline::frames) {
+        match gpu.final_video_args() {
+            Some(final_video) if hwaccel::encoder_works(&ffmpeg_path, &final_video) => plan.final_video = final_video,
+            Some(_) => {
+                emit_warning(window, task_id, "The GPU encoder failed to start; encoding on the CPU".to_string())
+            }
+            None => {}
+        }
+    }
+    let clips = tighten_silent_clips(&ffmpeg_path, clips)?;
+    let clips = if reverse_timeline { reverse_timeline_clips(clips) } else { clips };
+    if options.low_latency {
+        // The GOP is counted in frames, at the first clip's rate, which every
+        // clip is conformed to
+        let fps = clips
+            .first()
+            .and_then(|clip| probe::probe(&ffmpeg_path, &clip.file_path).ok())
+            .and_then(|info| info.first_video().and_then(|v| v.fps))
+            .unwrap_or(DEFAULT_ESTIMATE_FPS);
+        plan.set_low_latency(fps, options.threads);
+    }
+    // Checked on the final lengths, after silence trimming
+    validate_transition_fit(&clips)?;
+    let duration = timeline_length(&clips) * loop_count as f64;
+    markers::validate_fit(&markers, duration)?;
+    let overlay_font = if options.timecode_overlay || options.text_overlay.is_some() {
+        overlay::resolve_font(app_handle)
+    } else {
+        None
+    };
 
-    if !ffmpeg_path.exists() {
-        return Err(format!("FFmpeg binary not found at {:?}", ffmpeg_path));
+    // 2. Create temp directory for intermediate clips, unique to this run. The
+    // chosen root is checked again as it may have been unmounted since.
+    let temp_root = app_handle.state::<temp::TempDirConfig>().root();
+    temp::ensure_writable(&temp_root)?;
+    warn_if_low_space(window, task_id, &temp_root, "temp");
+    warn_if_low_space(window, task_id, Path::new(&output_path), "output");
+    let temp_dir = temp::export_dir(&temp_root);
+    std::fs::create_dir_all(&temp_dir).map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let render_cache = render_cache::cache_dir(app_handle);
+
+    let ctx = ExportContext {
+        ffmpeg_path: &ffmpeg_path,
+        temp_dir: &temp_dir,
+        overlay_font: overlay_font.as_deref(),
+        remote_target: remote_target.as_ref(),
+        options: &options,
+        plan: &plan,
+        window,
+        task_id,
+        registry: &registry,
+        loop_count,
+        timeline_length: timeline_length(&clips),
+        warnings: Mutex::default(),
+        faststart: AtomicBool::new(false),
+        render_cache: render_cache.as_deref(),
+        previous_clip_hashes: previous_clip_hashes.as_deref(),
+        markers: &markers,
+    };
+    let result = run_export(&clips, &audio_path, &output_path, &ctx);
+
+    // 6. Cleanup. Any ffmpeg child has been reaped by now, so nothing still
+    // holds the intermediates open.
+    let _ = std::fs::remove_dir_all(&temp_dir);
+
+    if registry.is_cancelled(task_id) {
+        let _ = window.emit("export-cancelled", task_id);
+        return Err(CANCELLED_ERROR.to_string());
+    }
+    let trims = result?;
+
+    let sidecar_format = match options.marker_sidecar {
+        None if !markers.is_empty() && !markers::supports_chapters(encode::Container::from_path(&output_path)) => {
+            Some(markers::SidecarFormat::Json)
+        }
+        format => format.filter(|_| !markers.is_empty()),
+    };
+    let marker_sidecar = match sidecar_format {
+        Some(format) => Some(markers::write_sidecar(format, &output_path, &markers, duration)?),
+        None => None,
+    };
+
+    ctx.emit_progress(ExportProgress::from_percent(100));
+
+    let size_bytes = std::fs::metadata(&output_path)
+        .map_err(|e| format!("Failed to read exported file {}: {}", output_path, e))?
+        .len();
+    let elapsed = started.elapsed().as_secs_f64();
+    let warnings = ctx.warnings.into_inner().unwrap().into_list();
+    Ok(ExportResult {
+        output_path,
+        size_bytes,
+        duration,
+        elapsed,
+        warnings,
+        clip_hashes: trims.clip_hashes,
+        reused_clips: trims.reused,
+        rendered_clips: trims.rendered,
+        marker_sidecar: marker_sidecar.map(|path| path.to_string_lossy().into_owned()),
+    })
+}
+
+// Outcome of a successful export task
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ExportResult {
+    output_path: String,
+    size_bytes: u64,
+    // Length of the exported video, in seconds
+    duration: f64,
+    // Seconds the render took
+    elapsed: f64,
+    // Warnings ffmpeg printed along the way, each already sent as an
+    // export-warning; the same warning is only listed once
+    warnings: Vec<String>,
+    // Render cache key of each clip's trim, in timeline order, to pass back
+    // as previous_clip_hashes when re-exporting. None for a clip that wasn't
+    // cached.
+    clip_hashes: Vec<Option<String>>,
+    // Clips taken from the render cache, and clips trimmed by this export
+    reused_clips: usize,
+    rendered_clips: usize,
+    // Where the markers' sidecar was written, if one was
+    marker_sidecar: Option<String>,
+}
+
+// How run_export came by each clip's intermediate
+#[derive(Default)]
+struct TrimReport {
+    clip_hashes: Vec<Option<String>>,
+    reused: usize,
+    rendered: usize,
+}
+
+// Everything an export run needs besides the timeline itself
+struct ExportContext<'a> {
+    ffmpeg_path: &'a Path,
+    temp_dir: &'a Path,
+    overlay_font: Option<&'a Path>,
+    remote_target: Option<&'a remote::RemoteTarget>,
+    options: &'a ExportOptions,
+    plan: &'a EncodePlan,
+    window: &'a tauri::Window,
+    // Export task this run belongs to; sent with each of its events
+    task_id: &'a str,
+    registry: &'a ProcessRegistry,
+    // Times the whole timeline plays in the output
+    loop_count: u32,
+    // Length of one pass of the timeline, in seconds
+    timeline_length: f64,
+    // Collected from every ffmpeg the export runs
+    warnings: Mutex<warnings::FfmpegWarnings>,
+    // Whether the output gets +faststart; decided once its size is estimated
+    faststart: AtomicBool,
+    // Where trimmed clips are kept between exports; None if there is nowhere
+    render_cache: Option<&'a Path>,
+    // Clip hashes of the export being redone. When given, only these clips
+    // are taken from the render cache and every other one is trimmed afresh.
+    previous_clip_hashes: Option<&'a [String]>,
+    // Written into the output as chapters
+    markers: &'a [markers::Marker],
+}
+
+impl ExportContext<'_> {
+    fn emit_progress(&self, progress: ExportProgress) {
+        self.window.state::<tasks::TaskStore>().set_percent(self.task_id, progress.percent);
+        let progress = ExportProgress { task_id: Some(self.task_id.to_string()), ..progress };
+        let _ = self.window.emit("export-progress", progress);
+    }
+
+    fn set_phase(&self, phase: tasks::TaskPhase) {
+        self.window.state::<tasks::TaskStore>().set_phase(self.task_id, phase);
+    }
+
+    // Muxer flags moving the index to the front, when the output gets them
+    fn movflags(&self) -> Vec<String> {
+        match self.faststart.load(Ordering::Relaxed) {
+            true => vec!["-movflags".to_string(), "+faststart".to_string()],
+            false => Vec::new(),
+        }
     }
 
-    // 2. Create temp directory for intermediate clips
-    let temp_dir = std::env::temp_dir().join("rvj_export");
-    if !temp_dir.exists() {
-        std::fs::create_dir_all(&temp_dir).map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    // Passes on a warning in a line of ffmpeg's stderr the first time it is seen
+    fn note_ffmpeg_line(&self, line: &str) {
+        if let Some(message) = self.warnings.lock().unwrap().add(line) {
+            emit_warning(self.window, self.task_id, message);
+        }
     }
+}
+
+// Assumed for the disk space estimate when a clip doesn't report its frame
+// rate, and when none reports a size (1080p30)
+const DEFAULT_ESTIMATE_FPS: f64 = 30.0;
+const DEFAULT_ESTIMATE_PIXEL_RATE: f64 = 1920.0 * 1080.0 * DEFAULT_ESTIMATE_FPS;
 
-    let mut concat_content = String::new();
-    let total_clips = clips.len();
+// Upper bound for export_video's loop_count
+const MAX_LOOP_COUNT: u32 = 1000;
+
+// Rate the music is resampled to when it is looped, so aloop's size (in
+// samples) is known
+const LOOP_SAMPLE_RATE: u32 = 48000;
+
+// Filters of a trim that would drop an alpha export's alpha plane, wrapped
+// so it is carried around them
+fn keep_alpha_of(options: &ExportOptions, label: &str, filters: impl IntoIterator<Item = String>) -> Option<String> {
+    let filters: Vec<String> = filters.into_iter().collect();
+    if filters.is_empty() {
+        None
+    } else if options.alpha {
+        Some(effects::keep_alpha(label, &filters))
+    } else {
+        Some(filters.join(","))
+    }
+}
+
+fn run_export(
+    clips: &[ClipData],
+    audio_path: &str,
+    output_path: &str,
+    ctx: &ExportContext,
+) -> Result<TrimReport, String> {
+    let ExportContext { ffmpeg_path, temp_dir, overlay_font, remote_target, options, plan, window, registry, loop_count, .. } = *ctx;
+    let mut trimmed_paths = Vec::with_capacity(clips.len());
+    let mut clip_lengths = Vec::with_capacity(clips.len());
+    // Trim progress is weighted by each clip's length on the timeline
+    let total_length: f64 = clips.iter().map(ClipData::timeline_duration).sum();
+    let mut trimmed_length = 0.0;
+    let mut timeline_position = 0.0;
+    let mut first_trim_start = None;
+    let mut trims = TrimReport::default();
+
+    // Every clip is probed up front: the concat needs them all in one format,
+    // and the effects below depend on each clip's frame rate and color
+    let sources = clips
+        .iter()
+        .map(|clip| {
+            let mut info = probe::probe(ffmpeg_path, &clip.file_path)?;
+            if let Some(index) = clip.video_stream_index {
+                info.select_stream(probe::StreamKind::Video, index)
+                    .map_err(|e| format!("Invalid video_stream_index for {}: {}", clip.file_path, e))?;
+            }
+            if let Some(index) = clip.audio_stream_index {
+                info.select_stream(probe::StreamKind::Audio, index)
+                    .map_err(|e| format!("Invalid audio_stream_index for {}: {}", clip.file_path, e))?;
+            }
+            Ok(info)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let conform = conform::Conform::reconcile(&sources, options.keep_clip_audio, options.alpha);
+    if let Some(conform) = &conform {
+        emit_warning(window, ctx.task_id, conform.warning(clips));
+    }
+    let silence = conform::silence_source(&sources);
+    let gpu_clips = gpu_clips(clips, ctx);
+    let watermark = match &options.watermark {
+        Some(watermark) => {
+            let image = probe::probe(ffmpeg_path, &watermark.path)?
+                .first_video()
+                .and_then(|v| Some((v.width?, v.height?)))
+                .ok_or_else(|| format!("Couldn't read the size of watermark image {}", watermark.path))?;
+            Some((watermark, image))
+        }
+        None => None,
+    };
+    let envelopes = if options.audio_reactive.is_empty() {
+        None
+    } else {
+        Some(reactive::Envelopes::analyse(ffmpeg_path, audio_path, ctx.timeline_length)?)
+    };
+    let driven_watermark = options.audio_reactive.iter().any(|m| m.parameter == reactive::ReactiveParameter::Opacity);
+
+    // Refuse to start an export that won't fit, rather than have ffmpeg fail
+    // to write part way through
+    let pixel_rate = sources
+        .iter()
+        .filter_map(|source| {
+            let video = source.first_video()?;
+            let (width, height) = video.display_size()?;
+            Some(width as f64 * height as f64 * video.fps.unwrap_or(DEFAULT_ESTIMATE_FPS))
+        })
+        .reduce(f64::max)
+        .unwrap_or(DEFAULT_ESTIMATE_PIXEL_RATE);
+    let needed = disk::SpaceNeeded::estimate(
+        plan,
+        pixel_rate,
+        total_length,
+        ctx.timeline_length,
+        clips.iter().skip(1).any(|c| c.transition.is_some()),
+        loop_count,
+    );
+    disk::check_space(temp_dir, Path::new(output_path), needed)?;
+    let container = encode::Container::from_path(output_path);
+    ctx.faststart.store(options.moov_placement.faststart(container, needed.output), Ordering::Relaxed);
 
     // 3. Trim each clip
+    ctx.set_phase(tasks::TaskPhase::Trimming);
     for (i, clip) in clips.iter().enumerate() {
-        let trimmed_name = format!("clip_{}.ts", i); // Use TS for easier concatenation
+        let trimmed_name = format!("clip_{}.{}", i, plan.intermediate_ext);
         let trimmed_path = temp_dir.join(&trimmed_name);
         
         let duration = clip.end_time - clip.start_time;
+        // A transition starts this clip while the previous one is still playing
+        if let Some(transition) = clip.transition.filter(|_| i > 0) {
+            timeline_position -= transition.duration;
+        }
         
-        // Trim command: ffmpeg -ss {start} -t {duration} -i {input} -c:v libx264 -preset ultrafast -c:a aac {output}
-        let status = Command::new(&ffmpeg_path)
-            .args([
-                "-y",
-                "-ss", &clip.start_time.to_string(),
-                "-t", &duration.to_string(),
-                "-i", &clip.file_path,
-                "-c:v", "libx264",
-                "-preset", "ultrafast", // Speed up for preview/debug
-                "-c:a", "aac",
-                "-f", "mpegts", // Intermediate format
-                trimmed_path.to_str().ok_or("Invalid path")?,
-            ])
-            .status()
-            .map_err(|e| format!("FFmpeg trim failed: {}", e))?;
+        // Trim command: ffmpeg -ss {start} -t {duration} -i {input} {trim codecs} -f {intermediate} {output}
+        let mut cmd = Command::new(ffmpeg_path);
+        cmd.args(warnings::LOG_LEVEL_ARGS);
+        // The source timestamp the trim starts at, with -copyts. Video and
+        // audio are both moved back by it, so the filters below still see
+        // clip time starting at 0 while the streams keep their offset.
+        let timestamp_base = options
+            .preserve_timestamps
+            .then(|| sources[i].start_time.unwrap_or(0.0) + clip.start_time);
+        if timestamp_base.is_some() {
+            cmd.arg("-copyts");
+        }
+        let gpu = gpu_clips[i];
+        if let Some(gpu) = gpu {
+            cmd.args(gpu.decode_args());
+        }
+        cmd.args([
+            "-y",
+            "-ss", &clip.start_time.to_string(),
+            "-t", &duration.to_string(),
+            "-i", &clip.file_path,
+        ]);
+        // Every trim re-encodes video, so picture effects are drawn here rather
+        // than in the final encode, which may be a stream copy
+        let mut video_filters: Vec<String> =
+            timestamp_base.iter().map(|base| format!("setpts=PTS-{}/TB", base)).collect();
+        let source = sources[i].first_video();
+        let interlaced = source.and_then(|v| v.interlaced).unwrap_or(false);
+        if clip.deinterlace.unwrap_or(interlaced) {
+            // Before anything scales or draws on the picture, which would smear
+            // the combing
+            video_filters.push(effects::DEINTERLACE_FILTER.to_string());
+        }
+        // Exports are always SDR, so HDR clips are tone-mapped unless the user
+        // opted out; SDR clips never are
+        if options.tonemap != Some(false) && source.is_some_and(|v| v.is_hdr) {
+            video_filters.push(effects::tonemap_filter(options.alpha));
+        }
+        // Graded in SDR, after any tone-mapping
+        let grading = effects::color_filter(clip.brightness, clip.contrast, clip.saturation, clip.gamma)
+            .into_iter()
+            .chain(clip.vignette.and_then(effects::vignette_filter));
+        video_filters.extend(keep_alpha_of(options, "grade", grading));
+        // Retimed before strobe and timecode, which work in timeline time
+        if let Some(ramp) = &clip.speed_ramp {
+            video_filters.push(effects::ramp_setpts_filter(ramp, duration));
+        }
+        if let Some(speed) = clip.speed {
+            video_filters.push(format!("setpts=PTS/{}", speed));
+        }
+        // After retiming so the output frame rate is the one conformed to
+        video_filters.extend(conform.as_ref().and_then(|c| c.video_filter(i)));
+        // Sharpened once conformed, so an upscale's softness is crisped up too
+        video_filters.extend(keep_alpha_of(options, "sharpen", clip.sharpen.and_then(effects::sharpen_filter)));
+        // Reversed once retimed, so speed keyframes stay in source time
+        if clip.reverse {
+            video_filters.push("reverse".to_string());
+        }
+        if clip.ping_pong {
+            video_filters.push(effects::PING_PONG_VIDEO_FILTER.to_string());
+        }
+        video_filters.extend(effects::freeze_filter(clip.freeze_start, clip.freeze_end));
+        let clip_length = clip.timeline_duration();
+        if let Some(strobe) = &options.strobe {
+            video_filters.extend(strobe.filter(timeline_position, clip_length));
+        }
+        // Every clip ends up at the conformed size if there is one
+        let frame = conform
+            .as_ref()
+            .and_then(|c| c.frame_size())
+            .or_else(|| source.and_then(|v| Some((v.width?, v.height?))));
+        let commands_path = temp_dir.join(format!("reactive_{}.txt", i));
+        if let Some(envelopes) = &envelopes {
+            video_filters.extend(reactive::clip_filters(
+                &options.audio_reactive,
+                envelopes,
+                timeline_position,
+                clip_length,
+                frame,
+                watermark.map_or(1.0, |(watermark, _)| watermark.opacity),
+                &commands_path,
+            )?);
+        }
+        // A clip without audio gets silence in its place whenever other clips
+        // (or the mix) have audio, so every intermediate has the same streams.
+        // It is the input after the clip and any watermark image.
+        let needs_silence = sources[i].first_audio().is_none()
+            && (options.keep_clip_audio || sources.iter().any(|s| s.first_audio().is_some()));
+        let silence_input = needs_silence.then_some(if watermark.is_some() { 2 } else { 1 });
+        // The streams are mapped explicitly once any is chosen, or ffmpeg
+        // would pick its own for the rest
+        let video_stream = format!("0:v:{}", clip.video_stream_index.unwrap_or(0));
+        let explicit_maps =
+            silence_input.is_some() || clip.audio_stream_index.is_some() || clip.video_stream_index.is_some();
+        // Drawn over everything above, under the timecode
+        let mut late_filters = Vec::new();
+        let textfile = temp_dir.join(format!("text_{}.txt", i));
+        if let Some(subtitles) = &options.subtitles {
+            late_filters.push(subtitles.filter(timeline_position));
+        }
+        if let Some(text) = &options.text_overlay {
+            let filename = Path::new(&clip.file_path)
+                .file_name()
+                .map_or(clip.file_path.clone(), |n| n.to_string_lossy().into_owned());
+            std::fs::write(&textfile, text.expand(&filename, timeline_position))
+                .map_err(|e| format!("Failed to write overlay text: {}", e))?;
+            late_filters.push(text.filter(overlay_font, &textfile));
+        }
+        if options.timecode_overlay {
+            // Drawn last so it stays readable over effects. Counted at the
+            // output's rate: the one clips are conformed to, if they are.
+            let fps = conform.as_ref().map(conform::Conform::fps).or(source.and_then(|v| v.fps)).unwrap_or(30.0);
+            let start = match options.timecode_base {
+                TimecodeBase::Timeline => timeline_position,
+                TimecodeBase::Clip => clip.start_time,
+            };
+            late_filters.push(overlay::timecode_filter(overlay_font, options.timecode_position, start, fps));
+        }
+        if let Some((watermark, image)) = watermark {
+            let frame = frame
+                .ok_or_else(|| format!("Couldn't read the frame size of {} to place the watermark", clip.file_path))?;
+            watermark.check_fit(frame, image)?;
+            let (image_chain, overlay) = watermark.filters(1, frame, image, driven_watermark);
+            if let Some(gpu) = gpu {
+                // The overlay is drawn on the CPU
+                video_filters.insert(0, gpu.download_filter());
+                late_filters.extend(gpu.upload_filter().map(str::to_string));
+            }
+            let base = if video_filters.is_empty() { "null".to_string() } else { video_filters.join(",") };
+            let mut graph = format!("[{}]{}[base];{};{}", video_stream, base, image_chain, overlay);
+            for filter in &late_filters {
+                graph.push(',');
+                graph.push_str(filter);
+            }
+            graph.push_str("[vout]");
+            if driven_watermark {
+                cmd.args(reactive::WATERMARK_INPUT_ARGS);
+            }
+            cmd.args(["-i", &watermark.path]);
+            add_silence_input(&mut cmd, silence_input, duration, &silence);
+            cmd.args(["-filter_complex", &graph, "-map", "[vout]"]);
+        } else {
+            add_silence_input(&mut cmd, silence_input, duration, &silence);
+            video_filters.extend(late_filters);
+            if let Some(gpu) = gpu {
+                video_filters = gpu.wrap_filters(video_filters);
+            }
+            if !video_filters.is_empty() {
+                cmd.args(["-vf", &video_filters.join(",")]);
+            }
+            if explicit_maps {
+                cmd.args(["-map", &video_stream]);
+            }
+        }
+        if let Some(input) = silence_input {
+            cmd.args(["-map", &format!("{}:a", input)]);
+        } else if let Some(index) = clip.audio_stream_index {
+            cmd.args(["-map", &format!("0:a:{}", index)]);
+        } else if watermark.is_some() || explicit_maps {
+            cmd.args(["-map", "0:a?"]);
+        }
+        // Generated silence starts at 0 already
+        let mut audio_filters: Vec<String> = timestamp_base
+            .filter(|_| silence_input.is_none())
+            .iter()
+            .map(|base| format!("asetpts=PTS-{}/TB", base))
+            .collect();
+        if options.keep_clip_audio && clip.speed_ramp.is_some() {
+            // Silence of the retimed length keeps the concat in sync
+            audio_filters.push(format!("volume=0,apad=whole_dur={len},atrim=end={len}", len = clip_length));
+        } else if options.keep_clip_audio {
+            if let Some(speed) = clip.speed {
+                audio_filters.push(effects::audio_speed_filter(speed, clip.preserve_pitch));
+            }
+            if clip.reverse {
+                audio_filters.push("areverse".to_string());
+            }
+            if clip.ping_pong {
+                audio_filters.push(effects::PING_PONG_AUDIO_FILTER.to_string());
+            }
+            audio_filters.extend(effects::freeze_audio_filter(clip.freeze_start, clip.freeze_end));
+            if options.normalize_clip_audio {
+                audio_filters.push(loudnorm_filter(options.clip_loudness_lufs));
+            }
+            if let Some(declick) = declick_filter(clip_length, options.declick_ms) {
+                audio_filters.push(declick);
+            }
+        }
+        audio_filters.extend(conform.as_ref().and_then(|c| c.audio_filter(i)));
+        if !audio_filters.is_empty() {
+            cmd.args(["-af", &audio_filters.join(",")]);
+        }
+        match gpu {
+            Some(gpu) => cmd.args(gpu.trim_video_args()),
+            None => cmd.args(&plan.trim_video),
+        };
+        cmd.args(&plan.trim_audio);
+        if timestamp_base.is_some() {
+            // Frames decoded before the seek point come out slightly negative
+            cmd.args(["-avoid_negative_ts", "make_zero"]);
+        }
+        cmd.args(["-f", plan.intermediate_format]);
+        // Looked up by everything the trim reads and does, before the output
+        // path is added, as that differs every run
+        let mut inputs = vec![Path::new(&clip.file_path)];
+        inputs.extend(watermark.map(|(watermark, _)| Path::new(&watermark.path)));
+        inputs.extend(options.subtitles.as_ref().map(|subtitles| Path::new(&subtitles.path)));
+        inputs.extend(options.text_overlay.as_ref().map(|_| textfile.as_path()));
+        inputs.extend(envelopes.as_ref().map(|_| commands_path.as_path()));
+        let key = ctx.render_cache.and_then(|_| render_cache::key(&cmd, temp_dir, &inputs));
+        let cache_entry = ctx
+            .render_cache
+            .zip(key.as_deref())
+            .map(|(dir, key)| render_cache::entry_path(dir, key, plan.intermediate_ext));
+        let reusable =
+            key.as_ref().is_some_and(|key| ctx.previous_clip_hashes.is_none_or(|hashes| hashes.contains(key)));
+        cmd.arg(trimmed_path.to_str().ok_or("Invalid path")?);
+        let reused = reusable && cache_entry.as_deref().is_some_and(|entry| render_cache::fetch(entry, &trimmed_path));
+        trims.clip_hashes.push(key);
+        if reused {
+            trims.reused += 1;
+        } else {
+            trims.rendered += 1;
+            let status = registry
+                .run_with_stderr(ctx.task_id, &mut cmd, |line| ctx.note_ffmpeg_line(line))
+                .map_err(|e| format!("FFmpeg trim failed: {}", e))?;
 
-        if !status.success() {
-            return Err(format!("FFmpeg trim exited with error for clip {}", i));
+            if !status.success() {
+                return Err(format!("FFmpeg trim exited with error for clip {}", i));
+            }
+            if let Some(entry) = &cache_entry {
+                render_cache::store(entry, &trimmed_path);
+            }
+        }
+        if timestamp_base.is_some() {
+            check_trim_start(ctx, &trimmed_path, i, &mut first_trim_start);
         }
 
-        concat_content.push_str(&format!("file '{}'\n", trimmed_path.to_str().unwrap().replace('\\', "/")));
+        trimmed_paths.push(trimmed_path);
+        clip_lengths.push(clip_length);
+        timeline_position += clip_length;
+        trimmed_length += clip_length;
         
         // Emit progress
-        let progress = ((i + 1) as f64 / (total_clips + 1) as f64 * 50.0) as u8;
-        window.emit("export-progress", ExportProgress { percent: progress }).unwrap();
+        let progress = (trimmed_length / total_length * 50.0) as u8;
+        ctx.emit_progress(ExportProgress::from_percent(progress));
+    }
+
+    if clips.iter().skip(1).any(|c| c.transition.is_some()) {
+        ctx.set_phase(tasks::TaskPhase::Transitions);
+        trimmed_paths = vec![join_transitions(clips, &clip_lengths, &trimmed_paths, ctx)?];
     }
 
-    // 4. Create concat file
+    // The final encode covers the second half of the progress bar
+    let total_duration = ctx.timeline_length * loop_count as f64;
+    // Every clip plays at the first clip's rate once conformed
+    let total_frames = sources
+        .first()
+        .and_then(|s| s.first_video())
+        .and_then(|v| v.fps)
+        .map(|fps| (total_duration * fps).round() as u64);
+    let report = |progress: &FfmpegProgress| {
+        let fraction = progress.out_time.map_or(0.0, |t| (t / total_duration).min(1.0));
+        // A remote encode writes its output on the other machine, where only
+        // ffmpeg's own count of the bytes is available
+        let bytes_written = match remote_target {
+            Some(_) => progress.total_size,
+            None => std::fs::metadata(output_path).ok().map(|m| m.len()),
+        };
+        ctx.emit_progress(ExportProgress {
+            task_id: None,
+            percent: (50.0 + fraction * 49.0) as u8,
+            frame: progress.frame,
+            time: progress.out_time,
+            fps: progress.fps,
+            speed: progress.speed,
+            frames_done: progress.frame,
+            total_frames,
+            bytes_written,
+        });
+    };
+
+    // Grabbed now, while the intermediates are still around. A container
+    // without room for it gets the export anyway.
+    let cover_image = match &options.cover {
+        Some(_) if !cover::supports(encode::Container::from_path(output_path)) => {
+            emit_warning(ctx.window, ctx.task_id, "This format can't hold cover art; exported without it".to_string());
+            None
+        }
+        Some(cover) => Some(cover::prepare(cover, &clip_lengths, &trimmed_paths, ctx)?),
+        None => None,
+    };
+
+    ctx.set_phase(tasks::TaskPhase::Encoding);
+
+    // Offload the final encode when a remote target is configured
+    match remote_target {
+        Some(target) => remote::run_final_concat(target, &trimmed_paths, audio_path, output_path, ctx, report)?,
+        None => final_concat(&trimmed_paths, audio_path, output_path, ctx, report)?,
+    }
+
+    if let Some(image) = cover_image {
+        cover::embed(ctx, output_path, &image)?;
+    }
+    if !ctx.markers.is_empty() {
+        if markers::supports_chapters(container) {
+            markers::embed_chapters(ctx, output_path, ctx.markers, ctx.timeline_length * loop_count as f64)?;
+        } else {
+            let warning = "This format can't hold chapters; markers are in the sidecar".to_string();
+            emit_warning(ctx.window, ctx.task_id, warning);
+        }
+    }
+    Ok(trims)
+}
+
+// Joins the trimmed clips (looped) and lays the music under them
+fn final_concat(
+    trimmed_paths: &[PathBuf],
+    audio_path: &str,
+    output_path: &str,
+    ctx: &ExportContext,
+    report: impl FnMut(&FfmpegProgress),
+) -> Result<(), String> {
+    let ExportContext { ffmpeg_path, temp_dir, options, registry, loop_count, .. } = *ctx;
+
+    // 4. Create concat file, listing the timeline once per loop
+    let concat_content: String = trimmed_paths
+        .iter()
+        .map(|path| format!("file '{}'\n", path.to_str().unwrap().replace('\\', "/")))
+        .collect::<String>()
+        .repeat(loop_count as usize);
     let concat_file_path = temp_dir.join("concat.txt");
     let mut concat_file = StdFile::create(&concat_file_path).map_err(|e| format!("Failed to create concat file: {}", e))?;
     concat_file.write_all(concat_content.as_bytes()).map_err(|e| format!("Failed to write concat file: {}", e))?;
 
     // 5. Final concatenation with audio
-    // Command: ffmpeg -f concat -safe 0 -i concat.txt -i audio.mp3 -map 0:v -map 1:a -c:v copy -shortest output.mp4
-    let mut cmd = Command::new(&ffmpeg_path);
+    let mut cmd = Command::new(ffmpeg_path);
+    let subtitle_paths: Vec<String> = options.subtitle_tracks.iter().map(|t| t.path.clone()).collect();
+    cmd.args(final_concat_args(concat_file_path.to_str().unwrap(), audio_path, &subtitle_paths, output_path, ctx));
+
+    let status = registry
+        .run_with_progress(ctx.task_id, &mut cmd, report, |line| ctx.note_ffmpeg_line(line))
+        .map_err(|e| format!("FFmpeg final concat failed: {}", e))?;
+
+    if !status.success() {
+        return Err("FFmpeg final concat exited with error".to_string());
+    }
+
+    Ok(())
+}
+
+// The GPU each clip is trimmed on, if any. The whole chain is tried on every
+// source first, and one that fails (a codec the GPU can't decode, say) is
+// trimmed on the CPU instead, with a warning.
+fn gpu_clips(clips: &[ClipData], ctx: &ExportContext) -> Vec<Option<hwaccel::GpuFrames>> {
+    let Some(gpu) = ctx.options.gpu_pipeline.map(hwaccel::GpuPipeline::frames) else {
+        return vec![None; clips.len()];
+    };
+    let mut works: HashMap<&str, bool> = HashMap::new();
+    let gpu_clips: Vec<_> = clips
+        .iter()
+        .map(|clip| {
+            let ok = *works
+                .entry(&clip.file_path)
+                .or_insert_with(|| hwaccel::chain_works(ctx.ffmpeg_path, gpu, &clip.file_path, clip.start_time));
+            ok.then_some(gpu)
+        })
+        .collect();
+    let failed = gpu_clips.iter().filter(|gpu| gpu.is_none()).count();
+    if failed > 0 {
+        emit_warning(
+            ctx.window,
+            ctx.task_id,
+            format!("{} of {} clips couldn't be processed on the GPU; trimming those on the CPU", failed, clips.len()),
+        );
+    }
+    gpu_clips
+}
+
+// Adds generated silence of the clip's source length as an input, when the
+// clip needs it. Filtered like real clip audio, it comes out just as long.
+fn add_silence_input(cmd: &mut Command, input: Option<usize>, duration: f64, source: &str) {
+    if input.is_some() {
+        cmd.args(["-f", "lavfi", "-t", &duration.to_string(), "-i", source]);
+    }
+}
+
+// How far a trimmed clip's start may be from the first one's before it is
+// reported; about a frame
+const TRIM_START_TOLERANCE: f64 = 0.04;
+
+// Checks a -copyts trim starts where the first clip's did. The muxer may add
+// a fixed delay (MPEG-TS does), so the first clip's start, rather than 0, is
+// what the others should match. A clip that doesn't is only reported, as the
+// export may still be fine.
+fn check_trim_start(ctx: &ExportContext, trimmed_path: &Path, clip: usize, first_start: &mut Option<f64>) {
+    let Some(path) = trimmed_path.to_str() else { return };
+    let Some(start) = probe::probe(ctx.ffmpeg_path, path).ok().and_then(|info| info.start_time) else {
+        return;
+    };
+    let expected = *first_start.get_or_insert(start);
+    if (start - expected).abs() > TRIM_START_TOLERANCE {
+        emit_warning(
+            ctx.window,
+            ctx.task_id,
+            format!(
+                "Clip {} starts {:.3}s off after trimming; it may play slightly out of sync",
+                clip + 1,
+                start - expected
+            ),
+        );
+    }
+}
+
+// Merges the trimmed clips into one intermediate with each clip's transition
+// rendered, so the final encode (local or remote) only sees a single segment
+fn join_transitions(
+    clips: &[ClipData],
+    lengths: &[f64],
+    trimmed_paths: &[PathBuf],
+    ctx: &ExportContext,
+) -> Result<PathBuf, String> {
+    let joined_path = ctx.temp_dir.join(format!("joined.{}", ctx.plan.intermediate_ext));
+    let with_audio = ctx.options.keep_clip_audio;
+    let transitions: Vec<_> = clips.iter().map(|c| c.transition).collect();
+
+    let mut cmd = Command::new(ctx.ffmpeg_path);
+    cmd.args(warnings::LOG_LEVEL_ARGS);
+    cmd.arg("-y");
+    for path in trimmed_paths {
+        cmd.arg("-i").arg(path);
+    }
     cmd.args([
+        "-filter_complex", &transitions::join_filter(lengths, &transitions, with_audio),
+        "-map", "[vout]",
+    ]);
+    if with_audio {
+        cmd.args(["-map", "[aout]"]);
+        cmd.args(&ctx.plan.trim_audio);
+    }
+    cmd.args(&ctx.plan.trim_video);
+    cmd.args(["-f", ctx.plan.intermediate_format, joined_path.to_str().ok_or("Invalid path")?]);
+
+    let status = ctx
+        .registry
+        .run_with_stderr(ctx.task_id, &mut cmd, |line| ctx.note_ffmpeg_line(line))
+        .map_err(|e| format!("FFmpeg transitions failed: {}", e))?;
+    if !status.success() {
+        return Err("FFmpeg transitions exited with error".to_string());
+    }
+    Ok(joined_path)
+}
+
+// Arguments for the final concat + audio mux. Shared with the remote path,
+// which runs the same encode against its own copies of the inputs.
+// Command: ffmpeg -f concat -safe 0 -i concat.txt -i audio.mp3 -map 0:v -map 1:a -c:v copy -shortest output.mp4
+// `subtitle_paths` are options.subtitle_tracks' files, wherever the encode
+// can read them.
+fn final_concat_args(
+    concat_path: &str,
+    audio_path: &str,
+    subtitle_paths: &[String],
+    output_path: &str,
+    ctx: &ExportContext,
+) -> Vec<String> {
+    let mut args: Vec<String> = [
+        warnings::LOG_LEVEL_ARGS[0],
+        warnings::LOG_LEVEL_ARGS[1],
         "-y",
+        // key=value progress on stdout; see ProcessRegistry::run_with_progress
+        "-progress", "pipe:1",
         "-f", "concat",
         "-safe", "0",
-        "-i", concat_file_path.to_str().unwrap(),
-        "-i", &audio_path,
-        "-map", "0:v",
-        "-map", "1:a",
-        "-c:v", "libx264", // Recode to ensure compatibility, or "copy" if same
-        "-preset", "medium",
-        "-c:a", "aac",
-        "-shortest", // Match length to shortest (useful if looping/padding logic is needed later)
-        &output_path,
+        "-i", concat_path,
+        "-i", audio_path,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    // Inputs 2.. are the subtitle tracks, which come before any output option
+    for path in subtitle_paths {
+        args.extend(["-i".to_string(), path.clone()]);
+    }
+    args.extend(["-map".to_string(), "0:v".to_string()]);
+
+    // When looping, the music under one pass of the timeline is repeated with
+    // it (padded with silence if the track is shorter), so every pass sounds
+    // the same. Kept clip audio loops along with the concat list.
+    let mut graph = Vec::new();
+    let music = if ctx.loop_count > 1 {
+        let samples = (ctx.timeline_length * LOOP_SAMPLE_RATE as f64).round() as u64;
+        graph.push(format!(
+            "[1:a]aresample={rate},atrim=end={len},apad=whole_dur={len},aloop=loop={}:size={}[music]",
+            ctx.loop_count - 1,
+            samples,
+            rate = LOOP_SAMPLE_RATE,
+            len = ctx.timeline_length
+        ));
+        "[music]"
+    } else {
+        "[1:a]"
+    };
+    if ctx.options.keep_clip_audio {
+        // Clip audio runs the length of the video, so it decides the mix length
+        graph.push(format!("[0:a]{}amix=inputs=2:duration=first:normalize=0[aout]", music));
+    } else if ctx.loop_count > 1 {
+        graph.push(format!("{}anull[aout]", music));
+    }
+    if graph.is_empty() {
+        args.extend(["-map".to_string(), "1:a".to_string()]);
+    } else {
+        args.extend([
+            "-filter_complex".to_string(),
+            graph.join(";"),
+            "-map".to_string(),
+            "[aout]".to_string(),
+        ]);
+    }
+
+    args.extend(ctx.plan.final_video.iter().cloned());
+    args.extend(ctx.plan.final_audio.iter().cloned());
+    args.extend(subtitles::track_args(&ctx.options.subtitle_tracks, 2, encode::Container::from_path(output_path)));
+    args.extend(ctx.movflags());
+    args.extend([
+        "-shortest".to_string(), // Match length to shortest (useful if looping/padding logic is needed later)
+        output_path.to_string(),
     ]);
+    args
+}
+
+// Containers export_audio_only can write, picked from the output extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AudioFormat {
+    Mp3,
+    Wav,
+    Flac,
+}
 
-    let status = cmd.status().map_err(|e| format!("FFmpeg final concat failed: {}", e))?;
+impl AudioFormat {
+    fn from_path(path: &str) -> Result<Self, String> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("mp3") => Ok(AudioFormat::Mp3),
+            Some("wav") => Ok(AudioFormat::Wav),
+            Some("flac") => Ok(AudioFormat::Flac),
+            _ => Err(format!("Unsupported audio output '{}', expected .mp3, .wav or .flac", path)),
+        }
+    }
 
-    if !status.success() {
-        return Err("FFmpeg final concat exited with error".to_string());
+    fn codec_args(self) -> &'static [&'static str] {
+        match self {
+            AudioFormat::Mp3 => &["-c:a", "libmp3lame", "-q:a", "2"],
+            AudioFormat::Wav => &["-c:a", "pcm_s16le"],
+            AudioFormat::Flac => &["-c:a", "flac"],
+        }
     }
+}
 
-    // 6. Cleanup
-    let _ = std::fs::remove_dir_all(&temp_dir);
+// Filter graph for the timeline's audio, labelled [aout]. Inputs 0..n are the
+// trimmed clips, de-clicked and concatenated in order; `external_input` is the
+// index of a separate track mixed on top for the length of the clips.
+fn timeline_audio_filter(clips: &[ClipData], declick_ms: f64, external_input: Option<usize>) -> String {
+    let mut graph = String::new();
+    for (i, clip) in clips.iter().enumerate() {
+        let filter = declick_filter(clip.end_time - clip.start_time, declick_ms)
+            .unwrap_or_else(|| "anull".to_string());
+        let stream = clip.audio_stream_index.map_or(format!("{}:a", i), |n| format!("{}:a:{}", i, n));
+        graph.push_str(&format!("[{}]{}[a{}];", stream, filter, i));
+    }
+    for i in 0..clips.len() {
+        graph.push_str(&format!("[a{}]", i));
+    }
+    graph.push_str(&format!("concat=n={}:v=0:a=1", clips.len()));
+    match external_input {
+        Some(index) => graph.push_str(&format!(
+            "[clips];[clips][{}:a]amix=inputs=2:duration=first:normalize=0[aout]",
+            index
+        )),
+        None => graph.push_str("[aout]"),
+    }
+    graph
+}
+
+// Command to export only the timeline's soundtrack: the clips' own audio,
+// concatenated, with the external track mixed in when one is given. With
+// `include_clip_audio` false the external track is cut to the timeline length.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn export_audio_only(
+    clips: Vec<ClipData>,
+    audio_path: Option<String>,
+    include_clip_audio: Option<bool>,
+    declick_ms: Option<f64>,
+    output_path: String,
+    window: tauri::Window,
+    app_handle: tauri::AppHandle,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<String, String> {
+    let include_clip_audio = include_clip_audio.unwrap_or(true);
+    let declick_ms = declick_ms.unwrap_or(DEFAULT_DECLICK_MS);
+    validate_declick_ms(declick_ms)?;
+    let format = AudioFormat::from_path(&output_path)?;
+    if clips.is_empty() {
+        return Err("No clips to export".to_string());
+    }
+    if !include_clip_audio && audio_path.is_none() {
+        return Err("Nothing to export: clip audio is excluded and no audio track was given".to_string());
+    }
+
+    let _job = registry.begin(EXPORT_JOB)?;
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    let clips = tighten_silent_clips(&ffmpeg_path, clips)?;
+    let total_duration: f64 = clips.iter().map(|c| c.end_time - c.start_time).sum();
+
+    let mut cmd = Command::new(&ffmpeg_path);
+    cmd.arg("-y");
+    if include_clip_audio {
+        for clip in &clips {
+            cmd.args([
+                "-ss", &clip.start_time.to_string(),
+                "-t", &(clip.end_time - clip.start_time).to_string(),
+                "-i", &clip.file_path,
+            ]);
+        }
+    }
+    if let Some(audio_path) = &audio_path {
+        cmd.args(["-i", audio_path]);
+    }
+    if include_clip_audio {
+        let external_input = audio_path.as_ref().map(|_| clips.len());
+        cmd.args(["-filter_complex", &timeline_audio_filter(&clips, declick_ms, external_input)]);
+        cmd.args(["-map", "[aout]"]);
+    } else {
+        cmd.args(["-map", "0:a", "-t", &total_duration.to_string()]);
+    }
+    cmd.arg("-vn");
+    cmd.args(format.codec_args());
+    cmd.arg(&output_path);
 
-    window.emit("export-progress", ExportProgress { percent: 100 }).unwrap();
-    
+    let status = registry.run_with_stderr(EXPORT_JOB, &mut cmd, |line| {
+        if let Some(seconds) = parse_ffmpeg_time(line) {
+            let progress = (seconds / total_duration).min(1.0) * 99.0;
+            let _ = window.emit("export-progress", ExportProgress::from_percent(progress as u8));
+        }
+    });
+
+    if registry.is_cancelled(EXPORT_JOB) {
+        let _ = std::fs::remove_file(&output_path);
+        window.emit("export-cancelled", ()).unwrap();
+        return Err(CANCELLED_ERROR.to_string());
+    }
+    let status = status.map_err(|e| format!("FFmpeg audio export failed: {}", e))?;
+    if !status.success() {
+        return Err("FFmpeg audio export exited with error".to_string());
+    }
+
+    window.emit("export-progress", ExportProgress::from_percent(100)).unwrap();
     Ok(output_path)
 }
 
+// Cancels the audio-only export and every export task. Returns false if none
+// was running.
+fn cancel_all_exports(registry: &ProcessRegistry, tasks: &tasks::TaskStore) -> bool {
+    let mut cancelled = registry.cancel(EXPORT_JOB);
+    for task_id in tasks.running_ids() {
+        cancelled |= registry.cancel(&task_id);
+    }
+    cancelled
+}
+
+// Command to cancel the export task `task_id`, leaving any others running, or
+// with None every running export. Kills the in-flight ffmpeg processes; the
+// export then cleans up its temp dir and emits `export-cancelled`.
+#[tauri::command]
+async fn cancel_export(
+    task_id: Option<String>,
+    registry: tauri::State<'_, ProcessRegistry>,
+    tasks: tauri::State<'_, tasks::TaskStore>,
+) -> Result<bool, String> {
+    match task_id {
+        Some(task_id) => Ok(registry.cancel(&task_id)),
+        None => Ok(cancel_all_exports(&registry, &tasks)),
+    }
+}
+
+// Command to cancel a proxy or thumbnail batch by the id from `job-started`.
+// Kills its ffmpeg processes; the job removes its partial output itself.
+#[tauri::command]
+async fn cancel_job(job_id: String, registry: tauri::State<'_, ProcessRegistry>) -> Result<bool, String> {
+    Ok(registry.cancel(&job_id))
+}
+
+// Command to change the global cancel-export shortcut (e.g. "Ctrl+Alt+X")
+#[tauri::command]
+async fn set_cancel_shortcut(
+    shortcut: String,
+    app_handle: tauri::AppHandle,
+    current: tauri::State<'_, CancelShortcut>,
+) -> Result<(), String> {
+    let new_shortcut = Shortcut::from_str(&shortcut)
+        .map_err(|e| format!("Invalid shortcut '{}': {}", shortcut, e))?;
+
+    let mut current = current.0.lock().unwrap();
+    let global_shortcut = app_handle.global_shortcut();
+    // The old shortcut stays registered until the new one is, so a shortcut
+    // the OS or another app holds leaves the old one working
+    let old_shortcut = Shortcut::from_str(&current.shortcut).ok().filter(|_| current.error.is_none());
+    if old_shortcut != Some(new_shortcut) {
+        global_shortcut
+            .register(new_shortcut)
+            .map_err(|e| format!("Failed to register shortcut '{}': {}", shortcut, e))?;
+        if let Some(old_shortcut) = old_shortcut {
+            let _ = global_shortcut.unregister(old_shortcut);
+        }
+    }
+
+    *current = CancelShortcutState { shortcut, error: None };
+    Ok(())
+}
+
+// Command to read the current global cancel-export shortcut, with the error
+// if it isn't registered
+#[tauri::command]
+async fn get_cancel_shortcut(current: tauri::State<'_, CancelShortcut>) -> Result<CancelShortcutState, String> {
+    Ok(current.0.lock().unwrap().clone())
+}
+
 // Command to validate file access
 #[tauri::command]
 async fn validate_file_path(path: String) -> Result<bool, String> {
@@ -127,97 +1961,570 @@ async fn validate_file_path(path: String) -> Result<bool, String> {
     Ok(file_path.exists())
 }
 
-// Command to generate video thumbnail using FFmpeg
+// What validate_file_paths found at one path
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PathStatus {
+    exists: bool,
+    is_dir: bool,
+}
+
+// Command to validate many paths in one call, e.g. a few hundred dropped
+// files or a project being opened. Results are in the order of `paths`.
+#[tauri::command]
+async fn validate_file_paths(paths: Vec<String>) -> Result<Vec<PathStatus>, String> {
+    Ok(paths
+        .iter()
+        .map(|path| match std::fs::metadata(path) {
+            Ok(metadata) => PathStatus { exists: true, is_dir: metadata.is_dir() },
+            Err(_) => PathStatus { exists: false, is_dir: false },
+        })
+        .collect())
+}
+
+// Command to show `path` in the OS file manager, e.g. the output after an
+// export. Files are selected in their folder where the platform supports it;
+// on Linux, which has no common way to do that, the folder is opened.
+#[tauri::command]
+async fn reveal_in_explorer(path: String, app_handle: tauri::AppHandle) -> Result<(), String> {
+    let target = PathBuf::from(&path);
+    if !target.exists() {
+        return Err(format!("File not found: {}", path));
+    }
+    let shell = app_handle.shell();
+    let command = if cfg!(target_os = "windows") {
+        if target.is_dir() {
+            shell.command("explorer").arg(&target)
+        } else {
+            shell.command("explorer").args([OsStr::new("/select,"), target.as_os_str()])
+        }
+    } else if cfg!(target_os = "macos") {
+        shell.command("open").arg("-R").arg(&target)
+    } else {
+        let folder = if target.is_dir() { target.as_path() } else { target.parent().unwrap_or(Path::new("/")) };
+        shell.command("xdg-open").arg(folder)
+    };
+    // Explorer exits with an error status even when it worked, so only a
+    // failure to start is reported
+    command.spawn().map_err(|e| format!("Failed to open the file manager: {}", e))?;
+    Ok(())
+}
+
+// Default number of thumbnail ffmpeg processes allowed at once
+const DEFAULT_THUMBNAIL_WORKERS: usize = 4;
+
+// Bounded pool shared by the thumbnail commands, so populating a big media bin
+// doesn't start one ffmpeg per file at once
+struct ThumbnailPool(tasks::Slots);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ThumbnailRequest {
+    file_path: String,
+    timestamp: f64,
+}
+
+// One entry per request of a batch; a failed file doesn't fail the batch
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ThumbnailResult {
+    data_url: Option<String>,
+    error: Option<String>,
+}
+
+// Image format of a thumbnail. WebP and JPEG are several times smaller than
+// PNG, which adds up over IPC and in memory for a large media bin.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+enum ThumbnailFormat {
+    #[default]
+    Png,
+    // Needs an ffmpeg with libwebp
+    Webp,
+    Jpeg,
+}
+
+impl ThumbnailFormat {
+    fn mime_type(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Png => "image/png",
+            ThumbnailFormat::Webp => "image/webp",
+            ThumbnailFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Png => "png",
+            ThumbnailFormat::Webp => "webp",
+            ThumbnailFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+// Upper end of generate_thumbnail's `quality`
+const MAX_THUMBNAIL_QUALITY: u32 = 100;
+
+// How a thumbnail is encoded: its format and, for the lossy ones, a quality
+// from 1 (smallest) to 100 (best), None for the format's default
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct ThumbnailEncoding {
+    format: ThumbnailFormat,
+    quality: Option<u32>,
+}
+
+impl ThumbnailEncoding {
+    fn new(format: ThumbnailFormat, quality: Option<u32>) -> Result<Self, String> {
+        match quality {
+            Some(_) if format == ThumbnailFormat::Png => {
+                return Err("PNG thumbnails are lossless; quality applies to jpeg and webp".to_string())
+            }
+            Some(q) if !(1..=MAX_THUMBNAIL_QUALITY).contains(&q) => {
+                return Err(format!("Thumbnail quality must be between 1 and {}, got {}", MAX_THUMBNAIL_QUALITY, q))
+            }
+            _ => {}
+        }
+        Ok(ThumbnailEncoding { format, quality })
+    }
+
+    fn codec_args(self) -> Vec<String> {
+        match self.format {
+            ThumbnailFormat::Png => vec!["-vcodec".into(), "png".into()],
+            ThumbnailFormat::Webp => {
+                vec!["-vcodec".into(), "libwebp".into(), "-quality".into(), self.quality.unwrap_or(75).to_string()]
+            }
+            // mjpeg's default bitrate leaves a single small frame blocky, so
+            // even the default sets a qscale
+            ThumbnailFormat::Jpeg => {
+                vec!["-vcodec".into(), "mjpeg".into(), "-q:v".into(), self.quality.map_or(3, jpeg_qscale).to_string()]
+            }
+        }
+    }
+}
+
+// Maps quality 1..=100 onto mjpeg's qscale, 31 (worst) to 2 (best). qscale 1
+// is left out: barely better than 2, at a much larger size.
+fn jpeg_qscale(quality: u32) -> u32 {
+    31 - (quality - 1) * 29 / (MAX_THUMBNAIL_QUALITY - 1)
+}
+
+// Extracts a single frame to `output`, "-" being stdout
+fn thumbnail_command(
+    ffmpeg_path: &Path,
+    file_path: &str,
+    timestamp: f64,
+    output: &str,
+    encoding: ThumbnailEncoding,
+) -> Command {
+    let scale = "scale=160:90:force_original_aspect_ratio=decrease";
+    frame_command(ffmpeg_path, file_path, timestamp, output, scale, &encoding.codec_args())
+}
+
+// Extracts the frame at `timestamp` to `output`, scaled by `scale` and
+// encoded with `codec_args`
+pub(crate) fn frame_command(
+    ffmpeg_path: &Path,
+    file_path: &str,
+    timestamp: f64,
+    output: &str,
+    scale: &str,
+    codec_args: &[impl AsRef<std::ffi::OsStr>],
+) -> Command {
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.args([
+        "-y",
+        "-ss", &timestamp.to_string(),
+        "-i", file_path,
+        "-vframes", "1",
+        "-f", "image2pipe",
+    ]);
+    cmd.args(codec_args);
+    cmd.args(["-vf", scale, output]);
+    cmd
+}
+
+fn image_data_url(image: &[u8], format: ThumbnailFormat) -> String {
+    use base64::Engine;
+    format!(
+        "data:{};base64,{}",
+        format.mime_type(),
+        base64::engine::general_purpose::STANDARD.encode(image)
+    )
+}
+
+// Extracts a single frame
+fn extract_thumbnail(
+    ffmpeg_path: &Path,
+    file_path: &str,
+    timestamp: f64,
+    encoding: ThumbnailEncoding,
+) -> Result<Vec<u8>, String> {
+    let output = thumbnail_command(ffmpeg_path, file_path, timestamp, "-", encoding)
+        .output()
+        .map_err(|e| format!("FFmpeg thumbnail extraction failed: {}", e))?;
+
+    if !output.status.success() {
+        return Err("FFmpeg failed to extract thumbnail".to_string());
+    }
+    Ok(output.stdout)
+}
+
+// The thumbnail as a base64 data URL, from the disk cache when it has a
+// current one, otherwise from `extract` (and then cached)
+fn cached_thumbnail(
+    cache_dir: Option<&Path>,
+    file_path: &str,
+    timestamp: f64,
+    encoding: ThumbnailEncoding,
+    extract: impl FnOnce() -> Result<Vec<u8>, String>,
+) -> Result<String, String> {
+    let entry = cache_dir.and_then(|dir| {
+        thumbnail_cache::entry_path(dir, file_path, timestamp, encoding.format.extension(), encoding.quality)
+    });
+    if let Some(image) = entry.as_deref().and_then(thumbnail_cache::load) {
+        return Ok(image_data_url(&image, encoding.format));
+    }
+    let image = extract()?;
+    if let Some(entry) = &entry {
+        thumbnail_cache::store(entry, &image);
+    }
+    Ok(image_data_url(&image, encoding.format))
+}
+
+// Like extract_thumbnail, but as part of `job` so cancel_job can stop it.
+// Registry children don't hand back stdout, so the frame goes through
+// `temp_path`, which is removed either way.
+fn extract_thumbnail_for_job(
+    registry: &ProcessRegistry,
+    job: &str,
+    ffmpeg_path: &Path,
+    request: &ThumbnailRequest,
+    temp_path: &Path,
+) -> Result<Vec<u8>, String> {
+    let output = temp_path.to_str().ok_or("Invalid path")?;
+    let encoding = ThumbnailEncoding::default();
+    let mut cmd = thumbnail_command(ffmpeg_path, &request.file_path, request.timestamp, output, encoding);
+    let result = match registry.run(job, &mut cmd) {
+        Ok(status) if status.success() => {
+            std::fs::read(temp_path).map_err(|e| format!("Failed to read thumbnail: {}", e))
+        }
+        Ok(_) => Err("FFmpeg failed to extract thumbnail".to_string()),
+        Err(e) => Err(format!("FFmpeg thumbnail extraction failed: {}", e)),
+    };
+    let _ = std::fs::remove_file(temp_path);
+    result
+}
+
+// Runs one thumbnail extraction once the pool has a free worker
+async fn pooled_thumbnail(
+    semaphore: Arc<Semaphore>,
+    extract: impl FnOnce() -> Result<String, String> + Send + 'static,
+) -> Result<String, String> {
+    let _permit = semaphore
+        .acquire_owned()
+        .await
+        .map_err(|e| format!("Thumbnail pool closed: {}", e))?;
+    tauri::async_runtime::spawn_blocking(extract)
+        .await
+        .map_err(|e| format!("Thumbnail worker failed: {}", e))?
+}
+
+// Command to generate video thumbnail using FFmpeg, as a data URL of
+// `format` (PNG by default). `quality` (1-100) trades the detail of a jpeg or
+// webp thumbnail for size, e.g. for very dense grids.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn generate_thumbnail(
     file_path: String,
     timestamp: f64,
+    format: Option<ThumbnailFormat>,
+    quality: Option<u32>,
+    app_handle: tauri::AppHandle,
+    pool: tauri::State<'_, ThumbnailPool>,
+    capabilities: tauri::State<'_, capabilities::CapabilitiesCache>,
+) -> Result<String, String> {
+    let encoding = ThumbnailEncoding::new(format.unwrap_or_default(), quality)?;
+    if encoding.format == ThumbnailFormat::Webp {
+        capabilities::require_encoder(&app_handle, &capabilities, "libwebp", "WebP thumbnails")?;
+    }
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    let cache_dir = thumbnail_cache::cache_dir(&app_handle);
+    pooled_thumbnail(pool.0.semaphore(), move || {
+        cached_thumbnail(cache_dir.as_deref(), &file_path, timestamp, encoding, || {
+            extract_thumbnail(&ffmpeg_path, &file_path, timestamp, encoding)
+        })
+    })
+    .await
+}
+
+// Command to generate many thumbnails in one call. Extraction runs on the
+// bounded pool; results come back in request order. The batch is a job:
+// `job-started` carries its id (`job_id`, or a generated one) for cancel_job,
+// after which the remaining requests fail as cancelled.
+#[tauri::command]
+async fn generate_thumbnails(
+    requests: Vec<ThumbnailRequest>,
+    job_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    pool: tauri::State<'_, ThumbnailPool>,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<Vec<ThumbnailResult>, String> {
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    let job_id = job_id.unwrap_or_else(|| registry.new_job_id("thumbnails"));
+    let _job = registry.begin(&job_id)?;
+    let _ = app_handle.emit("job-started", JobStarted { job_id: job_id.clone(), kind: "thumbnails".to_string() });
+
+    let temp_dir = std::env::temp_dir().join("rvj_thumbnails");
+    std::fs::create_dir_all(&temp_dir).map_err(|e| format!("Failed to create temp dir: {}", e))?;
+
+    let cache_dir = thumbnail_cache::cache_dir(&app_handle);
+    let semaphore = pool.0.semaphore();
+    let handles: Vec<_> = requests
+        .into_iter()
+        .enumerate()
+        .map(|(i, request)| {
+            let (app_handle, ffmpeg_path, job_id) = (app_handle.clone(), ffmpeg_path.clone(), job_id.clone());
+            let temp_path = temp_dir.join(format!("{}-{}.png", job_id, i));
+            let cache_dir = cache_dir.clone();
+            tauri::async_runtime::spawn(pooled_thumbnail(semaphore.clone(), move || {
+                let registry = app_handle.state::<ProcessRegistry>();
+                let encoding = ThumbnailEncoding::default();
+                cached_thumbnail(cache_dir.as_deref(), &request.file_path, request.timestamp, encoding, || {
+                    extract_thumbnail_for_job(&registry, &job_id, &ffmpeg_path, &request, &temp_path)
+                })
+            }))
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        let result = handle
+            .await
+            .map_err(|e| format!("Thumbnail worker failed: {}", e))
+            .and_then(|r| r);
+        results.push(match result {
+            Ok(data_url) => ThumbnailResult { data_url: Some(data_url), error: None },
+            Err(e) => ThumbnailResult { data_url: None, error: Some(e) },
+        });
+    }
+    Ok(results)
+}
+
+// Input-side seek lands this far before the target; the rest is decoded, so
+// the frame is exact without decoding from the start of a long file
+const SEEK_PREROLL: f64 = 5.0;
+
+// Command to grab the exact frame at a position, at native resolution, for
+// picking trim points. Pass `seconds` or a `frame` index (converted with the
+// file's fps). Returns a PNG data URL, or writes the image to `output_path`
+// and returns that path.
+#[tauri::command]
+async fn seek_frame(
+    file_path: String,
+    seconds: Option<f64>,
+    frame: Option<u64>,
+    output_path: Option<String>,
     app_handle: tauri::AppHandle,
 ) -> Result<String, String> {
     use base64::Engine;
-    
-    // Resolve FFmpeg path
-    let ffmpeg_path = app_handle
-        .path()
-        .resolve("bin/ffmpeg.exe", tauri::path::BaseDirectory::Resource)
-        .map_err(|e| format!("Failed to resolve FFmpeg path: {}", e))?;
 
-    if !ffmpeg_path.exists() {
-        return Err(format!("FFmpeg binary not found at {:?}", ffmpeg_path));
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    let position = match (seconds, frame) {
+        (Some(seconds), None) => seconds,
+        (None, Some(frame)) => {
+            let fps = probe::probe(&ffmpeg_path, &file_path)?
+                .first_video()
+                .and_then(|v| v.fps)
+                .ok_or("Could not determine the file's frame rate")?;
+            frame as f64 / fps
+        }
+        _ => return Err("Pass exactly one of seconds or frame".to_string()),
+    };
+    if position < 0.0 {
+        return Err(format!("Invalid position: {}", position));
     }
 
-    // Extract a single frame as PNG to stdout
-    let output = Command::new(&ffmpeg_path)
-        .args([
-            "-ss", &timestamp.to_string(),
-            "-i", &file_path,
-            "-vframes", "1",
-            "-f", "image2pipe",
-            "-vcodec", "png",
-            "-vf", "scale=160:90:force_original_aspect_ratio=decrease",
-            "-"
-        ])
+    // -ss before -i snaps to a keyframe; the one after -i decodes up to the
+    // exact frame
+    let coarse = (position - SEEK_PREROLL).max(0.0);
+    let mut cmd = Command::new(&ffmpeg_path);
+    cmd.args([
+        "-ss", &coarse.to_string(),
+        "-i", &file_path,
+        "-ss", &(position - coarse).to_string(),
+        "-vframes", "1",
+        "-an",
+    ]);
+    match &output_path {
+        Some(path) => cmd.args(["-y", path]),
+        None => cmd.args(["-f", "image2pipe", "-vcodec", "png", "-"]),
+    };
+    let output = cmd
+        .stdin(Stdio::null())
         .output()
-        .map_err(|e| format!("FFmpeg thumbnail extraction failed: {}", e))?;
+        .map_err(|e| format!("FFmpeg frame extraction failed: {}", e))?;
 
     if !output.status.success() {
-        return Err("FFmpeg failed to extract thumbnail".to_string());
+        return Err("FFmpeg failed to extract frame".to_string());
+    }
+    match output_path {
+        Some(path) => Ok(path),
+        None if output.stdout.is_empty() => Err(format!("No frame at {}s", position)),
+        None => Ok(format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&output.stdout)
+        )),
+    }
+}
+
+// Command to change how many thumbnail ffmpeg processes may run at once
+#[tauri::command]
+async fn set_thumbnail_concurrency(
+    max_workers: usize,
+    pool: tauri::State<'_, ThumbnailPool>,
+) -> Result<(), String> {
+    if max_workers == 0 {
+        return Err("Thumbnail concurrency must be at least 1".to_string());
+    }
+    pool.0.resize(max_workers);
+    Ok(())
+}
+
+// Sample rate audio is decoded at for peak extraction; plenty for a waveform
+const PEAKS_SAMPLE_RATE: u32 = 8000;
+
+// Command to compute waveform peaks: `buckets` values in 0..1, each the
+// loudest sample of its slice. `start`/`end` (seconds) restrict the decode to
+// a clip's trimmed region; the result still has exactly `buckets` entries.
+#[tauri::command]
+async fn generate_waveform_peaks(
+    file_path: String,
+    buckets: usize,
+    start: Option<f64>,
+    end: Option<f64>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<f32>, String> {
+    if buckets == 0 {
+        return Err("Bucket count must be at least 1".to_string());
+    }
+    if let (Some(start), Some(end)) = (start, end) {
+        if end <= start {
+            return Err(format!("Invalid range: end {} is not after start {}", end, start));
+        }
+    }
+
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+
+    let mut cmd = Command::new(&ffmpeg_path);
+    if let Some(start) = start {
+        cmd.args(["-ss", &start.to_string()]);
+    }
+    if let Some(end) = end {
+        cmd.args(["-t", &(end - start.unwrap_or(0.0)).to_string()]);
+    }
+    // Decode to mono 16-bit PCM on stdout
+    cmd.args([
+        "-i", &file_path,
+        "-vn",
+        "-ac", "1",
+        "-ar", &PEAKS_SAMPLE_RATE.to_string(),
+        "-f", "s16le",
+        "-",
+    ]);
+    let output = cmd
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("FFmpeg audio decode failed: {}", e))?;
+
+    if !output.status.success() {
+        return Err("FFmpeg failed to decode audio".to_string());
     }
 
-    // Encode as base64 data URL
-    let base64_data = base64::engine::general_purpose::STANDARD.encode(&output.stdout);
-    Ok(format!("data:image/png;base64,{}", base64_data))
+    let samples: Vec<i16> = output
+        .stdout
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+
+    let mut peaks = vec![0.0f32; buckets];
+    if !samples.is_empty() {
+        for (i, peak) in peaks.iter_mut().enumerate() {
+            let from = i * samples.len() / buckets;
+            let to = ((i + 1) * samples.len() / buckets).max(from + 1).min(samples.len());
+            let max = samples[from..to].iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+            *peak = max as f32 / 32768.0;
+        }
+    }
+    Ok(peaks)
 }
 
-// Command to generate a low-quality proxy video for fast preview
+// Largest rows/columns a contact sheet may have
+const MAX_CONTACT_SHEET_GRID: u32 = 16;
+
+// Command to lay out frames sampled evenly across a file as one grid image,
+// for reviewing a long source at a glance. The image format follows the
+// output extension (.png, .jpg).
 #[tauri::command]
-async fn generate_proxy_video(
-    input_path: String,
+#[allow(clippy::too_many_arguments)]
+async fn generate_contact_sheet(
+    file_path: String,
     output_path: String,
-    width: u32,
-    height: u32,
-    bitrate: String,
+    rows: u32,
+    columns: u32,
+    tile_width: u32,
+    tile_height: u32,
+    labels: Option<bool>,
     app_handle: tauri::AppHandle,
 ) -> Result<String, String> {
-    // Resolve FFmpeg path
-    let ffmpeg_path = app_handle
-        .path()
-        .resolve("bin/ffmpeg.exe", tauri::path::BaseDirectory::Resource)
-        .map_err(|e| format!("Failed to resolve FFmpeg path: {}", e))?;
+    for (name, value) in [("rows", rows), ("columns", columns)] {
+        if !(1..=MAX_CONTACT_SHEET_GRID).contains(&value) {
+            return Err(format!("{} must be between 1 and {}, got {}", name, MAX_CONTACT_SHEET_GRID, value));
+        }
+    }
+    if tile_width == 0 || tile_height == 0 {
+        return Err("Tile size must be at least 1x1".to_string());
+    }
 
-    if !ffmpeg_path.exists() {
-        return Err(format!("FFmpeg binary not found at {:?}", ffmpeg_path));
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    let duration = probe::probe(&ffmpeg_path, &file_path)?
+        .duration
+        .filter(|d| *d > 0.0)
+        .ok_or("Contact sheet needs a file with a known duration")?;
+
+    // One frame every `interval` seconds fills the grid over the whole file
+    let interval = duration / (rows * columns) as f64;
+    let mut filter = format!(
+        r"select='isnan(prev_selected_t)+gte(t-prev_selected_t\,{})',scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2",
+        interval,
+        w = tile_width,
+        h = tile_height
+    );
+    if labels.unwrap_or(true) {
+        let font = overlay::resolve_font(&app_handle);
+        filter.push(',');
+        filter.push_str(&overlay::timestamp_label_filter(font.as_deref()));
     }
+    filter.push_str(&format!(",tile={}x{}", columns, rows));
 
-    // Ensure output directory exists
-    if let Some(parent) = std::path::Path::new(&output_path).parent() {
-        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create proxy dir: {}", e))?;
+    if let Some(parent) = Path::new(&output_path).parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create output dir: {}", e))?;
     }
 
-    // FFmpeg command for generating proxy:
-    // - Scale to target resolution
-    // - Use fast encoding preset
-    // - Lower bitrate for smaller file size
     let status = Command::new(&ffmpeg_path)
         .args([
             "-y",
-            "-i", &input_path,
-            "-vf", &format!("scale={}:{}:force_original_aspect_ratio=decrease,pad={}:{}:(ow-iw)/2:(oh-ih)/2", width, height, width, height),
-            "-c:v", "libx264",
-            "-preset", "ultrafast",
-            "-b:v", &bitrate,
-            "-c:a", "aac",
-            "-b:a", "96k",
-            "-movflags", "+faststart", // Enable fast start for streaming
+            "-i", &file_path,
+            "-an",
+            "-vf", &filter,
+            "-fps_mode", "vfr",
+            "-frames:v", "1",
             &output_path,
         ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
         .status()
-        .map_err(|e| format!("FFmpeg proxy generation failed: {}", e))?;
+        .map_err(|e| format!("FFmpeg contact sheet failed: {}", e))?;
 
     if !status.success() {
-        return Err("FFmpeg proxy generation exited with error".to_string());
+        return Err("FFmpeg contact sheet exited with error".to_string());
     }
-
     Ok(output_path)
 }
 
@@ -227,13 +2534,188 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_http::init())
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(|app_handle, _shortcut, event| {
+                    // Same path as cancel_export without a task id; does
+                    // nothing when nothing is rendering
+                    if event.state() == ShortcutState::Pressed {
+                        let registry = app_handle.state::<ProcessRegistry>();
+                        cancel_all_exports(&registry, &app_handle.state::<tasks::TaskStore>());
+                    }
+                })
+                .build(),
+        )
+        .manage(ProcessRegistry::default())
+        .manage(CancelShortcut(Mutex::new(CancelShortcutState {
+            shortcut: DEFAULT_CANCEL_SHORTCUT.to_string(),
+            error: None,
+        })))
+        .manage(RemoteConfig::default())
+        .manage(ThumbnailPool(tasks::Slots::new(DEFAULT_THUMBNAIL_WORKERS)))
+        .manage(midi::MidiState::default())
+        .manage(osc::OscServer::default())
+        .manage(preview::PreviewState::default())
+        .manage(ndi::NdiOutput::default())
+        .manage(virtual_camera::VirtualCamera::default())
+        .manage(stream::LiveStream::default())
+        .manage(temp::TempDirConfig::default())
+        .manage(temp::StartupCleanup::default())
+        .manage(tasks::TaskStore::default())
+        .manage(capabilities::CapabilitiesCache::default())
+        .manage(tasks::ExportSlots(tasks::Slots::new(tasks::DEFAULT_MAX_CONCURRENT_EXPORTS)))
+        .setup(|app| {
+            // Not fatal: the OS may reserve the combination or another app may
+            // own it. get_cancel_shortcut reports it, and set_cancel_shortcut
+            // can pick another.
+            if let Err(e) = app.global_shortcut().register(DEFAULT_CANCEL_SHORTCUT) {
+                let error = format!("Failed to register shortcut '{}': {}", DEFAULT_CANCEL_SHORTCUT, e);
+                app.state::<CancelShortcut>().0.lock().unwrap().error = Some(error);
+            }
+            midi::watch_devices(app.handle().clone());
+            temp::cleanup_on_startup(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             export_video,
+            export_audio_only,
+            cancel_export,
+            cancel_job,
+            temp::cleanup_temp,
+            temp::get_startup_cleanup,
+            temp::set_temp_dir,
+            temp::get_temp_dir,
+            tasks::get_task_result,
+            tasks::get_task_status,
+            tasks::set_max_concurrent_exports,
+            disk::get_free_space,
+            set_cancel_shortcut,
+            get_cancel_shortcut,
+            remote::set_remote_target,
+            remote::get_remote_target,
             validate_file_path,
+            validate_file_paths,
+            reveal_in_explorer,
+            subtitles::mux_subtitles,
+            gif::export_clip_gif,
+            animated::export_webp,
+            animated::export_apng,
+            render_cache::clear_render_cache,
+            capabilities::get_ffmpeg_capabilities,
+            transitions::list_transitions,
             generate_thumbnail,
-            generate_proxy_video
+            generate_thumbnails,
+            set_thumbnail_concurrency,
+            seek_frame,
+            probe::get_frame_count,
+            probe::probe_media,
+            probe::stat_file,
+            probe::list_streams,
+            generate_waveform_peaks,
+            spectrum::get_spectrum,
+            edl::export_edl,
+            edl::import_edl,
+            fcpxml::export_fcpxml,
+            tempo::detect_tempo_sections,
+            generate_contact_sheet,
+            analysis::detect_black,
+            analysis::detect_silence,
+            analysis::verify_media,
+            midi::list_midi_devices,
+            midi::open_midi_device,
+            midi::close_midi_device,
+            osc::start_osc_server,
+            osc::stop_osc_server,
+            osc::send_osc,
+            preview::start_preview,
+            preview::stop_preview,
+            ndi::start_ndi_output,
+            ndi::stop_ndi_output,
+            virtual_camera::start_virtual_camera,
+            virtual_camera::stop_virtual_camera,
+            texture_share::start_spout_output,
+            texture_share::start_syphon_output,
+            stream::start_stream_rtmp,
+            stream::start_stream_srt,
+            stream::stop_stream,
+            proxy::generate_proxy_video,
+            proxy::generate_project_proxies
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            // Don't leave ffmpeg running (and holding temp files) after we quit
+            if let tauri::RunEvent::Exit = event {
+                app_handle.state::<ProcessRegistry>().kill_all();
+                app_handle.state::<preview::PreviewState>().stop();
+                app_handle.state::<ndi::NdiOutput>().0.stop();
+                app_handle.state::<virtual_camera::VirtualCamera>().0.stop();
+            }
+        });
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A process that runs for a while with `dir` as its working directory,
+    // which on Windows keeps the directory from being removed until it exits
+    fn long_running(dir: &Path) -> Command {
+        let mut cmd = if cfg!(windows) {
+            let mut cmd = Command::new("ping");
+            cmd.args(["-n", "30", "127.0.0.1"]);
+            cmd
+        } else {
+            let mut cmd = Command::new("sleep");
+            cmd.arg("30");
+            cmd
+        };
+        cmd.current_dir(dir);
+        cmd
+    }
+
+    #[test]
+    fn temp_dir_is_removable_after_cancel() {
+        let dir = temp::export_dir(&std::env::temp_dir());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("trimmed_0.ts"), b"partial").unwrap();
+
+        let registry = ProcessRegistry::default();
+        let job = registry.new_job_id("export");
+        let _job = registry.begin(&job).unwrap();
+        let result = std::thread::scope(|scope| {
+            let run = scope.spawn(|| registry.run(&job, &mut long_running(&dir)));
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while registry.jobs.lock().unwrap()[&job].children.is_empty() {
+                assert!(Instant::now() < deadline, "the process never started");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert!(registry.cancel(&job));
+            run.join().unwrap()
+        });
+
+        assert_eq!(result.unwrap_err(), JOB_CANCELLED_ERROR);
+        // The child was reaped by the cancel itself, so nothing holds the dir
+        assert!(registry.jobs.lock().unwrap()[&job].children.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn cancel_before_the_job_thread_starts_is_kept() {
+        let registry = ProcessRegistry::default();
+        let job = registry.new_job_id("export");
+        registry.reserve(&job).unwrap();
+        assert!(registry.reserve(&job).is_err());
+        // Sent before the job's thread has done anything
+        assert!(registry.cancel(&job));
+        {
+            let _job = registry.adopt(&job);
+            assert!(registry.is_cancelled(&job));
+            let result = registry.run(&job, &mut long_running(&std::env::temp_dir()));
+            assert_eq!(result.unwrap_err(), JOB_CANCELLED_ERROR);
+        }
+        assert!(!registry.cancel(&job));
+    }
+}
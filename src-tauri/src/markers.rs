@@ -0,0 +1,196 @@
+// Cue markers on the exported timeline. They are written into the output as
+// chapters where the container has them (by a stream-copy remux once the
+// final encode is done, as cover.rs does with its art) and, when asked for,
+// into a sidecar next to it for players and tools that ignore chapters.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::encode::Container;
+use crate::ExportContext;
+
+const MAX_MARKER_TITLE_CHARS: usize = 256;
+
+// A point in the output worth jumping to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Marker {
+    // Seconds from the start of the output
+    pub time: f64,
+    pub title: String,
+}
+
+// Sidecar written next to the output, named after it
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SidecarFormat {
+    // {output}.json: every marker with its start and end
+    Json,
+    // {output}.cue: a cue sheet with one track per marker
+    Cue,
+}
+
+// What a sidecar lists for each marker
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SidecarMarker {
+    start: f64,
+    end: f64,
+    title: String,
+}
+
+// Checks what can be checked before the timeline's length is known
+pub fn validate(markers: &[Marker]) -> Result<(), String> {
+    for marker in markers {
+        if !marker.time.is_finite() || marker.time < 0.0 {
+            return Err(format!("Invalid marker time: {}", marker.time));
+        }
+        if marker.title.chars().count() > MAX_MARKER_TITLE_CHARS || marker.title.chars().any(char::is_control) {
+            return Err(format!(
+                "Marker titles must be at most {} characters, without control characters",
+                MAX_MARKER_TITLE_CHARS
+            ));
+        }
+    }
+    let mut times: Vec<f64> = markers.iter().map(|m| m.time).collect();
+    times.sort_by(f64::total_cmp);
+    if let Some(pair) = times.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(format!("Two markers are at {}s", pair[0]));
+    }
+    Ok(())
+}
+
+// Every marker must fall inside the `duration` seconds of output
+pub fn validate_fit(markers: &[Marker], duration: f64) -> Result<(), String> {
+    match markers.iter().find(|m| m.time >= duration) {
+        Some(marker) => Err(format!(
+            "Marker \"{}\" at {}s is past the end of the {:.3}s output",
+            marker.title, marker.time, duration
+        )),
+        None => Ok(()),
+    }
+}
+
+// MP4, MOV and Matroska (WebM too) carry chapters; MXF and the rest don't
+pub fn supports_chapters(container: Container) -> bool {
+    matches!(container, Container::Mp4 | Container::Mov | Container::Mkv | Container::Webm)
+}
+
+// The markers in time order, each running to the next one or the end
+fn spans(markers: &[Marker], duration: f64) -> Vec<SidecarMarker> {
+    let mut sorted: Vec<&Marker> = markers.iter().collect();
+    sorted.sort_by(|a, b| a.time.total_cmp(&b.time));
+    sorted
+        .iter()
+        .enumerate()
+        .map(|(i, marker)| SidecarMarker {
+            start: marker.time,
+            end: sorted.get(i + 1).map_or(duration, |next| next.time),
+            title: marker.title.clone(),
+        })
+        .collect()
+}
+
+// FFMETADATA needs '=', ';', '#', '\' and newlines escaped
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn chapters_metadata(markers: &[Marker], duration: f64) -> String {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for span in spans(markers, duration) {
+        metadata.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (span.start * 1000.0).round() as u64,
+            (span.end * 1000.0).round() as u64,
+            escape_metadata(&span.title)
+        ));
+    }
+    metadata
+}
+
+// Remuxes `output_path` with the markers as its chapters, replacing the file.
+// The remux is written next to the output so the final rename stays on one
+// volume.
+pub fn embed_chapters(ctx: &ExportContext, output_path: &str, markers: &[Marker], duration: f64) -> Result<(), String> {
+    let metadata_path = ctx.temp_dir.join("chapters.txt");
+    std::fs::write(&metadata_path, chapters_metadata(markers, duration))
+        .map_err(|e| format!("Failed to write chapters: {}", e))?;
+    let output = Path::new(output_path);
+    let stem = output.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = output.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+    let partial = output.with_file_name(format!("{}.chapters-partial.{}", stem, ext));
+
+    let mut cmd = Command::new(ctx.ffmpeg_path);
+    cmd.args(["-y", "-i", output_path, "-f", "ffmetadata", "-i"]);
+    cmd.arg(&metadata_path);
+    // Every stream is kept, cover art and attachments included
+    cmd.args(["-map", "0", "-map_chapters", "1", "-c", "copy"]);
+    cmd.args(ctx.movflags());
+    cmd.arg(&partial);
+
+    let result = ctx
+        .registry
+        .run(ctx.task_id, &mut cmd)
+        .map_err(|e| format!("FFmpeg chapters failed: {}", e))
+        .and_then(|status| match status.success() {
+            true => Ok(()),
+            false => Err("FFmpeg chapters exited with error".to_string()),
+        })
+        .and_then(|()| {
+            std::fs::rename(&partial, output)
+                .map_err(|e| format!("Failed to replace the output with the chaptered copy: {}", e))
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+// "MM:SS:FF" at the 75 frames a second cue sheets count in
+fn cue_time(seconds: f64) -> String {
+    let frames = (seconds * 75.0).round() as u64;
+    format!("{:02}:{:02}:{:02}", frames / 75 / 60, frames / 75 % 60, frames % 75)
+}
+
+fn cue_sheet(output: &Path, markers: &[Marker], duration: f64) -> String {
+    let file_name = output.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    // WAVE is what players expect whatever the file's codec; quotes can't be
+    // escaped in a cue sheet
+    let mut sheet = format!("FILE \"{}\" WAVE\n", file_name.replace('"', "'"));
+    for (i, span) in spans(markers, duration).iter().enumerate() {
+        sheet.push_str(&format!(
+            "  TRACK {:02} AUDIO\n    TITLE \"{}\"\n    INDEX 01 {}\n",
+            i + 1,
+            span.title.replace('"', "'"),
+            cue_time(span.start)
+        ));
+    }
+    sheet
+}
+
+// Writes the sidecar for the output at `output_path` and returns its path
+pub fn write_sidecar(
+    format: SidecarFormat,
+    output_path: &str,
+    markers: &[Marker],
+    duration: f64,
+) -> Result<PathBuf, String> {
+    let output = Path::new(output_path);
+    let (path, contents) = match format {
+        SidecarFormat::Json => {
+            let json = serde_json::to_string_pretty(&spans(markers, duration))
+                .map_err(|e| format!("Failed to serialize markers: {}", e))?;
+            (output.with_extension("json"), json)
+        }
+        SidecarFormat::Cue => (output.with_extension("cue"), cue_sheet(output, markers, duration)),
+    };
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to write marker sidecar {}: {}", path.display(), e))?;
+    Ok(path)
+}
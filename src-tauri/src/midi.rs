@@ -0,0 +1,164 @@
+// MIDI controller input. One input device can be open at a time; its note and
+// CC messages are forwarded to the frontend as `midi-event`s, which maps them
+// to clip triggers and parameters.
+//
+// A watcher thread polls the device list so controllers can be plugged in and
+// out while the app runs: `midi-devices-changed` carries the new list, and
+// `midi-device-disconnected` fires when the open device goes away.
+
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+
+const CLIENT_NAME: &str = "rVJ";
+
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MidiDevice {
+    // Stable for as long as the device stays connected
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum MidiEventKind {
+    NoteOn,
+    NoteOff,
+    ControlChange,
+}
+
+// Payload of `midi-event`. Channels are 1-16, as printed on hardware.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MidiEvent {
+    kind: MidiEventKind,
+    channel: u8,
+    // Note number for notes, controller number for CC
+    number: u8,
+    // Velocity for notes, value for CC; 0-127
+    value: u8,
+    // Microseconds, from the backend's clock
+    timestamp: u64,
+}
+
+struct OpenDevice {
+    device: MidiDevice,
+    _connection: MidiInputConnection<()>,
+}
+
+#[derive(Default)]
+pub struct MidiState(Mutex<Option<OpenDevice>>);
+
+fn input() -> Result<MidiInput, String> {
+    MidiInput::new(CLIENT_NAME).map_err(|e| format!("Failed to initialise MIDI: {}", e))
+}
+
+fn devices(input: &MidiInput) -> Vec<MidiDevice> {
+    input
+        .ports()
+        .iter()
+        .filter_map(|port| {
+            // A port that vanished between listing and naming is skipped
+            let name = input.port_name(port).ok()?;
+            Some(MidiDevice { id: port.id(), name })
+        })
+        .collect()
+}
+
+// Note on/off and CC from a raw message; everything else is ignored
+fn parse_message(timestamp: u64, message: &[u8]) -> Option<MidiEvent> {
+    let (&status, data) = message.split_first()?;
+    let (&number, &value) = (data.first()?, data.get(1)?);
+    let kind = match status & 0xF0 {
+        // Note on with velocity 0 is the running-status way of saying note off
+        0x90 if value > 0 => MidiEventKind::NoteOn,
+        0x90 | 0x80 => MidiEventKind::NoteOff,
+        0xB0 => MidiEventKind::ControlChange,
+        _ => return None,
+    };
+    Some(MidiEvent { kind, channel: (status & 0x0F) + 1, number, value, timestamp })
+}
+
+// Polls for device changes until the app exits. Started once from setup.
+pub fn watch_devices(app_handle: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        let mut known = Vec::new();
+        loop {
+            if let Ok(input) = input() {
+                let current = devices(&input);
+                if current != known {
+                    let _ = app_handle.emit("midi-devices-changed", &current);
+
+                    // Drop the connection to an unplugged device so it can be
+                    // reopened cleanly once it comes back
+                    let state = app_handle.state::<MidiState>();
+                    let mut open = state.0.lock().unwrap();
+                    if let Some(gone) = open.as_ref().filter(|o| !current.contains(&o.device)) {
+                        let _ = app_handle.emit("midi-device-disconnected", &gone.device);
+                        *open = None;
+                    }
+                    known = current;
+                }
+            }
+            std::thread::sleep(DEVICE_POLL_INTERVAL);
+        }
+    });
+}
+
+// Command to list the connected MIDI input devices
+#[tauri::command]
+pub async fn list_midi_devices() -> Result<Vec<MidiDevice>, String> {
+    Ok(devices(&input()?))
+}
+
+// Command to start forwarding a device's messages as `midi-event`s. Replaces
+// any device opened before.
+#[tauri::command]
+pub async fn open_midi_device(
+    id: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MidiState>,
+) -> Result<MidiDevice, String> {
+    let mut input = input()?;
+    // Clock, sysex and active sensing would only be filtered out again below
+    input.ignore(Ignore::All);
+
+    let port = input
+        .find_port_by_id(id.clone())
+        .ok_or_else(|| format!("MIDI device not found: {}", id))?;
+    let name = input
+        .port_name(&port)
+        .map_err(|e| format!("Failed to read MIDI device name: {}", e))?;
+
+    // Close the current device first; some drivers only allow one client
+    let mut open = state.0.lock().unwrap();
+    *open = None;
+
+    let emitter = app_handle.clone();
+    let connection = input
+        .connect(
+            &port,
+            "rvj-input",
+            move |timestamp, message, _| {
+                if let Some(event) = parse_message(timestamp, message) {
+                    let _ = emitter.emit("midi-event", event);
+                }
+            },
+            (),
+        )
+        .map_err(|e| format!("Failed to open MIDI device {}: {}", name, e))?;
+
+    let device = MidiDevice { id, name };
+    *open = Some(OpenDevice { device: device.clone(), _connection: connection });
+    Ok(device)
+}
+
+// Command to close the open MIDI device, if any
+#[tauri::command]
+pub async fn close_midi_device(state: tauri::State<'_, MidiState>) -> Result<(), String> {
+    *state.0.lock().unwrap() = None;
+    Ok(())
+}
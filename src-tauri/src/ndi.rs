@@ -0,0 +1,59 @@
+// NDI output, so OBS, vMix, Resolume and the like can take rVJ's picture
+// over the network. An ffmpeg with the libndi_newtek muxer announces the
+// source and sends the preview's frames (see frame_sink.rs).
+
+use std::process::Command;
+
+use crate::capabilities::{self, CapabilitiesCache};
+use crate::frame_sink::{SinkSlot, MJPEG_STDIN_ARGS};
+use crate::resolve_ffmpeg;
+
+// NDI truncates longer source names
+const MAX_NDI_NAME_CHARS: usize = 64;
+
+pub struct NdiOutput(pub SinkSlot);
+
+impl Default for NdiOutput {
+    fn default() -> Self {
+        NdiOutput(SinkSlot::new("ndi", "NDI"))
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("NDI source name can't be empty".to_string());
+    }
+    if name.chars().count() > MAX_NDI_NAME_CHARS {
+        return Err(format!("NDI source name must be at most {} characters", MAX_NDI_NAME_CHARS));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("NDI source name can't contain control characters".to_string());
+    }
+    Ok(())
+}
+
+// Command to announce an NDI source called `name` (as receivers list it,
+// after the machine's name) carrying the preview. Starting it again renames
+// the source. Needs an ffmpeg built with NDI support; `ndi-stopped` reports
+// the source going away on its own.
+#[tauri::command]
+pub async fn start_ndi_output(
+    name: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, NdiOutput>,
+    capabilities: tauri::State<'_, CapabilitiesCache>,
+) -> Result<(), String> {
+    validate_name(&name)?;
+    capabilities::require_muxer(&app_handle, &capabilities, "libndi_newtek", "NDI output")?;
+    let mut cmd = Command::new(resolve_ffmpeg(&app_handle)?);
+    cmd.args(["-loglevel", "error"]);
+    cmd.args(MJPEG_STDIN_ARGS);
+    cmd.args(["-fps_mode", "passthrough", "-pix_fmt", "uyvy422", "-f", "libndi_newtek", &name]);
+    state.0.start(cmd, name)
+}
+
+// Command to withdraw the NDI source. Returns false if none was running.
+#[tauri::command]
+pub async fn stop_ndi_output(state: tauri::State<'_, NdiOutput>) -> Result<bool, String> {
+    Ok(state.0.stop())
+}
@@ -0,0 +1,169 @@
+// OSC control for live rigs (TouchOSC, Resolume, lighting desks). Incoming
+// messages on the configured UDP port are forwarded to the frontend as
+// `osc-message` events; bundles are flattened into their messages.
+//
+// The backend forwards every address as-is. The frontend maps these:
+//
+//   /rvj/clip/{n}/trigger           start clip n (1-based)
+//   /rvj/param/{name} <float>       set a parameter, normalised to 0..1
+//   /rvj/transport/play|stop|seek   transport; seek takes seconds
+//
+// Arguments arrive as JSON: ints and floats as numbers, strings and chars as
+// strings, true/false as booleans, blobs as arrays of bytes and anything
+// without a JSON equivalent as null.
+
+use rosc::{OscMessage, OscPacket, OscType};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tauri::Emitter;
+
+// How often the receive loop wakes up to check for a stop request
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(200);
+
+// Payload of `osc-message`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct OscEvent {
+    address: String,
+    args: Vec<Value>,
+}
+
+struct RunningServer {
+    port: u16,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl RunningServer {
+    fn shut_down(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+#[derive(Default)]
+pub struct OscServer(Mutex<Option<RunningServer>>);
+
+fn arg_to_json(arg: OscType) -> Value {
+    match arg {
+        OscType::Int(i) => i.into(),
+        OscType::Long(i) => i.into(),
+        OscType::Float(f) => f.into(),
+        OscType::Double(f) => f.into(),
+        OscType::String(s) => s.into(),
+        OscType::Char(c) => c.to_string().into(),
+        OscType::Bool(b) => b.into(),
+        OscType::Blob(bytes) => bytes.into(),
+        OscType::Array(array) => array.content.into_iter().map(arg_to_json).collect::<Vec<_>>().into(),
+        _ => Value::Null,
+    }
+}
+
+fn json_to_arg(value: &Value) -> Result<OscType, String> {
+    Ok(match value {
+        Value::Null => OscType::Nil,
+        Value::Bool(b) => OscType::Bool(*b),
+        Value::String(s) => OscType::String(s.clone()),
+        Value::Number(_) => match value.as_i64() {
+            Some(i) => i32::try_from(i).map(OscType::Int).unwrap_or(OscType::Long(i)),
+            None => OscType::Float(value.as_f64().unwrap_or(0.0) as f32),
+        },
+        Value::Array(_) | Value::Object(_) => {
+            return Err(format!("Unsupported OSC argument: {}", value));
+        }
+    })
+}
+
+fn emit_packet(app_handle: &tauri::AppHandle, packet: OscPacket) {
+    match packet {
+        OscPacket::Message(message) => {
+            let event = OscEvent {
+                address: message.addr,
+                args: message.args.into_iter().map(arg_to_json).collect(),
+            };
+            let _ = app_handle.emit("osc-message", event);
+        }
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                emit_packet(app_handle, packet);
+            }
+        }
+    }
+}
+
+// Command to listen for OSC on `port` (all interfaces). Restarts the server if
+// it is already running on another port.
+#[tauri::command]
+pub async fn start_osc_server(
+    port: u16,
+    app_handle: tauri::AppHandle,
+    server: tauri::State<'_, OscServer>,
+) -> Result<(), String> {
+    let mut running = server.0.lock().unwrap();
+    if running.as_ref().is_some_and(|s| s.port == port) {
+        return Ok(());
+    }
+    if let Some(previous) = running.take() {
+        previous.shut_down();
+    }
+
+    let socket = UdpSocket::bind(("0.0.0.0", port))
+        .map_err(|e| format!("Failed to listen for OSC on port {}: {}", port, e))?;
+    socket
+        .set_read_timeout(Some(RECEIVE_TIMEOUT))
+        .map_err(|e| format!("Failed to configure OSC socket: {}", e))?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let thread = std::thread::spawn(move || {
+        let mut buf = [0u8; rosc::decoder::MTU];
+        while !thread_stop.load(Ordering::Relaxed) {
+            // Timeouts just loop back to the stop check
+            let Ok((size, _)) = socket.recv_from(&mut buf) else { continue };
+            // Malformed packets are dropped; one bad sender shouldn't stop the server
+            if let Ok((_, packet)) = rosc::decoder::decode_udp(&buf[..size]) {
+                emit_packet(&app_handle, packet);
+            }
+        }
+    });
+
+    *running = Some(RunningServer { port, stop, thread });
+    Ok(())
+}
+
+// Command to stop the OSC server. Returns false if it wasn't running.
+#[tauri::command]
+pub async fn stop_osc_server(server: tauri::State<'_, OscServer>) -> Result<bool, String> {
+    match server.0.lock().unwrap().take() {
+        Some(running) => {
+            running.shut_down();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+// Command to send one OSC message to `host:port`, e.g. to drive lights or
+// another VJ app from the timeline
+#[tauri::command]
+pub async fn send_osc(host: String, port: u16, address: String, args: Vec<Value>) -> Result<(), String> {
+    if !address.starts_with('/') {
+        return Err(format!("OSC address must start with '/': {}", address));
+    }
+    let message = OscMessage {
+        addr: address,
+        args: args.iter().map(json_to_arg).collect::<Result<_, _>>()?,
+    };
+    let packet = rosc::encoder::encode(&OscPacket::Message(message))
+        .map_err(|e| format!("Failed to encode OSC message: {}", e))?;
+
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).map_err(|e| format!("Failed to open OSC socket: {}", e))?;
+    socket
+        .send_to(&packet, (host.as_str(), port))
+        .map_err(|e| format!("Failed to send OSC message to {}:{}: {}", host, port, e))?;
+    Ok(())
+}
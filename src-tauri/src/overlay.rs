@@ -0,0 +1,351 @@
+// Text and images burned into exported video: drawtext for text, overlay for
+// a watermark image
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+// Font shipped with the app for overlays, when the bundle includes one
+const BUNDLED_FONT: &str = "bin/fonts/overlay.ttf";
+
+// Fallbacks when no font is bundled; drawtext has no built-in font
+#[cfg(target_os = "windows")]
+const SYSTEM_FONTS: &[&str] = &["C:/Windows/Fonts/arial.ttf", "C:/Windows/Fonts/segoeui.ttf"];
+#[cfg(target_os = "macos")]
+const SYSTEM_FONTS: &[&str] = &["/System/Library/Fonts/Helvetica.ttc", "/Library/Fonts/Arial.ttf"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const SYSTEM_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl OverlayPosition {
+    // drawtext x/y expressions, inset from the frame edge by 2.5% of its height
+    fn xy(self) -> (&'static str, &'static str) {
+        let x = match self {
+            OverlayPosition::TopLeft | OverlayPosition::BottomLeft => "h*0.025",
+            OverlayPosition::TopCenter | OverlayPosition::BottomCenter => "(w-text_w)/2",
+            OverlayPosition::TopRight | OverlayPosition::BottomRight => "w-text_w-h*0.025",
+        };
+        let y = match self {
+            OverlayPosition::TopLeft | OverlayPosition::TopCenter | OverlayPosition::TopRight => "h*0.025",
+            _ => "h-text_h-h*0.025",
+        };
+        (x, y)
+    }
+}
+
+// What a burned-in timecode counts from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimecodeBase {
+    // Position in the exported timeline
+    Timeline,
+    // Position in each clip's source file
+    Clip,
+}
+
+fn default_text_position() -> OverlayPosition {
+    OverlayPosition::BottomLeft
+}
+
+fn default_text_color() -> String {
+    "white".to_string()
+}
+
+// A line of text burned into every clip. The text may contain tokens,
+// resolved for each clip at export time:
+//
+//   {date}       the date of the export, YYYY-MM-DD
+//   {filename}   the clip's source file name
+//   {timecode}   the running timeline position, HH:MM:SS.mmm
+//
+// Anything else, including other braces, is drawn as written.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TextOverlay {
+    pub text: String,
+    #[serde(default = "default_text_position")]
+    pub position: OverlayPosition,
+    // Pixels; None scales with the frame (a twentieth of its height)
+    #[serde(default)]
+    pub font_size: Option<u32>,
+    // ffmpeg color name or #RRGGBB
+    #[serde(default = "default_text_color")]
+    pub color: String,
+}
+
+impl TextOverlay {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.text.trim().is_empty() {
+            return Err("Text overlay must not be empty".to_string());
+        }
+        if self.font_size == Some(0) {
+            return Err("Text overlay font_size must be positive".to_string());
+        }
+        crate::effects::validate_color(&self.color)
+    }
+
+    // The text for one clip, in drawtext's expansion syntax: tokens become
+    // %{...} functions evaluated per frame, and everything else is escaped so
+    // a stray % or backslash is drawn literally. `timeline_offset` is where
+    // the clip starts on the timeline, as each clip's pts starts at 0.
+    pub fn expand(&self, filename: &str, timeline_offset: f64) -> String {
+        let mut out = String::new();
+        let mut rest = self.text.as_str();
+        while let Some(c) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("{date}") {
+                out.push_str("%{localtime:%Y-%m-%d}");
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("{filename}") {
+                out.push_str(&escape_text(filename));
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("{timecode}") {
+                out.push_str(&format!("%{{pts:hms:{}}}", timeline_offset));
+                rest = after;
+            } else {
+                out.push_str(&escape_text(&c.to_string()));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        out
+    }
+
+    // drawtext filter reading its text from `textfile`, which holds the
+    // output of `expand`. A file side-steps filtergraph quoting of user text.
+    pub fn filter(&self, font: Option<&Path>, textfile: &Path) -> String {
+        let (x, y) = self.position.xy();
+        let mut filter = String::from("drawtext=");
+        if let Some(font) = font {
+            filter.push_str(&format!("fontfile='{}':", escape_value(&font.to_string_lossy())));
+        }
+        let font_size = self.font_size.map_or("h/20".to_string(), |size| size.to_string());
+        filter.push_str(&format!(
+            "textfile='{}':x={}:y={}:fontsize={}:fontcolor={}:box=1:boxcolor=black@0.5:boxborderw=6",
+            escape_value(&textfile.to_string_lossy()),
+            x,
+            y,
+            font_size,
+            self.color
+        ));
+        filter
+    }
+}
+
+// Literal text for drawtext's expansion, where \ and % are special
+fn escape_text(text: &str) -> String {
+    text.replace('\\', r"\\").replace('%', r"\%")
+}
+
+// Default gap between a watermark and the frame edge, in pixels
+const DEFAULT_WATERMARK_MARGIN: u32 = 24;
+
+fn default_watermark_position() -> OverlayPosition {
+    OverlayPosition::BottomRight
+}
+
+fn default_watermark_margin() -> u32 {
+    DEFAULT_WATERMARK_MARGIN
+}
+
+fn default_watermark_opacity() -> f64 {
+    1.0
+}
+
+// An image (usually a PNG logo) laid over every frame
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Watermark {
+    pub path: String,
+    // Corner or edge to place it at, `margin` pixels in from the frame edge
+    #[serde(default = "default_watermark_position")]
+    pub position: OverlayPosition,
+    #[serde(default = "default_watermark_margin")]
+    pub margin: u32,
+    // Pixel offset of its top-left corner; overrides position when both are set
+    #[serde(default)]
+    pub x: Option<u32>,
+    #[serde(default)]
+    pub y: Option<u32>,
+    // 0 (invisible) to 1 (as in the image)
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f64,
+    // Width as a fraction of the frame width; None keeps the image's own size
+    #[serde(default)]
+    pub scale: Option<f64>,
+}
+
+impl Watermark {
+    pub fn validate(&self) -> Result<(), String> {
+        if !Path::new(&self.path).is_file() {
+            return Err(format!("Watermark image not found: {}", self.path));
+        }
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err(format!("Watermark opacity must be between 0 and 1, got {}", self.opacity));
+        }
+        if let Some(scale) = self.scale {
+            if !(scale > 0.0 && scale <= 1.0) {
+                return Err(format!("Watermark scale must be between 0 and 1, got {}", scale));
+            }
+        }
+        if self.x.is_some() != self.y.is_some() {
+            return Err("Watermark x and y must be set together".to_string());
+        }
+        Ok(())
+    }
+
+    // Size it is drawn at on a `frame` sized picture, given the image's own size
+    fn drawn_size(&self, frame: (u32, u32), image: (u32, u32)) -> (u32, u32) {
+        match self.scale {
+            Some(scale) => {
+                let width = (frame.0 as f64 * scale).round().max(1.0);
+                (width as u32, (image.1 as f64 * width / image.0 as f64).round().max(1.0) as u32)
+            }
+            None => image,
+        }
+    }
+
+    // Errors if the watermark would hang off the edge of the frame
+    pub fn check_fit(&self, frame: (u32, u32), image: (u32, u32)) -> Result<(), String> {
+        let (w, h) = self.drawn_size(frame, image);
+        let (right, bottom) = match (self.x, self.y) {
+            (Some(x), Some(y)) => (x + w, y + h),
+            _ => (w + self.margin, h + self.margin),
+        };
+        if right > frame.0 || bottom > frame.1 {
+            return Err(format!(
+                "Watermark ({}x{}) doesn't fit in the {}x{} frame at that position",
+                w, h, frame.0, frame.1
+            ));
+        }
+        Ok(())
+    }
+
+    // filter_complex chains for the image input `[input:v]`: the sized,
+    // faded image as [wm], and the overlay that lays it over `[base]`. A
+    // `driven` fade is named for reactive.rs to move, and the image input
+    // repeats, so the overlay ends with the clip.
+    pub fn filters(&self, input: usize, frame: (u32, u32), image: (u32, u32), driven: bool) -> (String, String) {
+        let (w, h) = self.drawn_size(frame, image);
+        let mut image_chain = format!("[{}:v]", input);
+        if (w, h) != image {
+            image_chain.push_str(&format!("scale={}:{},", w, h));
+        }
+        let mixer = if driven { crate::reactive::OPACITY_TARGET } else { "colorchannelmixer" };
+        image_chain.push_str(&format!("format=rgba,{}=aa={}[wm]", mixer, self.opacity));
+
+        let (x, y) = match (self.x, self.y) {
+            (Some(x), Some(y)) => (x.to_string(), y.to_string()),
+            _ => {
+                let m = self.margin;
+                let x = match self.position {
+                    OverlayPosition::TopLeft | OverlayPosition::BottomLeft => m.to_string(),
+                    OverlayPosition::TopCenter | OverlayPosition::BottomCenter => "(W-w)/2".to_string(),
+                    OverlayPosition::TopRight | OverlayPosition::BottomRight => format!("W-w-{}", m),
+                };
+                let y = match self.position {
+                    OverlayPosition::TopLeft | OverlayPosition::TopCenter | OverlayPosition::TopRight => m.to_string(),
+                    _ => format!("H-h-{}", m),
+                };
+                (x, y)
+            }
+        };
+        let shortest = if driven { ":shortest=1" } else { "" };
+        (image_chain, format!("[base][wm]overlay=x={}:y={}{}", x, y, shortest))
+    }
+}
+
+// Bundled overlay font, else the first system font that exists. None leaves the
+// choice to ffmpeg's fontconfig, which the Windows build may not have.
+pub fn resolve_font(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    let bundled = app_handle
+        .path()
+        .resolve(BUNDLED_FONT, tauri::path::BaseDirectory::Resource)
+        .ok()
+        .filter(|p| p.exists());
+    bundled.or_else(|| SYSTEM_FONTS.iter().map(PathBuf::from).find(|p| p.exists()))
+}
+
+// Escapes a value for use inside a single-quoted filter option. The graph
+// parser unquotes the value before the filter parses its options, so a quote
+// closes the quoting, comes through as \' for the option parser and reopens
+// it, and a colon stays \: for the option parser to unescape.
+pub fn escape_value(value: &str) -> String {
+    value.replace('\\', "/").replace(':', r"\:").replace('\'', r"'\\\''")
+}
+
+// "HH\:MM\:SS\:FF" for `seconds` at `fps`, as drawtext's `timecode` wants it
+fn timecode_start(seconds: f64, fps: f64) -> String {
+    let fps = fps.round().max(1.0) as u64;
+    let frames = (seconds.max(0.0) * fps as f64).round() as u64;
+    let (total_seconds, frame) = (frames / fps, frames % fps);
+    format!(
+        r"{:02}\:{:02}\:{:02}\:{:02}",
+        total_seconds / 3600,
+        total_seconds / 60 % 60,
+        total_seconds % 60,
+        frame
+    )
+}
+
+// drawtext filter showing a timecode that starts at `start_seconds` and
+// advances one frame per frame at `fps`
+pub fn timecode_filter(font: Option<&Path>, position: OverlayPosition, start_seconds: f64, fps: f64) -> String {
+    let (x, y) = position.xy();
+    let mut filter = String::from("drawtext=");
+    if let Some(font) = font {
+        filter.push_str(&format!("fontfile='{}':", escape_value(&font.to_string_lossy())));
+    }
+    filter.push_str(&format!(
+        "timecode='{}':rate={}:x={}:y={}:fontsize=h/20:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=6",
+        timecode_start(start_seconds, fps),
+        fps,
+        x,
+        y
+    ));
+    filter
+}
+
+// drawtext filter labelling each frame with its source timestamp (HH:MM:SS.mmm)
+pub fn timestamp_label_filter(font: Option<&Path>) -> String {
+    let mut filter = String::from("drawtext=");
+    if let Some(font) = font {
+        filter.push_str(&format!("fontfile='{}':", escape_value(&font.to_string_lossy())));
+    }
+    filter.push_str(r"text='%{pts\:hms}':x=4:y=h-text_h-4:fontsize=h/10:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=2");
+    filter
+}
+
+// drawtext filter stamping a faint "PROXY" across the middle of the frame, so
+// a proxy is never mistaken for the source
+pub fn proxy_label_filter(font: Option<&Path>) -> String {
+    let mut filter = String::from("drawtext=");
+    if let Some(font) = font {
+        filter.push_str(&format!("fontfile='{}':", escape_value(&font.to_string_lossy())));
+    }
+    filter.push_str("text='PROXY':x=(w-text_w)/2:y=(h-text_h)/2:fontsize=h/6:fontcolor=white@0.3");
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_quotes_and_colons_in_paths() {
+        assert_eq!(
+            escape_value(r"C:\Users\vj\Director's cut.srt"),
+            r"C\:/Users/vj/Director'\\\''s cut.srt"
+        );
+        assert_eq!(escape_value("/tmp/plain.txt"), "/tmp/plain.txt");
+    }
+}
@@ -0,0 +1,214 @@
+// Live timeline preview without a full export. ffmpeg decodes the timeline
+// from the playhead in real time (`-re`) as small MJPEG frames on stdout,
+// which are forwarded to the frontend as `preview-frame` events, and to the
+// NDI output and virtual camera when they are running. Seeking restarts the
+// preview at the new position.
+
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use tauri::{Emitter, Manager};
+
+use crate::ndi::NdiOutput;
+use crate::virtual_camera::VirtualCamera;
+use crate::{resolve_ffmpeg, ClipData};
+
+const DEFAULT_PREVIEW_WIDTH: u32 = 640;
+const DEFAULT_PREVIEW_FPS: u32 = 15;
+const MAX_PREVIEW_FPS: u32 = 60;
+
+// Payload of `preview-frame`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PreviewFrame {
+    // Timeline position of the frame, in seconds
+    position: f64,
+    data_url: String,
+}
+
+struct PreviewSession {
+    stop: Arc<AtomicBool>,
+    // The ffmpeg currently decoding, so stopping doesn't wait for it to finish
+    child: Arc<Mutex<Option<Child>>>,
+    thread: JoinHandle<()>,
+}
+
+impl PreviewSession {
+    fn shut_down(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(mut child) = self.child.lock().unwrap().take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        let _ = self.thread.join();
+    }
+}
+
+#[derive(Default)]
+pub struct PreviewState(Mutex<Option<PreviewSession>>);
+
+impl PreviewState {
+    // Stops the running preview; false if there was none
+    pub fn stop(&self) -> bool {
+        match self.0.lock().unwrap().take() {
+            Some(session) => {
+                session.shut_down();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Where each clip starts on the timeline
+fn clip_offsets(clips: &[ClipData]) -> Vec<f64> {
+    let mut offset = 0.0;
+    clips
+        .iter()
+        .map(|clip| {
+            let start = offset;
+            offset += clip.end_time - clip.start_time;
+            start
+        })
+        .collect()
+}
+
+// Pops the first complete JPEG (SOI .. EOI) off the front of `buf`
+fn take_jpeg(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let start = buf.windows(2).position(|w| w == [0xFF, 0xD8])?;
+    let end = buf[start + 2..].windows(2).position(|w| w == [0xFF, 0xD9])? + start + 4;
+    let frame = buf[start..end].to_vec();
+    buf.drain(..end);
+    Some(frame)
+}
+
+struct PreviewJob {
+    ffmpeg_path: PathBuf,
+    clips: Vec<ClipData>,
+    position: f64,
+    width: u32,
+    fps: u32,
+}
+
+fn run_preview(job: PreviewJob, stop: &AtomicBool, current: &Mutex<Option<Child>>, app_handle: &tauri::AppHandle) {
+    use base64::Engine;
+
+    let offsets = clip_offsets(&job.clips);
+    for (clip, &clip_offset) in job.clips.iter().zip(&offsets) {
+        let duration = clip.end_time - clip.start_time;
+        // Clips before the playhead are skipped, the one under it starts part-way
+        let skip = (job.position - clip_offset).max(0.0);
+        if skip >= duration {
+            continue;
+        }
+
+        let mut cmd = Command::new(&job.ffmpeg_path);
+        cmd.args([
+            "-re",
+            "-ss", &(clip.start_time + skip).to_string(),
+            "-t", &(duration - skip).to_string(),
+            "-i", &clip.file_path,
+            "-an",
+            "-vf", &format!("fps={},scale={}:-2", job.fps, job.width),
+            "-c:v", "mjpeg",
+            "-q:v", "5",
+            "-f", "image2pipe",
+            "-",
+        ]);
+        let Ok(mut child) = cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn() else {
+            return;
+        };
+        let Some(mut stdout) = child.stdout.take() else { return };
+        {
+            // A stop that raced the spawn would otherwise leave this child running
+            let mut current = current.lock().unwrap();
+            if stop.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            *current = Some(child);
+        }
+
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 64 * 1024];
+        let mut frame_index = 0u64;
+        // Ends when ffmpeg finishes the clip or is killed by a stop
+        while let Ok(read) = stdout.read(&mut chunk) {
+            if read == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..read]);
+            while let Some(jpeg) = take_jpeg(&mut buf) {
+                app_handle.state::<NdiOutput>().0.send_frame(&jpeg, app_handle);
+                app_handle.state::<VirtualCamera>().0.send_frame(&jpeg, app_handle);
+                let frame = PreviewFrame {
+                    position: clip_offset + skip + frame_index as f64 / job.fps as f64,
+                    data_url: format!(
+                        "data:image/jpeg;base64,{}",
+                        base64::engine::general_purpose::STANDARD.encode(&jpeg)
+                    ),
+                };
+                let _ = app_handle.emit("preview-frame", frame);
+                frame_index += 1;
+            }
+        }
+
+        if let Some(mut child) = current.lock().unwrap().take() {
+            let _ = child.wait();
+        }
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+    }
+    let _ = app_handle.emit("preview-ended", ());
+}
+
+// Command to play the timeline from `position` (seconds) as `preview-frame`
+// events. Calling it again while a preview runs seeks to the new position.
+#[tauri::command]
+pub async fn start_preview(
+    clips: Vec<ClipData>,
+    position: f64,
+    width: Option<u32>,
+    fps: Option<u32>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, PreviewState>,
+) -> Result<(), String> {
+    let width = width.unwrap_or(DEFAULT_PREVIEW_WIDTH);
+    let fps = fps.unwrap_or(DEFAULT_PREVIEW_FPS);
+    if width < 16 {
+        return Err(format!("Preview width must be at least 16, got {}", width));
+    }
+    if !(1..=MAX_PREVIEW_FPS).contains(&fps) {
+        return Err(format!("Preview fps must be between 1 and {}, got {}", MAX_PREVIEW_FPS, fps));
+    }
+    if position < 0.0 {
+        return Err(format!("Invalid preview position: {}", position));
+    }
+
+    let job = PreviewJob { ffmpeg_path: resolve_ffmpeg(&app_handle)?, clips, position, width, fps };
+
+    let mut session = state.0.lock().unwrap();
+    if let Some(previous) = session.take() {
+        previous.shut_down();
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let child = Arc::new(Mutex::new(None));
+    let thread = {
+        let (stop, child) = (stop.clone(), child.clone());
+        std::thread::spawn(move || run_preview(job, &stop, &child, &app_handle))
+    };
+    *session = Some(PreviewSession { stop, child, thread });
+    Ok(())
+}
+
+// Command to stop the running preview. Returns false if none was running.
+#[tauri::command]
+pub async fn stop_preview(state: tauri::State<'_, PreviewState>) -> Result<bool, String> {
+    Ok(state.stop())
+}
@@ -0,0 +1,681 @@
+// Media probing. Only ffmpeg is bundled (no ffprobe), so this reads the
+// stream summary `ffmpeg -hide_banner -i <file>` prints to stderr, e.g.
+//
+//   Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'clip.mp4':
+//     Duration: 00:00:10.01, start: 0.000000, bitrate: 1234 kb/s
+//     Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p(tv, bt709, progressive), 1920x1080 [SAR 1:1 DAR 16:9], 1000 kb/s, 29.97 fps, 29.97 tbr, 30k tbn (default)
+//     Stream #0:1[0x2](eng): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s (default)
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Data,
+    Attachment,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StreamInfo {
+    // Index within the file, as in `-map 0:{index}`
+    pub index: u32,
+    pub kind: StreamKind,
+    pub codec: String,
+    pub language: Option<String>,
+    // From the stream's metadata, e.g. "Director's commentary"
+    pub title: Option<String>,
+    // Flagged as the track players pick by default
+    pub is_default: bool,
+    // Video only
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fps: Option<f64>,
+    pub pix_fmt: Option<String>,
+    // "tv" (limited) or "pc" (full)
+    pub color_range: Option<String>,
+    // ffmpeg names, e.g. "bt709", "bt2020nc", "smpte2084"; "unknown" when
+    // the file leaves them unspecified
+    pub color_matrix: Option<String>,
+    pub color_primaries: Option<String>,
+    pub color_transfer: Option<String>,
+    // PQ or HLG transfer, which needs tone-mapping for an SDR export
+    pub is_hdr: bool,
+    // From the container's field order, or from analysing frames when
+    // probe_media is asked to; None if neither says. Interlaced sources
+    // should be deinterlaced on export.
+    pub interlaced: Option<bool>,
+    // Degrees from the display matrix side data (phones record portrait
+    // video as rotated landscape); ffmpeg applies it when decoding
+    pub rotation: Option<f64>,
+    // Audio only
+    pub sample_rate: Option<u32>,
+    pub channels: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaInfo {
+    // ffmpeg's demuxer names for the container, e.g. "mov,mp4,m4a,3gp,3g2,mj2"
+    pub format_name: Option<String>,
+    // For display, e.g. "MP4 (H.264 / AAC)"
+    pub summary: String,
+    // Seconds; None for streams without a known length
+    pub duration: Option<f64>,
+    // Timestamp of the first packet, in seconds; often non-zero in .ts/.mts
+    pub start_time: Option<f64>,
+    pub bitrate_kbps: Option<u32>,
+    pub streams: Vec<StreamInfo>,
+}
+
+impl StreamInfo {
+    // Width and height as displayed, after rotation
+    pub fn display_size(&self) -> Option<(u32, u32)> {
+        let (w, h) = (self.width?, self.height?);
+        let quarter_turn = self.rotation.is_some_and(|r| ((r.abs() + 90.0) % 180.0).abs() < 1.0);
+        Some(if quarter_turn { (h, w) } else { (w, h) })
+    }
+}
+
+impl MediaInfo {
+    pub fn first_video(&self) -> Option<&StreamInfo> {
+        self.streams.iter().find(|s| s.kind == StreamKind::Video)
+    }
+
+    pub fn first_audio(&self) -> Option<&StreamInfo> {
+        self.streams.iter().find(|s| s.kind == StreamKind::Audio)
+    }
+
+    // Moves the `kind` stream `index` (counting only that kind, as in
+    // `-map 0:a:{index}`) in front of the others of its kind, so first_video
+    // or first_audio is the stream a clip picked
+    pub fn select_stream(&mut self, kind: StreamKind, index: u32) -> Result<(), String> {
+        let positions: Vec<usize> = (0..self.streams.len()).filter(|&i| self.streams[i].kind == kind).collect();
+        let Some(&position) = positions.get(index as usize) else {
+            let name = match kind {
+                StreamKind::Video => "video",
+                StreamKind::Audio => "audio",
+                StreamKind::Subtitle => "subtitle",
+                StreamKind::Data => "data",
+                StreamKind::Attachment => "attachment",
+            };
+            return Err(format!("there is no {} stream {}, the file has {}", name, index, positions.len()));
+        };
+        let stream = self.streams.remove(position);
+        self.streams.insert(positions[0], stream);
+        Ok(())
+    }
+}
+
+pub fn probe(ffmpeg_path: &Path, file_path: &str) -> Result<MediaInfo, String> {
+    // With no output file ffmpeg exits with an error after printing the
+    // summary, so the exit status says nothing about the input
+    let output = Command::new(ffmpeg_path)
+        .args(["-hide_banner", "-i", file_path])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("FFmpeg probe failed: {}", e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.contains("Input #0") {
+        let reason = stderr.lines().last().unwrap_or("unknown error").trim();
+        return Err(format!("Could not read media file {}: {}", file_path, reason));
+    }
+    let mut info = parse_probe_output(&stderr);
+    info.summary = summary(&info, file_path);
+    Ok(info)
+}
+
+fn parse_probe_output(stderr: &str) -> MediaInfo {
+    let mut info = MediaInfo {
+        format_name: None,
+        summary: String::new(),
+        duration: None,
+        start_time: None,
+        bitrate_kbps: None,
+        streams: Vec::new(),
+    };
+
+    for line in stderr.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("Input #0, ") {
+            // "mov,mp4,m4a,3gp,3g2,mj2, from 'clip.mp4':"
+            info.format_name = rest.split_once(", from ").map(|(names, _)| names.to_string());
+        } else if let Some(rest) = line.strip_prefix("Duration: ") {
+            for field in rest.split(", ") {
+                if let Some(bitrate) = field.strip_prefix("bitrate: ") {
+                    info.bitrate_kbps = bitrate.trim_end_matches(" kb/s").parse().ok();
+                } else if let Some(start) = field.strip_prefix("start: ") {
+                    info.start_time = start.trim().parse().ok();
+                } else if !field.contains(':') {
+                    continue;
+                } else {
+                    info.duration = parse_timestamp(field);
+                }
+            }
+        } else if let Some(rest) = line.strip_prefix("Stream #0:") {
+            if let Some(stream) = parse_stream(rest) {
+                info.streams.push(stream);
+            }
+        } else if let Some(title) = line.strip_prefix("title").and_then(|r| r.trim_start().strip_prefix(':')) {
+            // Stream metadata comes after its stream line; the file's own
+            // (before any stream) is left out
+            if let Some(stream) = info.streams.last_mut() {
+                stream.title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+            }
+        } else if let Some(rest) = line.strip_prefix("displaymatrix: rotation of ") {
+            // Side data, printed under the stream it belongs to
+            if let Some(stream) = info.streams.last_mut() {
+                stream.rotation = rest.trim_end_matches(" degrees").parse().ok();
+            }
+        }
+    }
+    info
+}
+
+// "0[0x1](und): Video: h264 (High), yuv420p(tv, bt709), 1920x1080, 29.97 fps"
+fn parse_stream(rest: &str) -> Option<StreamInfo> {
+    let index_len = rest.find(|c: char| !c.is_ascii_digit())?;
+    let index = rest[..index_len].parse().ok()?;
+
+    let (head, description) = rest.split_once(": ")?;
+    let language = head
+        .find('(')
+        .and_then(|open| head[open + 1..].split(')').next())
+        .filter(|lang| !lang.is_empty() && *lang != "und")
+        .map(str::to_string);
+
+    let (kind, details) = description.split_once(": ")?;
+    let kind = match kind {
+        "Video" => StreamKind::Video,
+        "Audio" => StreamKind::Audio,
+        "Subtitle" => StreamKind::Subtitle,
+        "Attachment" => StreamKind::Attachment,
+        _ => StreamKind::Data,
+    };
+
+    let fields = split_top_level(details);
+    let codec = fields
+        .first()
+        .and_then(|f| f.split_whitespace().next())
+        .unwrap_or("unknown")
+        .trim_end_matches(',')
+        .to_string();
+
+    let mut stream = StreamInfo {
+        index,
+        kind,
+        codec,
+        language,
+        title: None,
+        is_default: details.contains("(default)"),
+        width: None,
+        height: None,
+        fps: None,
+        pix_fmt: None,
+        color_range: None,
+        color_matrix: None,
+        color_primaries: None,
+        color_transfer: None,
+        is_hdr: false,
+        interlaced: None,
+        rotation: None,
+        sample_rate: None,
+        channels: None,
+    };
+
+    match kind {
+        StreamKind::Video => {
+            if let Some(pix_fmt) = fields.get(1) {
+                let name = pix_fmt.split('(').next().unwrap_or(pix_fmt).trim();
+                stream.pix_fmt = Some(name.to_string());
+                parse_color(pix_fmt, &mut stream);
+            }
+            for field in &fields[1..] {
+                if let Some(fps) = field.strip_suffix(" fps") {
+                    stream.fps = parse_rate(fps);
+                } else if stream.width.is_none() {
+                    let size = field.split_whitespace().next().unwrap_or("");
+                    if let Some((w, h)) = size.split_once('x') {
+                        if let (Ok(w), Ok(h)) = (w.parse(), h.parse()) {
+                            stream.width = Some(w);
+                            stream.height = Some(h);
+                        }
+                    }
+                }
+            }
+        }
+        StreamKind::Audio => {
+            for field in &fields[1..] {
+                if let Some(rate) = field.strip_suffix(" Hz") {
+                    stream.sample_rate = rate.parse().ok();
+                } else if stream.sample_rate.is_some() && stream.channels.is_none() {
+                    // The field right after the sample rate is the layout
+                    stream.channels = Some(field.to_string());
+                }
+            }
+        }
+        _ => {}
+    }
+    Some(stream)
+}
+
+// Display name of an ffmpeg codec name
+fn codec_display_name(codec: &str) -> String {
+    let name = match codec {
+        "h264" => "H.264",
+        "hevc" => "H.265",
+        "av1" => "AV1",
+        "vp8" => "VP8",
+        "vp9" => "VP9",
+        "prores" => "ProRes",
+        "dnxhd" => "DNxHD",
+        "mpeg2video" => "MPEG-2",
+        "mpeg4" => "MPEG-4",
+        "mjpeg" => "Motion JPEG",
+        "aac" => "AAC",
+        "mp3" => "MP3",
+        "opus" => "Opus",
+        "vorbis" => "Vorbis",
+        "flac" => "FLAC",
+        "ac3" => "AC-3",
+        "eac3" => "E-AC-3",
+        "subrip" => "SRT",
+        "mov_text" => "MP4 text",
+        "ass" => "ASS",
+        _ if codec.starts_with("pcm_") => "PCM",
+        _ => return codec.to_ascii_uppercase(),
+    };
+    name.to_string()
+}
+
+// "MP4 (H.264 / AAC)": the container, named after the file's extension when
+// that is one of the demuxer's names (the mov demuxer reads .mp4 too), and
+// each distinct codec of its video, audio and subtitle streams
+fn summary(info: &MediaInfo, file_path: &str) -> String {
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let names: Vec<&str> = info.format_name.as_deref().map_or(Vec::new(), |n| n.split(',').collect());
+    let container = match extension {
+        Some(ext) if names.contains(&ext.as_str()) => ext,
+        _ => names.first().map_or("unknown".to_string(), |n| n.to_string()),
+    };
+
+    let mut codecs: Vec<String> = Vec::new();
+    for stream in &info.streams {
+        if !matches!(stream.kind, StreamKind::Video | StreamKind::Audio | StreamKind::Subtitle) {
+            continue;
+        }
+        let name = codec_display_name(&stream.codec);
+        if !codecs.contains(&name) {
+            codecs.push(name);
+        }
+    }
+    match codecs.is_empty() {
+        true => container.to_ascii_uppercase(),
+        false => format!("{} ({})", container.to_ascii_uppercase(), codecs.join(" / ")),
+    }
+}
+
+// Transfer functions of HDR video: PQ (HDR10, Dolby Vision) and HLG
+const HDR_TRANSFERS: &[&str] = &["smpte2084", "arib-std-b67"];
+
+// Color details from the pixel format field, e.g.
+// "yuv420p10le(tv, bt2020nc/bt2020/smpte2084, progressive)". ffmpeg prints
+// matrix/primaries/transfer, or a single name when all three are the same.
+fn parse_color(pix_fmt: &str, stream: &mut StreamInfo) {
+    let Some(details) = pix_fmt.split_once('(').and_then(|(_, d)| d.strip_suffix(')')) else {
+        return;
+    };
+    for item in details.split(", ").map(str::trim) {
+        match item {
+            "tv" | "pc" => stream.color_range = Some(item.to_string()),
+            // Field order
+            "progressive" => stream.interlaced = Some(false),
+            _ if item.starts_with("top") || item.starts_with("bottom") => stream.interlaced = Some(true),
+            _ => {
+                let parts: Vec<&str> = item.split('/').collect();
+                let (matrix, primaries, transfer) = match parts[..] {
+                    [matrix, primaries, transfer] => (matrix, primaries, transfer),
+                    _ => (item, item, item),
+                };
+                stream.color_matrix = Some(matrix.to_string());
+                stream.color_primaries = Some(primaries.to_string());
+                stream.color_transfer = Some(transfer.to_string());
+                stream.is_hdr = HDR_TRANSFERS.contains(&transfer);
+            }
+        }
+    }
+}
+
+// "29.97", "30", "23.98" or "30k" as ffmpeg prints frame rates
+fn parse_rate(rate: &str) -> Option<f64> {
+    match rate.strip_suffix('k') {
+        Some(thousands) => thousands.parse::<f64>().ok().map(|r| r * 1000.0),
+        None => rate.parse().ok(),
+    }
+}
+
+// "00:01:02.50" -> 62.5
+fn parse_timestamp(stamp: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    for part in stamp.trim().split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+// Splits on commas that aren't inside (...) or [...]
+fn split_top_level(s: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut depth = 0i32;
+    let mut current = String::new();
+    for c in s.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                fields.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        fields.push(current.trim().to_string());
+    }
+    fields
+}
+
+// Result of `get_frame_count`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FrameCount {
+    pub frames: u64,
+    pub fps: f64,
+    // True when derived from duration x fps rather than counted
+    pub estimated: bool,
+}
+
+// Number of frames ffmpeg decodes from the first video stream. Reads the last
+// `frame=` of the stats output, which is the final total.
+fn count_frames(ffmpeg_path: &Path, file_path: &str) -> Result<u64, String> {
+    let output = Command::new(ffmpeg_path)
+        .args(["-hide_banner", "-i", file_path, "-map", "0:v:0", "-an", "-f", "null", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("FFmpeg frame count failed: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("FFmpeg could not decode {}", file_path));
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let last = stderr.rfind("frame=").ok_or("FFmpeg reported no frames")?;
+    stderr[last + "frame=".len()..]
+        .split_whitespace()
+        .next()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| "Could not read the frame count".to_string())
+}
+
+// Command to get a file's video frame count and fps, for converting between
+// seconds and frame indices. The default fast mode estimates from duration x
+// fps; `accurate` counts by decoding the whole file.
+#[tauri::command]
+pub async fn get_frame_count(
+    file_path: String,
+    accurate: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> Result<FrameCount, String> {
+    let ffmpeg_path = crate::resolve_ffmpeg(&app_handle)?;
+    let info = probe(&ffmpeg_path, &file_path)?;
+    let fps = info
+        .first_video()
+        .and_then(|v| v.fps)
+        .ok_or("File has no video stream with a known frame rate")?;
+
+    if accurate.unwrap_or(false) {
+        let frames = count_frames(&ffmpeg_path, &file_path)?;
+        return Ok(FrameCount { frames, fps, estimated: false });
+    }
+    let duration = info.duration.ok_or("Could not determine the file's duration")?;
+    Ok(FrameCount { frames: (duration * fps).round() as u64, fps, estimated: true })
+}
+
+// Command to read a file's duration and streams, including the color details
+// that tell HDR footage apart. `detect_interlacing` analyses the first
+// seconds of video instead of trusting the container's field order, which
+// many DV and broadcast files get wrong or leave out.
+#[tauri::command]
+pub async fn probe_media(
+    file_path: String,
+    detect_interlacing: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> Result<MediaInfo, String> {
+    let ffmpeg_path = crate::resolve_ffmpeg(&app_handle)?;
+    let mut info = probe(&ffmpeg_path, &file_path)?;
+    if detect_interlacing.unwrap_or(false) {
+        if let Some(video) = info.streams.iter_mut().find(|s| s.kind == StreamKind::Video) {
+            video.interlaced = Some(crate::analysis::detect_interlacing(&ffmpeg_path, &file_path)?);
+        }
+    }
+    Ok(info)
+}
+
+// What stat_file found at a path
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FileStat {
+    pub exists: bool,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    // Last modification, in milliseconds since the Unix epoch
+    pub modified_ms: Option<u64>,
+    // Whether ffmpeg reads the file as media with an audio or video stream;
+    // None when not checked
+    pub is_media: Option<bool>,
+}
+
+// Command to read a path's size and modification time and, unless
+// `check_media` is false, whether it is readable media. A 0-byte placeholder
+// or a file that isn't media any more shows up here on project load, where
+// validate_file_path only says the path exists. A missing path is not an
+// error, just `exists: false`.
+#[tauri::command]
+pub async fn stat_file(
+    path: String,
+    check_media: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> Result<FileStat, String> {
+    let Ok(metadata) = std::fs::metadata(&path) else {
+        return Ok(FileStat::default());
+    };
+    let modified_ms = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64);
+    let is_media = match check_media.unwrap_or(true) {
+        false => None,
+        true if metadata.is_dir() || metadata.len() == 0 => Some(false),
+        true => {
+            let ffmpeg_path = crate::resolve_ffmpeg(&app_handle)?;
+            Some(probe(&ffmpeg_path, &path).is_ok_and(|info| {
+                info.streams.iter().any(|s| matches!(s.kind, StreamKind::Video | StreamKind::Audio))
+            }))
+        }
+    };
+    Ok(FileStat {
+        exists: true,
+        is_dir: metadata.is_dir(),
+        size: Some(metadata.len()),
+        modified_ms,
+        is_media,
+    })
+}
+
+// Command to list a file's streams with their index (as `-map 0:{index}`
+// takes it), codec, language and title, e.g. to let the user pick one of
+// several audio languages. `kind` keeps only streams of that type.
+#[tauri::command]
+pub async fn list_streams(
+    file_path: String,
+    kind: Option<StreamKind>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<StreamInfo>, String> {
+    let ffmpeg_path = crate::resolve_ffmpeg(&app_handle)?;
+    let streams = probe(&ffmpeg_path, &file_path)?.streams;
+    Ok(streams.into_iter().filter(|s| kind.is_none_or(|kind| s.kind == kind)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A phone recording: rotated, with the side data after the stream metadata
+    const PHONE_MOV: &str = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'IMG_0042.MOV':
+  Metadata:
+    major_brand     : qt
+    minor_version   : 0
+    creation_time   : 2024-05-01T18:22:03.000000Z
+  Duration: 00:00:12.48, start: 0.000000, bitrate: 18512 kb/s
+  Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p(tv, bt709, progressive), 1920x1080, 18180 kb/s, 29.97 fps, 29.97 tbr, 600 tbn (default)
+    Metadata:
+      creation_time   : 2024-05-01T18:22:03.000000Z
+      handler_name    : Core Media Video
+      vendor_id       : [0][0][0][0]
+    Side data:
+      displaymatrix: rotation of -90.00 degrees
+  Stream #0:1[0x2](und): Audio: aac (LC) (mp4a / 0x6134706D), 44100 Hz, mono, fltp, 91 kb/s (default)
+    Metadata:
+      creation_time   : 2024-05-01T18:22:03.000000Z
+      handler_name    : Core Media Audio
+      vendor_id       : [0][0][0][0]
+At least one output file must be specified
+";
+
+    // HDR10 with two audio tracks, subtitles and a cover image
+    const HDR_MKV: &str = "\
+Input #0, matroska,webm, from 'hdr.mkv':
+  Metadata:
+    title           : Demo reel
+    ENCODER         : Lavf60.16.100
+  Duration: 00:01:02.50, start: 0.007000, bitrate: 24316 kb/s
+  Stream #0:0: Video: hevc (Main 10), yuv420p10le(tv, bt2020nc/bt2020/smpte2084), 3840x2160 [SAR 1:1 DAR 16:9], 23.98 fps, 23.98 tbr, 1k tbn (default)
+    Metadata:
+      DURATION        : 00:01:02.502000000
+  Stream #0:1(jpn): Audio: eac3, 48000 Hz, 5.1(side), fltp, 640 kb/s (default)
+    Metadata:
+      title           : Japanese 5.1
+  Stream #0:2(eng): Audio: aac (LC), 48000 Hz, stereo, fltp
+    Metadata:
+      title           : Commentary
+  Stream #0:3(eng): Subtitle: subrip
+  Stream #0:4: Video: mjpeg (Baseline), yuvj420p(pc, bt470bg/unknown/unknown), 600x600 [SAR 1:1 DAR 1:1], 90k tbr, 90k tbn (attached pic)
+    Metadata:
+      filename        : cover.jpg
+      mimetype        : image/jpeg
+At least one output file must be specified
+";
+
+    const SONG_MP3: &str = "\
+Input #0, mp3, from 'song.mp3':
+  Metadata:
+    title           : Night Drive
+    artist          : Somebody
+  Duration: 00:03:25.12, start: 0.025056, bitrate: 320 kb/s
+  Stream #0:0: Audio: mp3 (mp3float), 44100 Hz, stereo, fltp, 320 kb/s
+At least one output file must be specified
+";
+
+    #[test]
+    fn reads_a_rotated_phone_clip() {
+        let info = parse_probe_output(PHONE_MOV);
+        assert_eq!(info.format_name.as_deref(), Some("mov,mp4,m4a,3gp,3g2,mj2"));
+        assert_eq!(info.duration, Some(12.48));
+        assert_eq!(info.start_time, Some(0.0));
+        assert_eq!(info.bitrate_kbps, Some(18512));
+        assert_eq!(info.streams.len(), 2);
+
+        let video = info.first_video().unwrap();
+        assert_eq!((video.index, video.codec.as_str(), video.language.as_deref()), (0, "h264", None));
+        assert_eq!((video.width, video.height, video.fps), (Some(1920), Some(1080), Some(29.97)));
+        assert_eq!(video.pix_fmt.as_deref(), Some("yuv420p"));
+        assert_eq!(video.color_range.as_deref(), Some("tv"));
+        assert_eq!(video.color_transfer.as_deref(), Some("bt709"));
+        assert_eq!(video.interlaced, Some(false));
+        assert!(!video.is_hdr && video.is_default);
+        assert_eq!(video.rotation, Some(-90.0));
+        assert_eq!(video.display_size(), Some((1080, 1920)));
+
+        let audio = info.first_audio().unwrap();
+        assert_eq!((audio.index, audio.codec.as_str()), (1, "aac"));
+        assert_eq!((audio.sample_rate, audio.channels.as_deref()), (Some(44100), Some("mono")));
+        assert_eq!(audio.rotation, None);
+    }
+
+    #[test]
+    fn reads_hdr_color_and_every_stream() {
+        let info = parse_probe_output(HDR_MKV);
+        assert_eq!(info.duration, Some(62.5));
+        assert_eq!(info.start_time, Some(0.007));
+        let kinds: Vec<StreamKind> = info.streams.iter().map(|s| s.kind).collect();
+        assert_eq!(
+            kinds,
+            [StreamKind::Video, StreamKind::Audio, StreamKind::Audio, StreamKind::Subtitle, StreamKind::Video]
+        );
+
+        let video = &info.streams[0];
+        assert_eq!(video.codec, "hevc");
+        assert_eq!((video.width, video.height, video.fps), (Some(3840), Some(2160), Some(23.98)));
+        assert_eq!(video.pix_fmt.as_deref(), Some("yuv420p10le"));
+        assert_eq!(video.color_matrix.as_deref(), Some("bt2020nc"));
+        assert_eq!(video.color_primaries.as_deref(), Some("bt2020"));
+        assert_eq!(video.color_transfer.as_deref(), Some("smpte2084"));
+        assert!(video.is_hdr);
+        assert_eq!(video.interlaced, None);
+        // The file's own title isn't a stream's
+        assert_eq!(video.title, None);
+
+        let (japanese, commentary) = (&info.streams[1], &info.streams[2]);
+        assert_eq!(japanese.language.as_deref(), Some("jpn"));
+        assert_eq!(japanese.channels.as_deref(), Some("5.1(side)"));
+        assert_eq!(japanese.title.as_deref(), Some("Japanese 5.1"));
+        assert!(japanese.is_default);
+        assert_eq!((commentary.codec.as_str(), commentary.sample_rate), ("aac", Some(48000)));
+        assert_eq!(commentary.title.as_deref(), Some("Commentary"));
+        assert!(!commentary.is_default);
+
+        assert_eq!(info.streams[3].codec, "subrip");
+        let cover = &info.streams[4];
+        assert_eq!((cover.codec.as_str(), cover.width, cover.fps), ("mjpeg", Some(600), None));
+        assert_eq!(cover.color_matrix.as_deref(), Some("bt470bg"));
+        assert!(!cover.is_hdr);
+
+        let mut picked = info.clone();
+        picked.select_stream(StreamKind::Audio, 1).unwrap();
+        assert_eq!(picked.first_audio().unwrap().index, 2);
+        assert!(picked.select_stream(StreamKind::Audio, 2).is_err());
+        assert_eq!(summary(&info, "hdr.mkv"), "MATROSKA (H.265 / E-AC-3 / AAC / SRT / Motion JPEG)");
+    }
+
+    #[test]
+    fn reads_an_audio_only_file() {
+        let info = parse_probe_output(SONG_MP3);
+        assert_eq!(info.format_name.as_deref(), Some("mp3"));
+        assert_eq!(info.duration, Some(205.12));
+        assert_eq!(info.start_time, Some(0.025056));
+        assert!(info.first_video().is_none());
+        assert_eq!(info.streams.len(), 1);
+        let audio = info.first_audio().unwrap();
+        assert_eq!((audio.codec.as_str(), audio.sample_rate), ("mp3", Some(44100)));
+        assert_eq!(audio.channels.as_deref(), Some("stereo"));
+        assert_eq!(audio.title, None);
+        assert_eq!(summary(&info, "song.mp3"), "MP3 (MP3)");
+    }
+}
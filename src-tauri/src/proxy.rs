@@ -0,0 +1,421 @@
+// Low-resolution proxy videos for smooth preview playback. Proxies are
+// regenerated only when missing or older than their source.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tauri::{Emitter, Manager};
+
+use crate::hwaccel::{self, GpuFrames};
+use crate::{overlay, probe, resolve_ffmpeg, tasks, JobStarted, ProcessRegistry, JOB_CANCELLED_ERROR};
+
+// Decode methods accepted for `-hwaccel`; "auto" lets ffmpeg pick one
+const HWACCEL_METHODS: &[&str] = &["auto", "cuda", "qsv", "videotoolbox", "d3d11va", "dxva2", "vaapi"];
+
+fn validate_hwaccel(hwaccel: Option<&str>) -> Result<(), String> {
+    if let Some(method) = hwaccel {
+        if !HWACCEL_METHODS.contains(&method) {
+            return Err(format!(
+                "Unsupported hwaccel '{}', expected one of: {}",
+                method,
+                HWACCEL_METHODS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ProxySize {
+    // Every proxy exactly this size, letterboxed to fit
+    Fixed { width: u32, height: u32 },
+    // The longest side scaled down to this, keeping the source's aspect
+    MaxDimension(u32),
+}
+
+impl ProxySize {
+    // `max_dimension` wins when both it and a fixed size are given
+    fn from_args(width: Option<u32>, height: Option<u32>, max_dimension: Option<u32>) -> Result<Self, String> {
+        let size = match (max_dimension, width, height) {
+            (Some(max), _, _) => ProxySize::MaxDimension(max),
+            (None, Some(width), Some(height)) => ProxySize::Fixed { width, height },
+            _ => return Err("Proxy size needs max_dimension, or both width and height".to_string()),
+        };
+        let too_small = match size {
+            ProxySize::Fixed { width, height } => width < 2 || height < 2,
+            ProxySize::MaxDimension(max) => max < 2,
+        };
+        if too_small {
+            return Err("Proxy dimensions must be at least 2 pixels".to_string());
+        }
+        Ok(size)
+    }
+}
+
+// Nearest even number, as the encoder needs for 4:2:0 video
+fn even(value: f64) -> u32 {
+    ((value / 2.0).round() as u32 * 2).max(2)
+}
+
+// How proxies are encoded; shared by every file of a batch
+#[derive(Debug, Clone)]
+struct ProxySettings {
+    size: ProxySize,
+    bitrate: String,
+    hwaccel: Option<String>,
+    // Stamp "PROXY" on the picture, in this font (None for ffmpeg's choice)
+    label: Option<Option<PathBuf>>,
+}
+
+fn source_size(ffmpeg_path: &Path, input_path: &str) -> Result<(u32, u32), String> {
+    probe::probe(ffmpeg_path, input_path)?
+        .first_video()
+        .and_then(|v| v.display_size())
+        .ok_or_else(|| format!("Couldn't read the frame size of {}", input_path))
+}
+
+// Scale filter for one source. In max_dimension mode the source is probed so
+// a portrait clip gets a portrait proxy; it is never scaled up.
+fn scale_filter(ffmpeg_path: &Path, input_path: &str, size: ProxySize) -> Result<String, String> {
+    match size {
+        ProxySize::Fixed { width, height } => Ok(format!(
+            "scale={}:{}:force_original_aspect_ratio=decrease,pad={}:{}:(ow-iw)/2:(oh-ih)/2",
+            width, height, width, height
+        )),
+        ProxySize::MaxDimension(max) => {
+            let (w, h) = source_size(ffmpeg_path, input_path)?;
+            let factor = (max as f64 / w.max(h) as f64).min(1.0);
+            Ok(format!("scale={}:{}", even(w as f64 * factor), even(h as f64 * factor)))
+        }
+    }
+}
+
+// The same scale as scale_filter, done on the GPU frames of `gpu`. GPU scale
+// filters only take an exact size, so the source is always probed, and a fixed
+// size is padded once the frames are back in system memory.
+fn gpu_scale_filter(ffmpeg_path: &Path, input_path: &str, size: ProxySize, gpu: GpuFrames) -> Result<String, String> {
+    let (w, h) = source_size(ffmpeg_path, input_path)?;
+    let filter = match size {
+        ProxySize::Fixed { width, height } => {
+            let factor = (width as f64 / w as f64).min(height as f64 / h as f64);
+            let (scaled_width, scaled_height) =
+                (even(w as f64 * factor).min(width), even(h as f64 * factor).min(height));
+            format!(
+                "{},{},pad={}:{}:(ow-iw)/2:(oh-ih)/2",
+                gpu.scale_filter(scaled_width, scaled_height),
+                hwaccel::DOWNLOAD_FILTER,
+                width,
+                height
+            )
+        }
+        ProxySize::MaxDimension(max) => {
+            let factor = (max as f64 / w.max(h) as f64).min(1.0);
+            let scale = gpu.scale_filter(even(w as f64 * factor), even(h as f64 * factor));
+            format!("{},{}", scale, hwaccel::DOWNLOAD_FILTER)
+        }
+    };
+    Ok(filter)
+}
+
+// "2M", "1500k" or plain bits per second, in kb/s
+pub fn bitrate_kbps(bitrate: &str) -> Option<f64> {
+    let bitrate = bitrate.trim();
+    match bitrate.chars().last()? {
+        'k' | 'K' => bitrate[..bitrate.len() - 1].parse().ok(),
+        'm' | 'M' => bitrate[..bitrate.len() - 1].parse::<f64>().ok().map(|m| m * 1000.0),
+        _ => bitrate.parse::<f64>().ok().map(|b| b / 1000.0),
+    }
+}
+
+// Whether the source is already as light as its proxy would be: H.264 (which
+// the preview plays smoothly), no larger than the proxy size and at no more
+// than the proxy bitrate. Unknown details count against it.
+fn is_light(ffmpeg_path: &Path, input_path: &str, settings: &ProxySettings) -> Result<bool, String> {
+    let info = probe::probe(ffmpeg_path, input_path)?;
+    let Some(video) = info.first_video() else { return Ok(false) };
+    let Some((w, h)) = video.display_size() else { return Ok(false) };
+    let fits = match settings.size {
+        ProxySize::Fixed { width, height } => w <= width && h <= height,
+        ProxySize::MaxDimension(max) => w.max(h) <= max,
+    };
+    let light_bitrate = match (info.bitrate_kbps, bitrate_kbps(&settings.bitrate)) {
+        (Some(source), Some(proxy)) => source as f64 <= proxy,
+        _ => false,
+    };
+    Ok(video.codec == "h264" && fits && light_bitrate)
+}
+
+// A proxy is current when it exists, isn't empty and was written after the
+// source last changed
+fn is_current(input_path: &str, output_path: &str) -> bool {
+    let modified = |path: &str| std::fs::metadata(path).and_then(|m| Ok((m.len(), m.modified()?))).ok();
+    match (modified(input_path), modified(output_path)) {
+        (Some((_, source)), Some((size, proxy))) => size > 0 && proxy >= source,
+        _ => false,
+    }
+}
+
+// Encodes one proxy as part of `job_id`. A cancelled or failed encode leaves
+// no partial file behind.
+fn encode_proxy(
+    registry: &ProcessRegistry,
+    job_id: &str,
+    ffmpeg_path: &Path,
+    input_path: &str,
+    output_path: &str,
+    settings: &ProxySettings,
+) -> Result<(), String> {
+    // Ensure output directory exists
+    if let Some(parent) = Path::new(output_path).parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create proxy dir: {}", e))?;
+    }
+
+    // FFmpeg command for generating proxy:
+    // - Optionally decode on the GPU, also scaling there when the hwaccel can
+    //   (the encode stays on the CPU)
+    // - Scale to target resolution
+    // - Use fast encoding preset
+    // - Lower bitrate for smaller file size
+    let run_proxy = |hwaccel: Option<&str>| {
+        let mut cmd = Command::new(ffmpeg_path);
+        cmd.arg("-y");
+        let gpu = hwaccel.and_then(hwaccel::gpu_frames);
+        let mut scale = match (hwaccel, gpu) {
+            (_, Some(gpu)) => {
+                cmd.args(gpu.decode_args());
+                gpu_scale_filter(ffmpeg_path, input_path, settings.size, gpu)?
+            }
+            (Some(method), None) => {
+                cmd.args(["-hwaccel", method]);
+                scale_filter(ffmpeg_path, input_path, settings.size)?
+            }
+            (None, None) => scale_filter(ffmpeg_path, input_path, settings.size)?,
+        };
+        if let Some(font) = &settings.label {
+            // Sized to the proxy, not the source
+            scale.push(',');
+            scale.push_str(&overlay::proxy_label_filter(font.as_deref()));
+        }
+        cmd.args([
+            "-i", input_path,
+            "-vf", &scale,
+            "-c:v", "libx264",
+            "-preset", "ultrafast",
+            "-b:v", &settings.bitrate,
+            "-c:a", "aac",
+            "-b:a", "96k",
+            "-movflags", "+faststart", // Enable fast start for streaming
+            output_path,
+        ]);
+        registry
+            .run(job_id, &mut cmd)
+            .map_err(|e| format!("FFmpeg proxy generation failed: {}", e))
+    };
+
+    let mut status = run_proxy(settings.hwaccel.as_deref());
+
+    // A missing driver or unsupported codec makes hwaccel init (or the GPU
+    // scale) fail outright, so retry once with plain software decode and scale
+    if status.as_ref().is_ok_and(|s| !s.success()) && settings.hwaccel.is_some() {
+        status = run_proxy(None);
+    }
+
+    // A killed encode leaves a truncated file behind
+    if registry.is_cancelled(job_id) {
+        let _ = std::fs::remove_file(output_path);
+        return Err(JOB_CANCELLED_ERROR.to_string());
+    }
+    if !status?.success() {
+        let _ = std::fs::remove_file(output_path);
+        return Err("FFmpeg proxy generation exited with error".to_string());
+    }
+    Ok(())
+}
+
+// Result of `generate_proxy_video`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyResult {
+    // The proxy, or the source itself when it was skipped
+    path: String,
+    // The source is already no heavier than the proxy would be, so no proxy
+    // was made and `path` is the source
+    skipped: bool,
+}
+
+// Command to generate a low-quality proxy video for fast preview. Give
+// `width` and `height` for a fixed, letterboxed size, or `max_dimension` to
+// keep each source's aspect. A source that is already H.264 at or under the
+// proxy size and bitrate is used as is rather than re-encoded. `label` stamps
+// a faint "PROXY" on the picture, so an export can't silently come from one.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_proxy_video(
+    input_path: String,
+    output_path: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    max_dimension: Option<u32>,
+    bitrate: String,
+    hwaccel: Option<String>,
+    label: Option<bool>,
+    job_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<ProxyResult, String> {
+    validate_hwaccel(hwaccel.as_deref())?;
+    let settings = ProxySettings {
+        size: ProxySize::from_args(width, height, max_dimension)?,
+        bitrate,
+        hwaccel,
+        label: label.unwrap_or(false).then(|| overlay::resolve_font(&app_handle)),
+    };
+
+    // Resolve FFmpeg path
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    if is_light(&ffmpeg_path, &input_path, &settings)? {
+        return Ok(ProxyResult { path: input_path, skipped: true });
+    }
+
+    // Announce the id before the long encode so the UI can offer a cancel
+    let job_id = job_id.unwrap_or_else(|| registry.new_job_id("proxy"));
+    let _job = registry.begin(&job_id)?;
+    let _ = app_handle.emit("job-started", JobStarted { job_id: job_id.clone(), kind: "proxy".to_string() });
+
+    encode_proxy(&registry, &job_id, &ffmpeg_path, &input_path, &output_path, &settings)?;
+    Ok(ProxyResult { path: output_path, skipped: false })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyRequest {
+    input_path: String,
+    output_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ProxyStatus {
+    Started,
+    Created,
+    Skipped,
+    Failed,
+}
+
+// Payload of `proxy-status`, sent as each file of a project batch starts and
+// finishes. `completed` of `total` files are done, for an overall progress bar.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ProxyStatusEvent {
+    input_path: String,
+    status: ProxyStatus,
+    error: Option<String>,
+    completed: usize,
+    total: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyFailure {
+    input_path: String,
+    error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProxySummary {
+    created: Vec<String>,
+    skipped: Vec<String>,
+    failed: Vec<ProxyFailure>,
+}
+
+// Command to bring the proxies of a whole project up to date. Files whose
+// proxy is already current are skipped; the rest are encoded in the export
+// slots (see set_max_concurrent_exports), as each is a full decode and encode.
+// Like the thumbnail batch this is one job for cancel_job, and a failed file
+// doesn't stop the others.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_project_proxies(
+    media: Vec<ProxyRequest>,
+    width: Option<u32>,
+    height: Option<u32>,
+    max_dimension: Option<u32>,
+    bitrate: String,
+    hwaccel: Option<String>,
+    job_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<ProxySummary, String> {
+    validate_hwaccel(hwaccel.as_deref())?;
+    let size = ProxySize::from_args(width, height, max_dimension)?;
+    let settings = Arc::new(ProxySettings { size, bitrate, hwaccel, label: None });
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+
+    let job_id = job_id.unwrap_or_else(|| registry.new_job_id("proxies"));
+    let _job = registry.begin(&job_id)?;
+    let _ = app_handle.emit("job-started", JobStarted { job_id: job_id.clone(), kind: "proxies".to_string() });
+
+    let total = media.len();
+    let completed = Arc::new(AtomicUsize::new(0));
+    let semaphore = app_handle.state::<tasks::ExportSlots>().0.semaphore();
+    let handles: Vec<_> = media
+        .into_iter()
+        .map(|request| {
+            let (app_handle, ffmpeg_path, job_id) = (app_handle.clone(), ffmpeg_path.clone(), job_id.clone());
+            let (settings, completed, semaphore) = (settings.clone(), completed.clone(), semaphore.clone());
+            tauri::async_runtime::spawn(async move {
+                let emit = |status, error: Option<String>, completed| {
+                    let _ = app_handle.emit("proxy-status", ProxyStatusEvent {
+                        input_path: request.input_path.clone(),
+                        status,
+                        error,
+                        completed,
+                        total,
+                    });
+                };
+                let result: Result<ProxyStatus, String> = if is_current(&request.input_path, &request.output_path) {
+                    Ok(ProxyStatus::Skipped)
+                } else {
+                    async {
+                        let _permit = semaphore
+                            .acquire_owned()
+                            .await
+                            .map_err(|e| format!("Failed to wait for an export slot: {}", e))?;
+                        emit(ProxyStatus::Started, None, completed.load(Ordering::Relaxed));
+                        let (worker_request, worker_handle) = (request.clone(), app_handle.clone());
+                        tauri::async_runtime::spawn_blocking(move || {
+                            let registry = worker_handle.state::<ProcessRegistry>();
+                            encode_proxy(
+                                &registry,
+                                &job_id,
+                                &ffmpeg_path,
+                                &worker_request.input_path,
+                                &worker_request.output_path,
+                                &settings,
+                            )
+                        })
+                        .await
+                        .map_err(|e| format!("Proxy worker failed: {}", e))??;
+                        Ok(ProxyStatus::Created)
+                    }
+                    .await
+                };
+                let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                match &result {
+                    Ok(status) => emit(*status, None, done),
+                    Err(e) => emit(ProxyStatus::Failed, Some(e.clone()), done),
+                }
+                (request.input_path, result)
+            })
+        })
+        .collect();
+
+    let mut summary = ProxySummary::default();
+    for handle in handles {
+        let (input_path, result) = handle.await.map_err(|e| format!("Proxy worker failed: {}", e))?;
+        match result {
+            Ok(ProxyStatus::Skipped) => summary.skipped.push(input_path),
+            Ok(_) => summary.created.push(input_path),
+            Err(error) => summary.failed.push(ProxyFailure { input_path, error }),
+        }
+    }
+    Ok(summary)
+}
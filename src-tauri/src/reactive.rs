@@ -0,0 +1,239 @@
+// Audio-reactive effects: picture parameters that follow the music in an
+// export. The music is split into bass, mid and high bands, each band's level
+// is measured ENVELOPE_RATE times a second, and every trim gets a sendcmd
+// file that moves the mapped filters to those levels as the clip plays.
+
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::overlay::escape_value;
+
+// Levels per second of timeline, and so how often the filters are updated
+const ENVELOPE_RATE: f64 = 30.0;
+
+// Rate the music is analysed at; enough to keep the high band
+const ANALYSIS_SAMPLE_RATE: u32 = 22050;
+
+// Crossovers between bass and mid, and mid and high, in Hz
+const BASS_MAX_HZ: u32 = 150;
+const HIGH_MIN_HZ: u32 = 4000;
+
+// How long a band's level takes to fall most of the way back after a hit, so
+// the picture pulses rather than flickers
+const RELEASE_SECONDS: f64 = 0.15;
+
+// Input options for a watermark whose opacity is driven: the image is
+// repeated as frames, so its fade can change while the clip plays
+pub const WATERMARK_INPUT_ARGS: [&str; 4] = ["-loop", "1", "-framerate", "30"];
+
+// Names of the filters the commands are sent to
+const BRIGHTNESS_TARGET: &str = "eq@reactive";
+const ZOOM_TARGET: &str = "crop@reactive";
+pub const OPACITY_TARGET: &str = "colorchannelmixer@reactive";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Band {
+    Bass,
+    Mid,
+    High,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReactiveParameter {
+    // Added to the picture's brightness
+    Brightness,
+    // Zooms in on the centre of the picture
+    Scale,
+    // Fades the watermark up from transparent, so it needs one
+    Opacity,
+}
+
+// One band driving one parameter
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct ReactiveMapping {
+    pub band: Band,
+    pub parameter: ReactiveParameter,
+    // How far the parameter moves when the band is at its loudest: brightness
+    // -1 to 1 (eq's range), scale 0 to 1 (1 doubles the size), opacity 0 to
+    // 1 (1 is invisible in silence)
+    pub amount: f64,
+}
+
+pub fn validate_mappings(mappings: &[ReactiveMapping], has_watermark: bool) -> Result<(), String> {
+    for (i, mapping) in mappings.iter().enumerate() {
+        let range = match mapping.parameter {
+            ReactiveParameter::Brightness => -1.0..=1.0,
+            ReactiveParameter::Scale | ReactiveParameter::Opacity => 0.0..=1.0,
+        };
+        if !range.contains(&mapping.amount) {
+            return Err(format!(
+                "Audio-reactive {:?} amount must be between {} and {}, got {}",
+                mapping.parameter,
+                range.start(),
+                range.end(),
+                mapping.amount
+            ));
+        }
+        if mappings[..i].iter().any(|m| m.parameter == mapping.parameter) {
+            return Err(format!("Audio-reactive {:?} can only follow one band", mapping.parameter));
+        }
+        if mapping.parameter == ReactiveParameter::Opacity && !has_watermark {
+            return Err("Audio-reactive opacity needs a watermark".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Each band's level over the music, 0 to 1, ENVELOPE_RATE values a second
+pub struct Envelopes {
+    bands: [Vec<f32>; 3],
+}
+
+impl Envelopes {
+    // Measures the first `length` seconds of the music at `audio_path`
+    pub fn analyse(ffmpeg_path: &Path, audio_path: &str, length: f64) -> Result<Self, String> {
+        let graph = format!(
+            "[0:a]aformat=sample_fmts=flt:sample_rates={}:channel_layouts=mono,asplit=3[b][m][h];\
+[b]lowpass=f={bass}[bass];[m]highpass=f={bass},lowpass=f={high}[mid];[h]highpass=f={high}[high];\
+[bass][mid][high]join=inputs=3:channel_layout=3.0[out]",
+            ANALYSIS_SAMPLE_RATE,
+            bass = BASS_MAX_HZ,
+            high = HIGH_MIN_HZ
+        );
+        // The bands come out interleaved as 32-bit floats on stdout
+        let mut child = Command::new(ffmpeg_path)
+            .args(["-hide_banner", "-loglevel", "error", "-t", &length.to_string(), "-i", audio_path, "-vn"])
+            .args(["-filter_complex", &graph, "-map", "[out]", "-f", "f32le", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to analyse the music: {}", e))?;
+        let mut stdout = child.stdout.take().ok_or("Failed to analyse the music: no stdout")?;
+
+        // Read a window at a time, as a long mix decoded whole would be
+        // gigabytes
+        let window_bytes = (ANALYSIS_SAMPLE_RATE as f64 / ENVELOPE_RATE).round() as u64 * 12;
+        let mut bands: [Vec<f32>; 3] = Default::default();
+        let mut window = Vec::with_capacity(window_bytes as usize);
+        loop {
+            window.clear();
+            (&mut stdout)
+                .take(window_bytes)
+                .read_to_end(&mut window)
+                .map_err(|e| format!("Failed to analyse the music: {}", e))?;
+            let frames = window.len() / 12;
+            if frames == 0 {
+                break;
+            }
+            let mut sums = [0.0f64; 3];
+            for frame in window.chunks_exact(12) {
+                for (band, sum) in sums.iter_mut().enumerate() {
+                    let bytes = [frame[band * 4], frame[band * 4 + 1], frame[band * 4 + 2], frame[band * 4 + 3]];
+                    let sample = f32::from_le_bytes(bytes) as f64;
+                    *sum += sample * sample;
+                }
+            }
+            for (levels, sum) in bands.iter_mut().zip(sums) {
+                levels.push((sum / frames as f64).sqrt() as f32);
+            }
+        }
+        let status = child.wait().map_err(|e| format!("Failed to analyse the music: {}", e))?;
+        if !status.success() {
+            return Err(format!("FFmpeg could not analyse the music in {}", audio_path));
+        }
+
+        // Each band is scaled to its own loudest moment, so a quiet band
+        // moves its parameter as far as a loud one, then held through its
+        // release
+        let release = (-1.0 / (RELEASE_SECONDS * ENVELOPE_RATE)).exp() as f32;
+        for levels in &mut bands {
+            let peak = levels.iter().cloned().fold(0.0f32, f32::max);
+            let mut held = 0.0f32;
+            for level in levels.iter_mut() {
+                let scaled = if peak > 0.0 { *level / peak } else { 0.0 };
+                held = scaled.max(held * release);
+                *level = held;
+            }
+        }
+        Ok(Envelopes { bands })
+    }
+
+    // Level of `band` at `time` on the timeline; silence past the music's end
+    fn level(&self, band: Band, time: f64) -> f64 {
+        let index = (time * ENVELOPE_RATE).floor().max(0.0) as usize;
+        self.bands[band as usize].get(index).map_or(0.0, |level| *level as f64)
+    }
+}
+
+// Writes `commands_path`, the sendcmd file for a clip placed at `clip_start`
+// on the timeline and lasting `duration`, and returns the video filters that
+// read it, in order. `frame` is the clip's frame size, which a zoom scales
+// back to; `watermark_opacity` the opacity a driven watermark fades up to.
+// Empty when nothing is mapped.
+pub fn clip_filters(
+    mappings: &[ReactiveMapping],
+    envelopes: &Envelopes,
+    clip_start: f64,
+    duration: f64,
+    frame: Option<(u32, u32)>,
+    watermark_opacity: f64,
+    commands_path: &Path,
+) -> Result<Vec<String>, String> {
+    if mappings.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mapping = |parameter| mappings.iter().find(|m| m.parameter == parameter);
+    let zoom = match mapping(ReactiveParameter::Scale) {
+        Some(mapping) => {
+            let frame = frame.ok_or("Couldn't read the clip's frame size for the audio-reactive zoom")?;
+            Some((mapping, frame))
+        }
+        None => None,
+    };
+
+    let mut commands = String::new();
+    let mut previous = Vec::new();
+    let first = (clip_start.max(0.0) * ENVELOPE_RATE).floor() as u64;
+    let last = ((clip_start + duration) * ENVELOPE_RATE).ceil() as u64;
+    for step in first..=last {
+        let time = step as f64 / ENVELOPE_RATE;
+        let mut values = Vec::new();
+        if let Some(mapping) = mapping(ReactiveParameter::Brightness) {
+            let brightness = mapping.amount * envelopes.level(mapping.band, time);
+            values.push(format!("{} brightness {:.3}", BRIGHTNESS_TARGET, brightness));
+        }
+        if let Some((mapping, (width, height))) = zoom {
+            // The crop is shrunk as the level rises, in even sizes for 4:2:0
+            let factor = 1.0 + mapping.amount * envelopes.level(mapping.band, time);
+            let even = |size: u32| ((size as f64 / factor / 2.0).round() as u32 * 2).max(2);
+            values.push(format!("{} w {}", ZOOM_TARGET, even(width)));
+            values.push(format!("{} h {}", ZOOM_TARGET, even(height)));
+        }
+        if let Some(mapping) = mapping(ReactiveParameter::Opacity) {
+            let fade = 1.0 - mapping.amount * (1.0 - envelopes.level(mapping.band, time));
+            values.push(format!("{} aa {:.3}", OPACITY_TARGET, watermark_opacity * fade));
+        }
+        // Only changes are written, which keeps quiet passages short
+        if values != previous {
+            commands.push_str(&format!("{:.3} {};\n", (time - clip_start).max(0.0), values.join(", ")));
+            previous = values;
+        }
+    }
+    std::fs::write(commands_path, commands).map_err(|e| format!("Failed to write audio-reactive commands: {}", e))?;
+
+    let mut filters = vec![format!("sendcmd=f='{}'", escape_value(&commands_path.to_string_lossy()))];
+    if mapping(ReactiveParameter::Brightness).is_some() {
+        filters.push(format!("{}=brightness=0", BRIGHTNESS_TARGET));
+    }
+    if let Some((_, (width, height))) = zoom {
+        // Centred by crop's default x/y, which follow the size
+        filters.push(format!("{}=w=iw:h=ih", ZOOM_TARGET));
+        filters.push(format!("scale={}:{}", width, height));
+    }
+    Ok(filters)
+}
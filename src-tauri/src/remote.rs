@@ -0,0 +1,230 @@
+// Render offload: runs the final concat of an export on another machine over
+// SSH. The trimmed intermediates are copied up with scp, ffmpeg runs remotely
+// and the result is pulled back to the requested output path.
+//
+// Needs the OpenSSH client (`ssh`/`scp`) on PATH and key-based auth to the
+// target; BatchMode stops a password prompt from hanging the export.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::{final_concat_args, ExportContext, FfmpegProgress, ProcessRegistry};
+
+// Parent of the working directories for uploaded intermediates on the
+// remote machine
+const REMOTE_WORK_ROOT: &str = "/tmp";
+
+// Remote working directory of one export, named after its local temp dir so
+// exports running at the same time don't share one
+fn work_dir(ctx: &ExportContext) -> String {
+    let name = ctx.temp_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    format!("{}/{}", REMOTE_WORK_ROOT, name)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RemoteTarget {
+    host: String,
+    user: String,
+    // ffmpeg binary on the remote machine, e.g. /usr/bin/ffmpeg
+    ffmpeg_path: String,
+    #[serde(default)]
+    port: Option<u16>,
+}
+
+// The configured remote target; None keeps every export local
+#[derive(Default)]
+pub struct RemoteConfig(pub Mutex<Option<RemoteTarget>>);
+
+impl RemoteTarget {
+    fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("host", &self.host), ("user", &self.user), ("ffmpeg_path", &self.ffmpeg_path)] {
+            if value.is_empty() {
+                return Err(format!("Remote {} must not be empty", name));
+            }
+            // Would otherwise be read by ssh as an option
+            if value.starts_with('-') || value.chars().any(char::is_whitespace) {
+                return Err(format!("Invalid remote {}: {}", name, value));
+            }
+        }
+        Ok(())
+    }
+
+    fn destination(&self) -> String {
+        format!("{}@{}", self.user, self.host)
+    }
+
+    fn ssh(&self) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            cmd.args(["-p", &port.to_string()]);
+        }
+        cmd.arg(self.destination());
+        cmd
+    }
+
+    fn scp(&self) -> Command {
+        let mut cmd = Command::new("scp");
+        cmd.args(["-q", "-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            cmd.args(["-P", &port.to_string()]);
+        }
+        cmd
+    }
+
+    fn remote_path(&self, path: &str) -> String {
+        format!("{}:{}", self.destination(), path)
+    }
+}
+
+// Quotes an argument for the remote POSIX shell
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+// ".mp4" for "C:/out/final.mp4", so remote copies keep the container ffmpeg infers
+fn extension_of(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{}", e))
+        .unwrap_or_default()
+}
+
+fn run_step(registry: &ProcessRegistry, job: &str, mut cmd: Command, step: &str) -> Result<(), String> {
+    let status = registry
+        .run(job, &mut cmd)
+        .map_err(|e| format!("Remote {} failed: {}", step, e))?;
+    if !status.success() {
+        return Err(format!("Remote {} exited with error", step));
+    }
+    Ok(())
+}
+
+// Uploads the intermediates, audio and subtitle tracks, runs the final
+// concat remotely and downloads the result to `output_path`. `on_progress`
+// receives the remote ffmpeg's progress, which ssh relays over stdout.
+pub fn run_final_concat(
+    target: &RemoteTarget,
+    trimmed_paths: &[PathBuf],
+    audio_path: &str,
+    output_path: &str,
+    ctx: &ExportContext,
+    mut on_progress: impl FnMut(&FfmpegProgress),
+) -> Result<(), String> {
+    let registry = ctx.registry;
+    let work_dir = work_dir(ctx);
+
+    // The concat list has to reference the uploaded copies
+    let mut concat_content = String::new();
+    for path in trimmed_paths {
+        let name = path.file_name().and_then(|n| n.to_str()).ok_or("Invalid path")?;
+        concat_content.push_str(&format!("file '{}/{}'\n", work_dir, name));
+    }
+    let concat_content = concat_content.repeat(ctx.loop_count as usize);
+    let concat_file_path = ctx.temp_dir.join("concat_remote.txt");
+    std::fs::write(&concat_file_path, concat_content)
+        .map_err(|e| format!("Failed to write concat file: {}", e))?;
+
+    let mut mkdir = target.ssh();
+    mkdir.arg(format!("mkdir -p {}", shell_quote(&work_dir)));
+    run_step(registry, ctx.task_id, mkdir, "setup")?;
+
+    let result = upload_and_encode(
+        target,
+        trimmed_paths,
+        audio_path,
+        output_path,
+        &concat_file_path,
+        ctx,
+        &mut on_progress,
+    );
+
+    // Best effort, and deliberately outside the registry so it still runs
+    // after a cancel
+    let _ = target
+        .ssh()
+        .arg(format!("rm -rf {}", shell_quote(&work_dir)))
+        .status();
+
+    result
+}
+
+fn upload_and_encode(
+    target: &RemoteTarget,
+    trimmed_paths: &[PathBuf],
+    audio_path: &str,
+    output_path: &str,
+    concat_file_path: &Path,
+    ctx: &ExportContext,
+    on_progress: &mut impl FnMut(&FfmpegProgress),
+) -> Result<(), String> {
+    let registry = ctx.registry;
+    let work_dir = work_dir(ctx);
+    let remote_audio = format!("{}/audio{}", work_dir, extension_of(audio_path));
+    let remote_output = format!("{}/output{}", work_dir, extension_of(output_path));
+    let remote_concat = format!("{}/concat.txt", work_dir);
+
+    let mut upload = target.scp();
+    upload.args(trimmed_paths);
+    upload.arg(target.remote_path(&format!("{}/", work_dir)));
+    run_step(registry, ctx.task_id, upload, "upload")?;
+
+    let mut upload = target.scp();
+    upload.arg(audio_path).arg(target.remote_path(&remote_audio));
+    run_step(registry, ctx.task_id, upload, "upload")?;
+
+    let mut upload = target.scp();
+    upload.arg(concat_file_path).arg(target.remote_path(&remote_concat));
+    run_step(registry, ctx.task_id, upload, "upload")?;
+
+    let mut remote_subtitles = Vec::new();
+    for (n, track) in ctx.options.subtitle_tracks.iter().enumerate() {
+        let remote_subtitle = format!("{}/subtitle_{}{}", work_dir, n, extension_of(&track.path));
+        let mut upload = target.scp();
+        upload.arg(&track.path).arg(target.remote_path(&remote_subtitle));
+        run_step(registry, ctx.task_id, upload, "upload")?;
+        remote_subtitles.push(remote_subtitle);
+    }
+
+    let mut remote_command = shell_quote(&target.ffmpeg_path);
+    for arg in final_concat_args(&remote_concat, &remote_audio, &remote_subtitles, &remote_output, ctx) {
+        remote_command.push(' ');
+        remote_command.push_str(&shell_quote(&arg));
+    }
+    let mut encode = target.ssh();
+    encode.arg(remote_command);
+    let status = registry
+        .run_with_progress(ctx.task_id, &mut encode, on_progress, |line| ctx.note_ffmpeg_line(line))
+        .map_err(|e| format!("Remote FFmpeg failed: {}", e))?;
+    if !status.success() {
+        return Err("Remote FFmpeg exited with error".to_string());
+    }
+
+    let mut download = target.scp();
+    download.arg(target.remote_path(&remote_output)).arg(output_path);
+    run_step(registry, ctx.task_id, download, "download")
+}
+
+// Command to set (or with None, clear) the remote render target
+#[tauri::command]
+pub async fn set_remote_target(
+    target: Option<RemoteTarget>,
+    config: tauri::State<'_, RemoteConfig>,
+) -> Result<(), String> {
+    if let Some(target) = &target {
+        target.validate()?;
+    }
+    *config.0.lock().unwrap() = target;
+    Ok(())
+}
+
+// Command to read the configured remote render target
+#[tauri::command]
+pub async fn get_remote_target(
+    config: tauri::State<'_, RemoteConfig>,
+) -> Result<Option<RemoteTarget>, String> {
+    Ok(config.0.lock().unwrap().clone())
+}
@@ -0,0 +1,206 @@
+// On-disk cache of trimmed intermediates, in the app's cache directory, so a
+// re-export only re-trims the clips that changed. An entry is keyed by the
+// whole trim command: every range, filter and codec setting is in its
+// arguments. The files it reads are keyed by size and modification time, so
+// a source overwritten in place misses the cache instead of reusing old frames.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+
+use crate::overlay;
+
+// Bumped whenever trims are built differently in ways the arguments don't show
+const CACHE_VERSION: u32 = 2;
+
+// Least recently used entries are removed beyond this
+const MAX_CACHE_BYTES: u64 = 20_000_000_000;
+
+// Tells apart the partial files of stores running at the same time
+static NEXT_PARTIAL: AtomicU64 = AtomicU64::new(0);
+
+pub fn cache_dir(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    let dir = app_handle.path().app_cache_dir().ok()?.join("render");
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+// 64-bit FNV-1a. Keys outlive the build that made them (on disk, and as the
+// clip hashes the frontend keeps), so unlike DefaultHasher's, this hash must
+// never change.
+struct KeyHasher(u64);
+
+impl KeyHasher {
+    fn new() -> Self {
+        KeyHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    // Length-prefixed, so neighbouring values can't run together
+    fn value(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+
+    fn fingerprint(&mut self, fingerprint: Option<(u64, u128)>) {
+        match fingerprint {
+            Some((len, modified)) => self.value(&[len.to_le_bytes().as_slice(), &modified.to_le_bytes()].concat()),
+            None => self.value(&[]),
+        }
+    }
+}
+
+// Size and modification time of `path`
+fn fingerprint(path: &Path) -> Option<(u64, u128)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos()))
+}
+
+// Key of the intermediate `cmd` makes, built up to its output path. `files`
+// are the files it reads; their paths are left out of the key, so the same
+// clip elsewhere on the timeline still hits. Files the export itself wrote to
+// `temp_dir` (overlay text) are rewritten every run, so their contents count
+// instead. The key is hex, and is also what export_video reports as the
+// clip's hash. None if a file can't be read, in which case the clip isn't
+// cached.
+pub fn key(cmd: &Command, temp_dir: &Path, files: &[&Path]) -> Option<String> {
+    let mut hasher = KeyHasher::new();
+    hasher.value(&CACHE_VERSION.to_le_bytes());
+    // Another ffmpeg build may encode differently
+    hasher.fingerprint(fingerprint(Path::new(cmd.get_program())));
+    for file in files {
+        if file.starts_with(temp_dir) {
+            hasher.value(&std::fs::read(file).ok()?);
+        } else {
+            hasher.fingerprint(Some(fingerprint(file)?));
+        }
+    }
+    for arg in cmd.get_args() {
+        let mut arg = arg.to_string_lossy().into_owned();
+        for (n, file) in files.iter().enumerate() {
+            let path = file.to_string_lossy();
+            let placeholder = format!("{{file{}}}", n);
+            // Paths inside a filtergraph are escaped
+            arg = arg.replace(&overlay::escape_value(&path), &placeholder).replace(&*path, &placeholder);
+        }
+        hasher.value(arg.as_bytes());
+    }
+    Some(format!("{:016x}", hasher.0))
+}
+
+pub fn entry_path(dir: &Path, key: &str, extension: &str) -> PathBuf {
+    dir.join(format!("{}.{}", key, extension))
+}
+
+// Puts the cached intermediate at `dest`, linked rather than copied where the
+// filesystem allows. false on a miss.
+pub fn fetch(entry: &Path, dest: &Path) -> bool {
+    if !entry.is_file() {
+        return false;
+    }
+    if std::fs::hard_link(entry, dest).is_err() && std::fs::copy(entry, dest).is_err() {
+        let _ = std::fs::remove_file(dest);
+        return false;
+    }
+    // Marks the entry as recently used, for pruning
+    if let Ok(file) = std::fs::File::options().write(true).open(entry) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    true
+}
+
+// Best effort: an export whose clips can't be cached still goes ahead
+pub fn store(entry: &Path, trimmed: &Path) {
+    // Written aside and renamed, so a concurrent export never reads half a file
+    let partial = entry.with_extension(format!("{}.partial", NEXT_PARTIAL.fetch_add(1, Ordering::Relaxed)));
+    if std::fs::copy(trimmed, &partial).is_ok() && std::fs::rename(&partial, entry).is_ok() {
+        if let Some(dir) = entry.parent() {
+            prune(dir);
+        }
+    } else {
+        let _ = std::fs::remove_file(&partial);
+    }
+}
+
+// Removes the least recently used entries until the cache fits MAX_CACHE_BYTES
+fn prune(dir: &Path) {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<(PathBuf, u64, SystemTime)> = read_dir
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            let path = entry.path();
+            if !metadata.is_file() || path.extension().is_some_and(|e| e == "partial") {
+                return None;
+            }
+            Some((path, metadata.len(), metadata.modified().ok()?))
+        })
+        .collect();
+    // Most recently used first
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.2));
+    let mut total = 0;
+    for (path, size, _) in entries {
+        total += size;
+        if total > MAX_CACHE_BYTES {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// Command to delete every cached intermediate; the next export re-trims all
+// of its clips. Returns the number of bytes freed.
+#[tauri::command]
+pub async fn clear_render_cache(app_handle: tauri::AppHandle) -> Result<u64, String> {
+    let Some(dir) = cache_dir(&app_handle) else {
+        return Ok(0);
+    };
+    let read_dir = std::fs::read_dir(&dir).map_err(|e| format!("Failed to read render cache: {}", e))?;
+    let mut freed = 0;
+    for entry in read_dir.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_file() && std::fs::remove_file(entry.path()).is_ok() {
+            freed += metadata.len();
+        }
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fnv(bytes: &[u8]) -> u64 {
+        let mut hasher = KeyHasher::new();
+        hasher.write(bytes);
+        hasher.0
+    }
+
+    #[test]
+    fn key_hasher_is_fnv1a() {
+        assert_eq!(fnv(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn values_are_length_prefixed() {
+        let (mut split, mut joined) = (KeyHasher::new(), KeyHasher::new());
+        split.value(b"ab");
+        split.value(b"c");
+        joined.value(b"a");
+        joined.value(b"bc");
+        assert_ne!(split.0, joined.0);
+    }
+}
@@ -0,0 +1,262 @@
+// Frequency spectrum of a file's audio over time, for spectrum visualizers
+// and anything else that needs more than the waveform's loudness. The audio
+// is decoded to mono PCM and each step is run through an FFT here.
+
+use std::f64::consts::PI;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::resolve_ffmpeg;
+
+// Rate the audio is decoded at; keeps everything up to 11 kHz
+const SPECTRUM_SAMPLE_RATE: u32 = 22050;
+
+// Samples per FFT: ~93 ms, so bins ~11 Hz apart
+const FFT_SIZE: usize = 2048;
+
+// Lowest frequency the bands start from, in Hz
+const MIN_FREQUENCY: f64 = 20.0;
+
+const MAX_BANDS: usize = 256;
+const MIN_INTERVAL: f64 = 0.005;
+const MAX_INTERVAL: f64 = 10.0;
+
+// In-place radix-2 FFT of re + i·im; the length must be a power of two
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// FFT bins each of `bands` log-spaced bands covers, from MIN_FREQUENCY to
+// the top of the decode. A band narrower than a bin takes the bin nearest its
+// centre, so none is always empty.
+fn band_bins(bands: usize) -> Vec<(usize, usize)> {
+    let nyquist = SPECTRUM_SAMPLE_RATE as f64 / 2.0;
+    let bin_width = SPECTRUM_SAMPLE_RATE as f64 / FFT_SIZE as f64;
+    let edge = |b: usize| MIN_FREQUENCY * (nyquist / MIN_FREQUENCY).powf(b as f64 / bands as f64);
+    (0..bands)
+        .map(|b| {
+            let (low, high) = (edge(b), edge(b + 1));
+            let first = (low / bin_width).ceil() as usize;
+            let last = ((high / bin_width).ceil() as usize).min(FFT_SIZE / 2);
+            if first < last {
+                (first, last)
+            } else {
+                let centre = (((low * high).sqrt() / bin_width).round() as usize).min(FFT_SIZE / 2 - 1);
+                (centre, centre + 1)
+            }
+        })
+        .collect()
+}
+
+// Band magnitudes of one window of samples (zero-padded if short). A full
+// scale sine reads about 1 in its band.
+fn analyse_window(samples: &[f32], window: &[f64], bins: &[(usize, usize)]) -> Vec<f32> {
+    let mut re: Vec<f64> = (0..FFT_SIZE).map(|i| samples.get(i).map_or(0.0, |s| *s as f64) * window[i]).collect();
+    let mut im = vec![0.0; FFT_SIZE];
+    fft(&mut re, &mut im);
+    // A Hann window halves the amplitude, and a real sine splits across the
+    // positive and negative bins
+    let scale = 4.0 / FFT_SIZE as f64;
+    bins.iter()
+        .map(|&(first, last)| {
+            (first..last).map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt() * scale).fold(0.0, f64::max) as f32
+        })
+        .collect()
+}
+
+// `bands` magnitudes every `interval` seconds through start..end of the file
+// (the whole file by default), one row per step. Each row is the spectrum of
+// FFT_SIZE samples from its step's time on.
+pub fn spectrum(
+    ffmpeg_path: &Path,
+    file_path: &str,
+    start: Option<f64>,
+    end: Option<f64>,
+    bands: usize,
+    interval: f64,
+) -> Result<Vec<Vec<f32>>, String> {
+    let mut cmd = Command::new(ffmpeg_path);
+    if let Some(start) = start {
+        cmd.args(["-ss", &start.to_string()]);
+    }
+    if let Some(end) = end {
+        cmd.args(["-t", &(end - start.unwrap_or(0.0)).to_string()]);
+    }
+    // Decode to mono 32-bit float PCM on stdout
+    cmd.args([
+        "-i", file_path,
+        "-vn",
+        "-ac", "1",
+        "-ar", &SPECTRUM_SAMPLE_RATE.to_string(),
+        "-f", "f32le",
+        "-",
+    ]);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("FFmpeg audio decode failed: {}", e))?;
+    let mut stdout = child.stdout.take().ok_or("FFmpeg audio decode failed: no stdout")?;
+
+    let window: Vec<f64> = (0..FFT_SIZE).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / FFT_SIZE as f64).cos()).collect();
+    let bins = band_bins(bands);
+    let hop = ((interval * SPECTRUM_SAMPLE_RATE as f64).round() as usize).max(1);
+    let mut rows = Vec::new();
+    // Samples from `offset` on that a later window still needs, and the
+    // sample the next window starts at
+    let mut pending: Vec<f32> = Vec::new();
+    let mut offset = 0;
+    let mut next = 0;
+    let mut chunk = Vec::new();
+    loop {
+        chunk.clear();
+        (&mut stdout)
+            .take(FFT_SIZE as u64 * 16)
+            .read_to_end(&mut chunk)
+            .map_err(|e| format!("FFmpeg audio decode failed: {}", e))?;
+        let finished = chunk.is_empty();
+        pending.extend(chunk.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+        // The last windows run past the end of the audio and are padded
+        let needed = if finished { 1 } else { FFT_SIZE };
+        while next + needed <= offset + pending.len() {
+            rows.push(analyse_window(&pending[next - offset..], &window, &bins));
+            next += hop;
+        }
+        let consumed = (next - offset).min(pending.len());
+        pending.drain(..consumed);
+        offset += consumed;
+        if finished {
+            break;
+        }
+    }
+    let status = child.wait().map_err(|e| format!("FFmpeg audio decode failed: {}", e))?;
+    if !status.success() {
+        return Err("FFmpeg failed to decode audio".to_string());
+    }
+    Ok(rows)
+}
+
+// Command to compute a file's spectrum over time: a row of `bands`
+// magnitudes (log-spaced from 20 Hz, about 0-1) every `interval` seconds,
+// through `start`/`end` if given
+#[tauri::command]
+pub async fn get_spectrum(
+    file_path: String,
+    bands: usize,
+    interval: f64,
+    start: Option<f64>,
+    end: Option<f64>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<Vec<f32>>, String> {
+    if !(1..=MAX_BANDS).contains(&bands) {
+        return Err(format!("Band count must be between 1 and {}, got {}", MAX_BANDS, bands));
+    }
+    if !(MIN_INTERVAL..=MAX_INTERVAL).contains(&interval) {
+        return Err(format!(
+            "Interval must be between {} and {} seconds, got {}",
+            MIN_INTERVAL, MAX_INTERVAL, interval
+        ));
+    }
+    if let (Some(start), Some(end)) = (start, end) {
+        if end <= start {
+            return Err(format!("Invalid range: end {} is not after start {}", end, start));
+        }
+    }
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    spectrum(&ffmpeg_path, &file_path, start, end, bands, interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fft_of_an_impulse_is_flat() {
+        let mut re = vec![0.0; 16];
+        let mut im = vec![0.0; 16];
+        re[0] = 1.0;
+        fft(&mut re, &mut im);
+        for k in 0..16 {
+            assert!((re[k] - 1.0).abs() < 1e-12 && im[k].abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn fft_puts_a_cosine_in_its_bins() {
+        let n = 64;
+        let mut re: Vec<f64> = (0..n).map(|i| (2.0 * PI * 5.0 * i as f64 / n as f64).cos()).collect();
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+        for k in 0..n {
+            let magnitude = (re[k] * re[k] + im[k] * im[k]).sqrt();
+            let expected = if k == 5 || k == n - 5 { n as f64 / 2.0 } else { 0.0 };
+            assert!((magnitude - expected).abs() < 1e-9, "bin {}: {}", k, magnitude);
+        }
+    }
+
+    #[test]
+    fn band_bins_are_ordered_and_never_empty() {
+        for bands in [1, 8, 64, MAX_BANDS] {
+            let bins = band_bins(bands);
+            assert_eq!(bins.len(), bands);
+            for (i, &(first, last)) in bins.iter().enumerate() {
+                assert!(first < last && last <= FFT_SIZE / 2, "band {} of {}: {:?}", i, bands, (first, last));
+                if i > 0 {
+                    assert!(first >= bins[i - 1].0);
+                }
+            }
+        }
+        // One band covers everything from MIN_FREQUENCY up
+        let lowest = (MIN_FREQUENCY / (SPECTRUM_SAMPLE_RATE as f64 / FFT_SIZE as f64)).ceil() as usize;
+        assert_eq!(band_bins(1), vec![(lowest, FFT_SIZE / 2)]);
+    }
+
+    #[test]
+    fn a_full_scale_sine_reads_about_one_in_its_band() {
+        let frequency = 1000.0;
+        let samples: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| (2.0 * PI * frequency * i as f64 / SPECTRUM_SAMPLE_RATE as f64).sin() as f32)
+            .collect();
+        let window: Vec<f64> =
+            (0..FFT_SIZE).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / FFT_SIZE as f64).cos()).collect();
+        let bins = band_bins(32);
+        let magnitudes = analyse_window(&samples, &window, &bins);
+        let bin = (frequency / (SPECTRUM_SAMPLE_RATE as f64 / FFT_SIZE as f64)).round() as usize;
+        let band = bins.iter().position(|&(first, last)| (first..last).contains(&bin)).unwrap();
+        assert!((magnitudes[band] - 1.0).abs() < 0.1, "{}", magnitudes[band]);
+        let loudest = magnitudes.iter().cloned().fold(0.0, f32::max);
+        assert_eq!(magnitudes[band], loudest);
+    }
+}
@@ -0,0 +1,465 @@
+// Live streaming of the timeline, e.g. to Twitch or YouTube over RTMP, or
+// over SRT, which recovers lost packets and so holds up better on poor
+// networks, for feeds between venues and production machines. One
+// ffmpeg reads the clips from the playhead, paced to real time, and pushes
+// them with the low-latency encode (see encode::low_latency_video). The
+// music, or silence without it, is mixed in, as most services drop a stream
+// that has no audio.
+//
+// A stream that drops (a network blip, the server restarting) is restarted
+// where it left off, a few times, before it is reported as failed.
+// `stream-status` follows every stage, with the bitrate while live.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+
+use crate::capabilities::{self, CapabilitiesCache};
+use crate::{encode, proxy, resolve_ffmpeg, ClipData, FfmpegProgress, ProcessRegistry};
+
+const DEFAULT_STREAM_WIDTH: u32 = 1280;
+const DEFAULT_STREAM_HEIGHT: u32 = 720;
+const DEFAULT_STREAM_FPS: u32 = 30;
+const MAX_STREAM_FPS: u32 = 60;
+const MAX_STREAM_DIMENSION: u32 = 3840;
+// What Twitch recommends for 720p30
+const DEFAULT_STREAM_BITRATE: &str = "4500k";
+const MIN_STREAM_KBPS: f64 = 300.0;
+const MAX_STREAM_KBPS: f64 = 50_000.0;
+const STREAM_AUDIO_BITRATE: &str = "160k";
+
+// Restarts of a dropped stream before giving up, waiting a little longer
+// before each
+const MAX_RECONNECTS: u32 = 5;
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+// A stream that stayed up this long starts over with all its reconnects
+const RECONNECT_RESET: Duration = Duration::from_secs(30);
+
+// SRT's default receive buffer; more rides out worse networks, at more delay
+const DEFAULT_SRT_LATENCY_MS: u32 = 120;
+const MAX_SRT_LATENCY_MS: u32 = 10_000;
+// Length bounds SRT sets on passphrases
+const SRT_PASSPHRASE_CHARS: std::ops::RangeInclusive<usize> = 10..=79;
+// Seven MPEG-TS packets, the payload SRT is tuned for
+const SRT_PACKET_SIZE: u32 = 1316;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum StreamState {
+    Connecting,
+    // Sending; repeated with the current bitrate
+    Live,
+    // Dropped, and about to restart
+    Reconnecting,
+    // Ended by stop_stream or the end of the timeline
+    Stopped,
+    // Dropped for good; `error` says why
+    Failed,
+}
+
+// Payload of `stream-status`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StreamStatus {
+    // "rtmp" or "srt"
+    protocol: String,
+    state: StreamState,
+    // Timeline position being sent, in seconds
+    position: Option<f64>,
+    bitrate_kbps: Option<f64>,
+    error: Option<String>,
+}
+
+// Picture and bitrate of a stream
+#[derive(Debug, Clone)]
+struct StreamSettings {
+    width: u32,
+    height: u32,
+    fps: u32,
+    bitrate: String,
+}
+
+impl StreamSettings {
+    fn from_args(
+        width: Option<u32>,
+        height: Option<u32>,
+        fps: Option<u32>,
+        bitrate: Option<String>,
+    ) -> Result<Self, String> {
+        let settings = StreamSettings {
+            width: width.unwrap_or(DEFAULT_STREAM_WIDTH),
+            height: height.unwrap_or(DEFAULT_STREAM_HEIGHT),
+            fps: fps.unwrap_or(DEFAULT_STREAM_FPS),
+            bitrate: bitrate.unwrap_or_else(|| DEFAULT_STREAM_BITRATE.to_string()),
+        };
+        for (name, value) in [("width", settings.width), ("height", settings.height)] {
+            if !(16..=MAX_STREAM_DIMENSION).contains(&value) || value % 2 != 0 {
+                return Err(format!(
+                    "Stream {} must be even and between 16 and {}, got {}",
+                    name, MAX_STREAM_DIMENSION, value
+                ));
+            }
+        }
+        if !(1..=MAX_STREAM_FPS).contains(&settings.fps) {
+            return Err(format!("Stream fps must be between 1 and {}, got {}", MAX_STREAM_FPS, settings.fps));
+        }
+        match proxy::bitrate_kbps(&settings.bitrate) {
+            Some(kbps) if (MIN_STREAM_KBPS..=MAX_STREAM_KBPS).contains(&kbps) => Ok(settings),
+            _ => Err(format!(
+                "Invalid stream bitrate '{}': expected {}k to {}k, e.g. \"4500k\"",
+                settings.bitrate, MIN_STREAM_KBPS, MAX_STREAM_KBPS
+            )),
+        }
+    }
+}
+
+// Where a stream goes
+#[derive(Debug, Clone)]
+struct StreamTarget {
+    protocol: &'static str,
+    // The muxer the protocol carries
+    format: &'static str,
+    url: String,
+    // Part of `url` kept out of errors and logs, e.g. a stream key
+    secret: Option<String>,
+}
+
+impl StreamTarget {
+    fn redact(&self, text: &str) -> String {
+        match &self.secret {
+            Some(secret) if !secret.is_empty() => text.replace(secret.as_str(), "****"),
+            _ => text.to_string(),
+        }
+    }
+}
+
+struct StreamJob {
+    ffmpeg_path: PathBuf,
+    clips: Vec<ClipData>,
+    audio_path: Option<String>,
+    position: f64,
+    settings: StreamSettings,
+    target: StreamTarget,
+}
+
+// The command streaming the timeline from `position`; None once that is past
+// its end
+fn stream_command(job: &StreamJob, position: f64) -> Option<Command> {
+    let StreamSettings { width, height, fps, ref bitrate } = job.settings;
+    let mut cmd = Command::new(&job.ffmpeg_path);
+    cmd.args(["-loglevel", "error", "-progress", "pipe:1"]);
+    let mut graph = Vec::new();
+    let mut offset = 0.0;
+    for clip in &job.clips {
+        let duration = clip.end_time - clip.start_time;
+        // Clips before the playhead are skipped, the one under it starts part-way
+        let skip = (position - offset).max(0.0);
+        offset += duration;
+        if skip >= duration {
+            continue;
+        }
+        cmd.args([
+            "-ss", &(clip.start_time + skip).to_string(),
+            "-t", &(duration - skip).to_string(),
+            "-i", &clip.file_path,
+        ]);
+        graph.push(format!(
+            "[{i}:v]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,\
+             setsar=1,fps={fps},format=yuv420p[v{i}]",
+            i = graph.len(),
+            w = width,
+            h = height,
+            fps = fps
+        ));
+    }
+    if graph.is_empty() {
+        return None;
+    }
+    let clip_count = graph.len();
+    match &job.audio_path {
+        Some(audio_path) => cmd.args(["-ss", &position.to_string(), "-i", audio_path]),
+        None => cmd.args(["-f", "lavfi", "-i", "anullsrc=r=44100:cl=stereo"]),
+    };
+    // Only the picture is held to real time; ffmpeg reads the audio as the
+    // muxer needs it. Audio that runs out is padded with silence, so the
+    // stream lasts as long as the clips.
+    let inputs: String = (0..clip_count).map(|i| format!("[v{}]", i)).collect();
+    graph.push(format!("{}concat=n={}:v=1:a=0,realtime[vout]", inputs, clip_count));
+    graph.push(format!("[{}:a]aresample=44100,apad[aout]", clip_count));
+    cmd.args(["-filter_complex", &graph.join(";"), "-map", "[vout]", "-map", "[aout]", "-shortest"]);
+    cmd.args(encode::low_latency_video(fps as f64));
+    cmd.args(["-b:v", bitrate, "-maxrate", bitrate, "-bufsize", bitrate]);
+    cmd.args(["-c:a", "aac", "-b:a", STREAM_AUDIO_BITRATE, "-f", job.target.format, &job.target.url]);
+    Some(cmd)
+}
+
+fn emit_status(app_handle: &tauri::AppHandle, job: &StreamJob, status: StreamStatus) {
+    let error = status.error.as_deref().map(|e| job.target.redact(e));
+    let _ = app_handle.emit("stream-status", StreamStatus { error, ..status });
+}
+
+// Waits out a reconnect delay; false if the stream was stopped meanwhile
+fn wait_unless_stopped(is_stopped: impl Fn() -> bool, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    while Instant::now() < deadline {
+        if is_stopped() {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    !is_stopped()
+}
+
+// `stop` covers a stop that comes before the job is registered, which
+// cancelling it would miss
+fn run_stream(job: StreamJob, job_id: &str, stop: &AtomicBool, app_handle: &tauri::AppHandle) {
+    let registry = app_handle.state::<ProcessRegistry>();
+    let Ok(_job) = registry.begin(job_id) else { return };
+    let is_stopped = || stop.load(Ordering::Relaxed) || registry.is_cancelled(job_id);
+    let status = |state, position, bitrate_kbps, error| StreamStatus {
+        protocol: job.target.protocol.to_string(),
+        state,
+        position,
+        bitrate_kbps,
+        error,
+    };
+
+    let mut position = job.position;
+    let mut reconnects = 0;
+    loop {
+        let Some(mut cmd) = stream_command(&job, position) else {
+            emit_status(app_handle, &job, status(StreamState::Stopped, Some(position), None, None));
+            return;
+        };
+        if is_stopped() {
+            emit_status(app_handle, &job, status(StreamState::Stopped, Some(position), None, None));
+            return;
+        }
+        emit_status(app_handle, &job, status(StreamState::Connecting, Some(position), None, None));
+        let started = Instant::now();
+        let mut sent = 0.0;
+        let mut last_error = None;
+        let result = registry.run_with_progress(
+            job_id,
+            &mut cmd,
+            |progress: &FfmpegProgress| {
+                // Nothing is muxed until the server has accepted the stream
+                if progress.total_size.is_some_and(|size| size > 0) {
+                    sent = progress.out_time.unwrap_or(sent);
+                    let status = status(StreamState::Live, Some(position + sent), progress.bitrate, None);
+                    emit_status(app_handle, &job, status);
+                }
+            },
+            |line| last_error = Some(line.to_string()),
+        );
+        position += sent;
+
+        if is_stopped() {
+            emit_status(app_handle, &job, status(StreamState::Stopped, Some(position), None, None));
+            return;
+        }
+        let error = match result {
+            Ok(exit) if exit.success() => {
+                emit_status(app_handle, &job, status(StreamState::Stopped, Some(position), None, None));
+                return;
+            }
+            Ok(_) => last_error.unwrap_or_else(|| "FFmpeg stream exited with error".to_string()),
+            Err(e) => e,
+        };
+        if started.elapsed() >= RECONNECT_RESET {
+            reconnects = 0;
+        }
+        reconnects += 1;
+        if reconnects > MAX_RECONNECTS {
+            emit_status(app_handle, &job, status(StreamState::Failed, Some(position), None, Some(error)));
+            return;
+        }
+        emit_status(app_handle, &job, status(StreamState::Reconnecting, Some(position), None, Some(error)));
+        if !wait_unless_stopped(is_stopped, RECONNECT_DELAY * reconnects) {
+            emit_status(app_handle, &job, status(StreamState::Stopped, Some(position), None, None));
+            return;
+        }
+    }
+}
+
+struct RunningStream {
+    job_id: String,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct LiveStream(Mutex<Option<RunningStream>>);
+
+impl LiveStream {
+    // Stops the running stream; false if there was none
+    pub fn stop(&self, registry: &ProcessRegistry) -> bool {
+        match self.0.lock().unwrap().take() {
+            Some(stream) => {
+                stream.stop.store(true, Ordering::Relaxed);
+                registry.cancel(&stream.job_id);
+                let _ = stream.thread.join();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn validate_timeline(clips: &[ClipData], audio_path: Option<&str>, position: f64) -> Result<(), String> {
+    if clips.is_empty() {
+        return Err("No clips to stream".to_string());
+    }
+    for clip in clips {
+        if !Path::new(&clip.file_path).is_file() {
+            return Err(format!("File not found: {}", clip.file_path));
+        }
+    }
+    if let Some(audio_path) = audio_path.filter(|path| !Path::new(path).is_file()) {
+        return Err(format!("File not found: {}", audio_path));
+    }
+    if !position.is_finite() || position < 0.0 {
+        return Err(format!("Invalid stream position: {}", position));
+    }
+    Ok(())
+}
+
+// Starts streaming the timeline to `target`, replacing any stream running
+#[allow(clippy::too_many_arguments)]
+fn start_stream(
+    target: StreamTarget,
+    clips: Vec<ClipData>,
+    audio_path: Option<String>,
+    position: Option<f64>,
+    settings: StreamSettings,
+    app_handle: &tauri::AppHandle,
+    state: &LiveStream,
+    registry: &ProcessRegistry,
+) -> Result<String, String> {
+    let position = position.unwrap_or(0.0);
+    validate_timeline(&clips, audio_path.as_deref(), position)?;
+    let job = StreamJob { ffmpeg_path: resolve_ffmpeg(app_handle)?, clips, audio_path, position, settings, target };
+
+    state.stop(registry);
+    let job_id = registry.new_job_id("stream");
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = {
+        let (app_handle, job_id, stop) = (app_handle.clone(), job_id.clone(), stop.clone());
+        std::thread::spawn(move || run_stream(job, &job_id, &stop, &app_handle))
+    };
+    *state.0.lock().unwrap() = Some(RunningStream { job_id: job_id.clone(), stop, thread });
+    Ok(job_id)
+}
+
+// Command to stream the timeline from `position` (seconds, default 0) to an
+// RTMP server: `url` is the server's ingest address, e.g.
+// "rtmp://live.twitch.tv/app", and `key` the stream key, which never appears
+// in errors. Defaults to 1280x720 at 30 fps and 4500k. `audio_path` is the
+// music under the clips. Returns the stream's job id; `stream-status`
+// reports its progress.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_stream_rtmp(
+    url: String,
+    key: String,
+    clips: Vec<ClipData>,
+    audio_path: Option<String>,
+    position: Option<f64>,
+    width: Option<u32>,
+    height: Option<u32>,
+    fps: Option<u32>,
+    bitrate: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, LiveStream>,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<String, String> {
+    let lower = url.to_ascii_lowercase();
+    if !lower.starts_with("rtmp://") && !lower.starts_with("rtmps://") {
+        return Err(format!("RTMP url must start with rtmp:// or rtmps://, got {}", url));
+    }
+    if key.is_empty() || key.chars().any(char::is_whitespace) {
+        return Err("Invalid stream key".to_string());
+    }
+    let settings = StreamSettings::from_args(width, height, fps, bitrate)?;
+    let target = StreamTarget {
+        protocol: "rtmp",
+        format: "flv",
+        url: format!("{}/{}", url.trim_end_matches('/'), key),
+        secret: Some(key),
+    };
+    start_stream(target, clips, audio_path, position, settings, &app_handle, &state, &registry)
+}
+
+// `url` with SRT's options appended to its query. ffmpeg takes `latency` in
+// microseconds.
+fn srt_url(url: &str, latency_ms: u32, passphrase: Option<&str>) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let mut url = format!("{}{}latency={}&pkt_size={}", url, separator, latency_ms as u64 * 1000, SRT_PACKET_SIZE);
+    if let Some(passphrase) = passphrase {
+        url.push_str(&format!("&passphrase={}", passphrase));
+    }
+    url
+}
+
+// Command to stream the timeline over SRT, like start_stream_rtmp, to `url`
+// ("srt://host:port", connecting as the caller). `latency_ms` (default 120)
+// is how long the receiver waits for lost packets to be resent; `passphrase`
+// (10 to 79 characters) encrypts the stream and never appears in errors.
+// Needs an ffmpeg built with libsrt.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_stream_srt(
+    url: String,
+    latency_ms: Option<u32>,
+    passphrase: Option<String>,
+    clips: Vec<ClipData>,
+    audio_path: Option<String>,
+    position: Option<f64>,
+    width: Option<u32>,
+    height: Option<u32>,
+    fps: Option<u32>,
+    bitrate: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, LiveStream>,
+    registry: tauri::State<'_, ProcessRegistry>,
+    capabilities: tauri::State<'_, CapabilitiesCache>,
+) -> Result<String, String> {
+    if !url.to_ascii_lowercase().starts_with("srt://") {
+        return Err(format!("SRT url must start with srt://, got {}", url));
+    }
+    let latency_ms = latency_ms.unwrap_or(DEFAULT_SRT_LATENCY_MS);
+    if latency_ms > MAX_SRT_LATENCY_MS {
+        return Err(format!("SRT latency must be at most {} ms, got {}", MAX_SRT_LATENCY_MS, latency_ms));
+    }
+    if let Some(passphrase) = &passphrase {
+        if !SRT_PASSPHRASE_CHARS.contains(&passphrase.chars().count()) {
+            return Err("SRT passphrase must be 10 to 79 characters".to_string());
+        }
+        // It goes in the url's query as it is
+        if passphrase.chars().any(|c| c.is_whitespace() || "&#?%".contains(c)) {
+            return Err("SRT passphrase can't contain spaces or any of & # ? %".to_string());
+        }
+    }
+    let settings = StreamSettings::from_args(width, height, fps, bitrate)?;
+    capabilities::require_output_protocol(&app_handle, &capabilities, "srt", "SRT streaming")?;
+    let target = StreamTarget {
+        protocol: "srt",
+        format: "mpegts",
+        url: srt_url(&url, latency_ms, passphrase.as_deref()),
+        secret: passphrase,
+    };
+    start_stream(target, clips, audio_path, position, settings, &app_handle, &state, &registry)
+}
+
+// Command to end the running stream. Returns false if none was running.
+#[tauri::command]
+pub async fn stop_stream(
+    state: tauri::State<'_, LiveStream>,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<bool, String> {
+    Ok(state.stop(&registry))
+}
@@ -0,0 +1,268 @@
+// Subtitles for an export: either burned into the picture with the subtitles
+// (libass) filter, or muxed as soft tracks in the final encode. Subtitle files
+// are timed against the whole timeline, so when burning, each clip's frames
+// are shifted to their timeline position while it renders. mux_subtitles adds
+// a track to a finished video without re-encoding it.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tauri::Emitter;
+
+use crate::encode::Container;
+use crate::overlay::escape_value;
+use crate::probe::{self, StreamKind};
+use crate::{resolve_ffmpeg, JobStarted, ProcessRegistry, JOB_CANCELLED_ERROR};
+
+// Sizes libass is asked for are kept to what stays readable on screen
+const MIN_FONT_SIZE: u32 = 8;
+const MAX_FONT_SIZE: u32 = 120;
+const MAX_OUTLINE: f64 = 10.0;
+
+// Overrides for how burned captions look; unset fields keep the file's own
+// style (or libass's default for SRT)
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SubtitleStyle {
+    #[serde(default)]
+    pub font_size: Option<u32>,
+    // #RRGGBB
+    #[serde(default)]
+    pub primary_color: Option<String>,
+    // Outline thickness around each letter, in pixels
+    #[serde(default)]
+    pub outline: Option<f64>,
+    // Numpad layout as in ASS: 1-3 bottom, 4-6 middle, 7-9 top
+    #[serde(default)]
+    pub alignment: Option<u8>,
+}
+
+// "#RRGGBB" as ASS's &HAABBGGRR, fully opaque
+fn ass_color(color: &str) -> Result<String, String> {
+    let hex = color
+        .strip_prefix('#')
+        .filter(|h| h.len() == 6 && h.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("Invalid subtitle color '{}', expected #RRGGBB", color))?;
+    let (r, g, b) = (&hex[0..2], &hex[2..4], &hex[4..6]);
+    Ok(format!("&H00{}{}{}", b, g, r).to_uppercase())
+}
+
+impl SubtitleStyle {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(color) = &self.primary_color {
+            ass_color(color)?;
+        }
+        if let Some(alignment) = self.alignment {
+            if !(1..=9).contains(&alignment) {
+                return Err(format!("Subtitle alignment must be between 1 and 9, got {}", alignment));
+            }
+        }
+        Ok(())
+    }
+
+    // force_style value, or None when nothing is overridden
+    fn force_style(&self) -> Option<String> {
+        let mut fields = Vec::new();
+        if let Some(size) = self.font_size {
+            fields.push(format!("FontSize={}", size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE)));
+        }
+        if let Some(color) = self.primary_color.as_deref().and_then(|c| ass_color(c).ok()) {
+            fields.push(format!("PrimaryColour={}", color));
+        }
+        if let Some(outline) = self.outline.filter(|o| o.is_finite()) {
+            fields.push(format!("Outline={}", outline.clamp(0.0, MAX_OUTLINE)));
+        }
+        if let Some(alignment) = self.alignment {
+            fields.push(format!("Alignment={}", alignment));
+        }
+        if fields.is_empty() {
+            return None;
+        }
+        Some(fields.join(","))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BurnedSubtitles {
+    // .srt, .ass/.ssa or .vtt, timed to the exported timeline
+    pub path: String,
+    #[serde(default)]
+    pub style: SubtitleStyle,
+}
+
+const SUBTITLE_EXTENSIONS: &[&str] = &["srt", "ass", "ssa", "vtt"];
+
+pub fn validate_subtitle_file(path: &str) -> Result<(), String> {
+    let file = Path::new(path);
+    if !file.is_file() {
+        return Err(format!("Subtitle file not found: {}", path));
+    }
+    let ext = file.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    if !ext.as_deref().is_some_and(|e| SUBTITLE_EXTENSIONS.contains(&e)) {
+        return Err(format!("Unsupported subtitle file {}; expected .srt, .ass, .ssa or .vtt", path));
+    }
+    Ok(())
+}
+
+impl BurnedSubtitles {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_subtitle_file(&self.path)?;
+        self.style.validate()
+    }
+
+    // Renders the captions onto a clip that starts `timeline_offset` seconds
+    // into the timeline: its frames are moved to timeline time for libass and
+    // back again afterwards
+    pub fn filter(&self, timeline_offset: f64) -> String {
+        let mut subtitles = format!("subtitles=filename='{}'", escape_value(&self.path));
+        if let Some(style) = self.style.force_style() {
+            subtitles.push_str(&format!(":force_style='{}'", style));
+        }
+        format!(
+            "setpts=PTS+{offset}/TB,{},setpts=PTS-{offset}/TB",
+            subtitles,
+            offset = timeline_offset
+        )
+    }
+}
+
+// Whether captions are drawn into the picture or muxed as tracks the viewer
+// can switch between
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleMode {
+    // `subtitles` is burned in
+    Burn,
+    // `subtitle_tracks` are muxed as selectable subtitle streams
+    Soft,
+}
+
+// One selectable subtitle stream
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubtitleTrack {
+    pub path: String,
+    // ISO 639-2 code, e.g. "eng" or "spa"
+    pub language: String,
+}
+
+fn validate_language(language: &str) -> Result<(), String> {
+    if !(language.len() == 3 && language.chars().all(|c| c.is_ascii_lowercase())) {
+        return Err(format!(
+            "Invalid subtitle language '{}', expected a three-letter code such as \"eng\"",
+            language
+        ));
+    }
+    Ok(())
+}
+
+impl SubtitleTrack {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_subtitle_file(&self.path)?;
+        validate_language(&self.language)
+    }
+}
+
+// Subtitle codec the output container takes: Matroska stores SRT, ASS and
+// WebVTT as they are, MP4 and MOV only take mov_text
+pub fn soft_subtitle_codec(container: Container) -> Result<&'static str, String> {
+    match container {
+        Container::Mkv => Ok("copy"),
+        Container::Mp4 | Container::Mov => Ok("mov_text"),
+        _ => Err("Subtitle tracks need an .mkv, .mp4 or .mov output".to_string()),
+    }
+}
+
+// Output arguments mapping each track, whose inputs start at `first_input`,
+// with its language tag
+pub fn track_args(tracks: &[SubtitleTrack], first_input: usize, container: Container) -> Vec<String> {
+    let mut args = Vec::new();
+    for (n, track) in tracks.iter().enumerate() {
+        args.push("-map".to_string());
+        args.push(format!("{}:s", first_input + n));
+        args.push(format!("-metadata:s:s:{}", n));
+        args.push(format!("language={}", track.language));
+    }
+    if tracks.is_empty() {
+        return args;
+    }
+    // The container was checked when the export started
+    if let Ok(codec) = soft_subtitle_codec(container) {
+        args.push("-c:s".to_string());
+        args.push(codec.to_string());
+    }
+    args
+}
+
+// "clip.mp4" -> "clip.subtitled.mp4", next to the original
+fn default_mux_output(video_path: &Path) -> PathBuf {
+    let stem = video_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match video_path.extension() {
+        Some(ext) => format!("{}.subtitled.{}", stem, ext.to_string_lossy()),
+        None => format!("{}.subtitled", stem),
+    };
+    video_path.with_file_name(name)
+}
+
+// Command to add `subtitle_path` to an existing video as a selectable track.
+// Every stream of the video, earlier subtitle tracks included, is copied as
+// is. The output (by default "<name>.subtitled.<ext>" next to the video) must
+// be .mkv, .mp4 or .mov. `job-started` carries the id for cancel_job. Returns
+// the output path.
+#[tauri::command]
+pub async fn mux_subtitles(
+    video_path: String,
+    subtitle_path: String,
+    output_path: Option<String>,
+    language: Option<String>,
+    app_handle: tauri::AppHandle,
+    registry: tauri::State<'_, ProcessRegistry>,
+) -> Result<String, String> {
+    if !Path::new(&video_path).is_file() {
+        return Err(format!("Video not found: {}", video_path));
+    }
+    validate_subtitle_file(&subtitle_path)?;
+    if let Some(language) = &language {
+        validate_language(language)?;
+    }
+    let output_path = output_path
+        .unwrap_or_else(|| default_mux_output(Path::new(&video_path)).to_string_lossy().into_owned());
+    if Path::new(&output_path) == Path::new(&video_path) {
+        return Err("The output must be a different file from the video".to_string());
+    }
+    let codec = soft_subtitle_codec(Container::from_path(&output_path))?;
+
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    // The new track's index among the output's subtitle streams
+    let existing = probe::probe(&ffmpeg_path, &video_path)?
+        .streams
+        .iter()
+        .filter(|s| s.kind == StreamKind::Subtitle)
+        .count();
+
+    let mut cmd = Command::new(&ffmpeg_path);
+    cmd.args(["-y", "-i", &video_path, "-i", &subtitle_path, "-map", "0", "-map", "1:0", "-c", "copy"]);
+    cmd.args(["-c:s", codec]);
+    if let Some(language) = &language {
+        cmd.args([format!("-metadata:s:s:{}", existing), format!("language={}", language)]);
+    }
+    cmd.arg(&output_path);
+
+    let job_id = registry.new_job_id("subtitles");
+    let _job = registry.begin(&job_id)?;
+    let _ = app_handle.emit("job-started", JobStarted { job_id: job_id.clone(), kind: "subtitles".to_string() });
+    let result = registry
+        .run(&job_id, &mut cmd)
+        .map_err(|e| format!("FFmpeg subtitle mux failed: {}", e))
+        .and_then(|status| match status.success() {
+            true => Ok(()),
+            false => Err("FFmpeg subtitle mux exited with error".to_string()),
+        });
+    if registry.is_cancelled(&job_id) {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(JOB_CANCELLED_ERROR.to_string());
+    }
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(e);
+    }
+    Ok(output_path)
+}
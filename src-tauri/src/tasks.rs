@@ -0,0 +1,259 @@
+// Exports run as background tasks: export_video checks the request, hands the
+// render to its own thread and returns a task id straight away, so the invoke
+// doesn't stay pending for the length of a render. Every event of a task
+// carries its id, and the task's state is kept for get_task_status and
+// get_task_result, so a reloaded UI can pick a running render back up.
+// Several tasks can run at once, each with its own temp dir and ffmpeg
+// processes (its task id is also its ProcessRegistry job), up to a
+// configurable limit; tasks beyond it wait their turn.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
+use tokio::sync::Semaphore;
+
+use crate::{ExportResult, CANCELLED_ERROR};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+// Step of the export a running task is at
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskPhase {
+    // Waiting for another export to finish
+    Queued,
+    // Probing, silence trimming and other checks before any clip is rendered
+    Preparing,
+    Trimming,
+    // Joining the trimmed clips through their transitions
+    Transitions,
+    // The final encode, local or remote
+    Encoding,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaskState {
+    pub status: TaskStatus,
+    // As in the last export-progress
+    pub percent: u8,
+    pub phase: TaskPhase,
+    // Set once the task succeeded
+    pub result: Option<ExportResult>,
+    // Set once it failed or was cancelled
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct TaskStore {
+    tasks: Mutex<HashMap<String, TaskState>>,
+    next_id: AtomicU64,
+}
+
+// Payload of `export-complete`, sent when a task succeeded
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ExportComplete {
+    task_id: String,
+    #[serde(flatten)]
+    result: ExportResult,
+}
+
+// Payload of `export-failed`, sent when a task failed (but not when it was
+// cancelled, which sends `export-cancelled`)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ExportFailed {
+    task_id: String,
+    error: String,
+    // Where the export was when it failed
+    phase: TaskPhase,
+}
+
+// Payload of `export-finished`, sent once per task however it ended
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TaskFinished {
+    task_id: String,
+    // Set when the export succeeded
+    result: Option<ExportResult>,
+    // Set when it failed or was cancelled
+    error: Option<String>,
+    cancelled: bool,
+}
+
+impl TaskStore {
+    // Registers a running task and returns its id, e.g. "export-3"
+    pub fn create(&self, kind: &str) -> String {
+        let id = format!("{}-{}", kind, self.next_id.fetch_add(1, Ordering::Relaxed));
+        let state = TaskState {
+            status: TaskStatus::Running,
+            percent: 0,
+            phase: TaskPhase::Queued,
+            result: None,
+            error: None,
+        };
+        self.tasks.lock().unwrap().insert(id.clone(), state);
+        id
+    }
+
+    pub fn running_ids(&self) -> Vec<String> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.iter().filter(|(_, state)| state.status == TaskStatus::Running).map(|(id, _)| id.clone()).collect()
+    }
+
+    pub fn set_percent(&self, task_id: &str, percent: u8) {
+        if let Some(state) = self.tasks.lock().unwrap().get_mut(task_id) {
+            state.percent = percent;
+        }
+    }
+
+    pub fn set_phase(&self, task_id: &str, phase: TaskPhase) {
+        if let Some(state) = self.tasks.lock().unwrap().get_mut(task_id) {
+            state.phase = phase;
+        }
+    }
+
+    fn phase(&self, task_id: &str) -> Option<TaskPhase> {
+        self.tasks.lock().unwrap().get(task_id).map(|state| state.phase)
+    }
+
+    fn finish(&self, task_id: &str, result: Result<ExportResult, String>) {
+        if let Some(state) = self.tasks.lock().unwrap().get_mut(task_id) {
+            match result {
+                Ok(result) => {
+                    state.status = TaskStatus::Succeeded;
+                    state.result = Some(result);
+                }
+                Err(error) => {
+                    let cancelled = error == CANCELLED_ERROR;
+                    state.status = if cancelled { TaskStatus::Cancelled } else { TaskStatus::Failed };
+                    state.error = Some(error);
+                }
+            }
+        }
+    }
+}
+
+// Records how a task ended and announces it: `export-complete` or
+// `export-failed` first, then `export-finished` in every case
+pub fn complete(app_handle: &tauri::AppHandle, task_id: &str, result: Result<ExportResult, String>) {
+    let store = app_handle.state::<TaskStore>();
+    let finished = TaskFinished {
+        task_id: task_id.to_string(),
+        result: result.as_ref().ok().cloned(),
+        error: result.as_ref().err().cloned(),
+        cancelled: result.as_ref().err().is_some_and(|e| e == CANCELLED_ERROR),
+    };
+    match &result {
+        Ok(result) => {
+            let complete = ExportComplete { task_id: task_id.to_string(), result: result.clone() };
+            let _ = app_handle.emit("export-complete", complete);
+        }
+        Err(_) if finished.cancelled => {}
+        Err(error) => {
+            let failed = ExportFailed {
+                task_id: task_id.to_string(),
+                error: error.clone(),
+                phase: store.phase(task_id).unwrap_or(TaskPhase::Preparing),
+            };
+            let _ = app_handle.emit("export-failed", failed);
+        }
+    }
+    store.finish(task_id, result);
+    let _ = app_handle.emit("export-finished", finished);
+}
+
+fn unknown_task(task_id: &str) -> String {
+    format!("Unknown task '{}'", task_id)
+}
+
+// Command to fetch a task's outcome: None while it is still running, the
+// export's result once it succeeded, and its error once it failed
+#[tauri::command]
+pub async fn get_task_result(
+    task_id: String,
+    store: tauri::State<'_, TaskStore>,
+) -> Result<Option<ExportResult>, String> {
+    let tasks = store.tasks.lock().unwrap();
+    let state = tasks.get(&task_id).ok_or_else(|| unknown_task(&task_id))?;
+    match &state.error {
+        Some(error) => Err(error.clone()),
+        None => Ok(state.result.clone()),
+    }
+}
+
+// Command to read where a task is: its status, progress and phase, and its
+// result or error once it has finished
+#[tauri::command]
+pub async fn get_task_status(task_id: String, store: tauri::State<'_, TaskStore>) -> Result<TaskState, String> {
+    store.tasks.lock().unwrap().get(&task_id).cloned().ok_or_else(|| unknown_task(&task_id))
+}
+
+// Default number of exports rendering at once. Each already runs several
+// encoder threads, so more than this mostly makes every export slower.
+pub const DEFAULT_MAX_CONCURRENT_EXPORTS: usize = 2;
+
+// Limits how many tasks of one kind run at once. A single semaphore is kept
+// for good: raising the limit adds permits, and lowering it takes permits
+// back, the free ones straight away and the rest as running tasks release
+// them, so running and queued tasks always count against the current limit.
+pub struct Slots {
+    semaphore: Arc<Semaphore>,
+    limit: Mutex<usize>,
+}
+
+impl Slots {
+    pub fn new(limit: usize) -> Self {
+        Slots { semaphore: Arc::new(Semaphore::new(limit)), limit: Mutex::new(limit) }
+    }
+
+    pub fn semaphore(&self) -> Arc<Semaphore> {
+        self.semaphore.clone()
+    }
+
+    pub fn resize(&self, limit: usize) {
+        let mut current = self.limit.lock().unwrap();
+        if limit > *current {
+            self.semaphore.add_permits(limit - *current);
+        } else if limit < *current {
+            let excess = *current - limit;
+            let owed = excess - self.semaphore.forget_permits(excess);
+            if owed > 0 {
+                let semaphore = self.semaphore.clone();
+                tauri::async_runtime::spawn(async move {
+                    for _ in 0..owed {
+                        match semaphore.clone().acquire_owned().await {
+                            Ok(permit) => permit.forget(),
+                            Err(_) => return,
+                        }
+                    }
+                });
+            }
+        }
+        *current = limit;
+    }
+}
+
+// Limits how many export tasks, and proxy encodes of a project batch, render
+// at once
+pub struct ExportSlots(pub Slots);
+
+// Command to change how many exports (and project proxy encodes) may render at
+// once
+#[tauri::command]
+pub async fn set_max_concurrent_exports(
+    max_exports: usize,
+    slots: tauri::State<'_, ExportSlots>,
+) -> Result<(), String> {
+    if max_exports == 0 {
+        return Err("Export concurrency must be at least 1".to_string());
+    }
+    slots.0.resize(max_exports);
+    Ok(())
+}
@@ -0,0 +1,230 @@
+// Working directories for exports. Each export gets its own directory under
+// the temp root (the system temp dir unless the user picked another), named
+// after the process that made it, and removes it when done. A crash skips
+// that, so directories left by other (dead) runs are cleaned up at startup
+// once they are old enough to be clearly abandoned. Every directory the user
+// picks is remembered, so that covers crashed runs outside the system temp
+// dir too.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tauri::{Emitter, Manager};
+
+const EXPORT_DIR_PREFIX: &str = "rvj_export";
+
+// Age after which another run's directory counts as abandoned. Generous, as
+// a second instance of the app may be in the middle of a long export.
+const STALE_AFTER: Duration = Duration::from_secs(6 * 60 * 60);
+
+static NEXT_EXPORT_DIR: AtomicU64 = AtomicU64::new(0);
+
+// Lists, in the app's cache dir, every temp dir set_temp_dir was given
+const CHOSEN_ROOTS_FILE: &str = "temp_roots.txt";
+
+// Directory chosen for intermediates instead of the system temp dir, e.g. a
+// fast SSD or a drive with room for a 4K timeline's tens of GB
+#[derive(Default)]
+pub struct TempDirConfig(pub Mutex<Option<PathBuf>>);
+
+impl TempDirConfig {
+    // Where export directories are created right now
+    pub fn root(&self) -> PathBuf {
+        self.0.lock().unwrap().clone().unwrap_or_else(std::env::temp_dir)
+    }
+}
+
+// Creates `dir` if needed and checks a file can be written in it
+pub fn ensure_writable(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create temp dir {}: {}", dir.display(), e))?;
+    let probe = dir.join(format!(".{}-write-test-{}", EXPORT_DIR_PREFIX, std::process::id()));
+    std::fs::write(&probe, b"").map_err(|e| format!("Temp dir {} is not writable: {}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+// A fresh directory name under `root` for one export run: rvj_export-{pid}-{n}
+pub fn export_dir(root: &Path) -> PathBuf {
+    let n = NEXT_EXPORT_DIR.fetch_add(1, Ordering::Relaxed);
+    root.join(format!("{}-{}-{}", EXPORT_DIR_PREFIX, std::process::id(), n))
+}
+
+// Whether `name` is an export directory of this process, which is either in
+// use or about to be removed by its export
+fn is_own(name: &str) -> bool {
+    name.strip_prefix(EXPORT_DIR_PREFIX)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|rest| rest.split('-').next())
+        .is_some_and(|pid| pid == std::process::id().to_string())
+}
+
+// What a cleanup removed, and what it couldn't with the reason
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct TempCleanup {
+    pub removed: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+// Removes export directories in `root` (including the old shared
+// `rvj_export`) that other runs left behind and that haven't changed for
+// `max_age`, adding them to `cleanup`
+pub fn remove_stale(root: &Path, max_age: Duration, cleanup: &mut TempCleanup) {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(EXPORT_DIR_PREFIX) || is_own(&name) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else { continue };
+        let age = metadata.modified().ok().and_then(|m| now.duration_since(m).ok());
+        if !metadata.is_dir() || age.is_none_or(|age| age < max_age) {
+            continue;
+        }
+        let path = entry.path().to_string_lossy().into_owned();
+        match std::fs::remove_dir_all(entry.path()) {
+            Ok(()) => cleanup.removed.push(path),
+            Err(e) => cleanup.failed.push(format!("{}: {}", path, e)),
+        }
+    }
+}
+
+fn chosen_roots_file(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    Some(app_handle.path().app_cache_dir().ok()?.join(CHOSEN_ROOTS_FILE))
+}
+
+// Temp dirs chosen in this or earlier runs
+fn chosen_roots(app_handle: &tauri::AppHandle) -> Vec<PathBuf> {
+    let text = chosen_roots_file(app_handle).and_then(|file| std::fs::read_to_string(file).ok());
+    text.map_or(Vec::new(), |text| text.lines().filter(|l| !l.is_empty()).map(PathBuf::from).collect())
+}
+
+// Adds `dir` to the chosen roots. Failing to only means a crash there isn't
+// cleaned up at the next startup, which isn't worth failing set_temp_dir over.
+fn remember_root(app_handle: &tauri::AppHandle, dir: &Path) {
+    let Some(file) = chosen_roots_file(app_handle) else { return };
+    let mut roots = chosen_roots(app_handle);
+    if roots.iter().any(|root| root == dir) {
+        return;
+    }
+    roots.push(dir.to_path_buf());
+    let text: String = roots.iter().map(|root| format!("{}\n", root.display())).collect();
+    if let Some(parent) = file.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let _ = std::fs::write(file, text);
+}
+
+// Every directory exports may have been made in: the system temp dir, the
+// current choice and the ones chosen before
+fn all_roots(app_handle: &tauri::AppHandle) -> Vec<PathBuf> {
+    let mut roots = vec![std::env::temp_dir(), app_handle.state::<TempDirConfig>().root()];
+    roots.extend(chosen_roots(app_handle));
+    let mut unique = Vec::with_capacity(roots.len());
+    for root in roots {
+        if !unique.contains(&root) {
+            unique.push(root);
+        }
+    }
+    unique
+}
+
+// What the startup cleanup did, for a UI that loaded after `temp-cleanup`
+#[derive(Default)]
+pub struct StartupCleanup(pub Mutex<Option<TempCleanup>>);
+
+// Cleans up after crashed runs; called once from setup, off the main thread.
+// Anything removed or left behind is reported as `temp-cleanup`.
+pub fn cleanup_on_startup(app_handle: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        let mut cleanup = TempCleanup::default();
+        for root in all_roots(&app_handle) {
+            remove_stale(&root, STALE_AFTER, &mut cleanup);
+        }
+        if !cleanup.removed.is_empty() || !cleanup.failed.is_empty() {
+            let _ = app_handle.emit("temp-cleanup", &cleanup);
+        }
+        *app_handle.state::<StartupCleanup>().0.lock().unwrap() = Some(cleanup);
+    });
+}
+
+// Command to read what the startup cleanup removed and couldn't remove; None
+// while it is still running
+#[tauri::command]
+pub async fn get_startup_cleanup(state: tauri::State<'_, StartupCleanup>) -> Result<Option<TempCleanup>, String> {
+    Ok(state.0.lock().unwrap().clone())
+}
+
+// Command to remove abandoned export directories now, from the system temp
+// dir and every chosen one. `older_than_hours` defaults to the startup
+// threshold; the running app's own exports are never touched.
+#[tauri::command]
+pub async fn cleanup_temp(older_than_hours: Option<f64>, app_handle: tauri::AppHandle) -> Result<TempCleanup, String> {
+    let max_age = match older_than_hours {
+        Some(hours) if hours.is_finite() && hours >= 0.0 => Duration::from_secs_f64(hours * 3600.0),
+        Some(hours) => return Err(format!("Invalid older_than_hours: {}", hours)),
+        None => STALE_AFTER,
+    };
+    let mut cleanup = TempCleanup::default();
+    for root in all_roots(&app_handle) {
+        remove_stale(&root, max_age, &mut cleanup);
+    }
+    Ok(cleanup)
+}
+
+// Command to put export intermediates under `path`, or with None, back in the
+// system temp dir. The directory is created if needed and must be writable.
+#[tauri::command]
+pub async fn set_temp_dir(
+    path: Option<String>,
+    config: tauri::State<'_, TempDirConfig>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let dir = path.map(PathBuf::from);
+    if let Some(dir) = &dir {
+        if !dir.is_absolute() {
+            return Err(format!("Temp dir must be an absolute path, got {}", dir.display()));
+        }
+        ensure_writable(dir)?;
+        remember_root(&app_handle, dir);
+    }
+    *config.0.lock().unwrap() = dir;
+    Ok(())
+}
+
+// Command to read where export intermediates currently go
+#[tauri::command]
+pub async fn get_temp_dir(config: tauri::State<'_, TempDirConfig>) -> Result<String, String> {
+    Ok(config.root().to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_own_matches_this_process_only() {
+        let pid = std::process::id();
+        assert!(is_own(&format!("rvj_export-{}-0", pid)));
+        assert!(is_own(&format!("rvj_export-{}-12", pid)));
+        assert!(!is_own(&format!("rvj_export-{}-0", pid + 1)));
+        assert!(!is_own(&format!("rvj_export-{}0-0", pid)));
+        // The old shared directory belongs to no run
+        assert!(!is_own("rvj_export"));
+        assert!(!is_own(&format!("other-{}-0", pid)));
+    }
+
+    #[test]
+    fn export_dirs_are_unique_and_own() {
+        let root = std::env::temp_dir();
+        let (first, second) = (export_dir(&root), export_dir(&root));
+        assert_ne!(first, second);
+        for dir in [first, second] {
+            assert!(is_own(&dir.file_name().unwrap().to_string_lossy()));
+        }
+    }
+}
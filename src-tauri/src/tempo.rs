@@ -0,0 +1,268 @@
+// Tempo sections of a track. A DJ mix or a live set rarely holds one BPM,
+// so rather than one global estimate the track is split into stretches of
+// steady tempo: onsets are found from the rise of the spectrum (see
+// spectrum.rs), the tempo of each overlapping window is read off the onsets'
+// autocorrelation, and neighbouring windows that agree are merged.
+
+use serde::{Deserialize, Serialize};
+
+use crate::resolve_ffmpeg;
+use crate::spectrum;
+
+// Seconds between onset strength values, and the bands they are measured over
+const ONSET_INTERVAL: f64 = 0.01;
+const ONSET_BANDS: usize = 32;
+
+// Tempo is estimated over windows this long, this far apart. A window holds
+// a dozen or more beats, enough to settle on one tempo.
+const WINDOW_SECONDS: f64 = 8.0;
+const WINDOW_HOP_SECONDS: f64 = 4.0;
+
+// Tempos looked for, as the timeline's BPM controls accept
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+
+// Tempos are mostly near this; used to pick between a tempo and its double
+// or half, which correlate almost as well
+const TYPICAL_BPM: f64 = 120.0;
+
+// Windows within this fraction of a section's tempo belong to it
+const TEMPO_TOLERANCE: f64 = 0.04;
+
+// Sections shorter than this many windows are folded into a neighbour, so a
+// fill or a noisy window doesn't split a section in two
+const MIN_SECTION_WINDOWS: usize = 2;
+
+// A stretch of the track at one tempo, in seconds from the file's start
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct TempoSection {
+    pub start: f64,
+    pub end: f64,
+    pub bpm: f64,
+    // 0 to 1: how clearly the onsets repeat at this tempo. Low for
+    // breakdowns and beatless passages, whose bpm is little more than a guess.
+    pub confidence: f64,
+}
+
+// How much the spectrum rises at each step, summed over bands, with the
+// local average taken off so only onsets stand out
+fn onset_strength(rows: &[Vec<f32>]) -> Vec<f64> {
+    // Compressed, so quiet bands count alongside loud ones
+    let compress = |m: f32| (1.0 + 100.0 * m as f64).ln();
+    let flux: Vec<f64> = rows
+        .windows(2)
+        .map(|pair| pair[0].iter().zip(&pair[1]).map(|(a, b)| (compress(*b) - compress(*a)).max(0.0)).sum())
+        .collect();
+    let radius = (0.25 / ONSET_INTERVAL) as usize;
+    (0..flux.len())
+        .map(|i| {
+            let around = &flux[i.saturating_sub(radius)..(i + radius + 1).min(flux.len())];
+            (flux[i] - around.iter().sum::<f64>() / around.len() as f64).max(0.0)
+        })
+        .collect()
+}
+
+// Best tempo of one window of onset strengths and its confidence
+fn window_tempo(onsets: &[f64]) -> (f64, f64) {
+    let energy: f64 = onsets.iter().map(|o| o * o).sum();
+    if energy <= 0.0 {
+        return (TYPICAL_BPM, 0.0);
+    }
+    let correlation = |lag: usize| onsets.iter().zip(&onsets[lag..]).map(|(a, b)| a * b).sum::<f64>() / energy;
+    let bpm_of = |lag: f64| 60.0 / (lag * ONSET_INTERVAL);
+    let min_lag = (60.0 / MAX_BPM / ONSET_INTERVAL).floor() as usize;
+    let max_lag = ((60.0 / MIN_BPM / ONSET_INTERVAL).ceil() as usize).min(onsets.len().saturating_sub(2));
+    if min_lag < 1 || max_lag <= min_lag {
+        return (TYPICAL_BPM, 0.0);
+    }
+
+    // Each lag is weighted by how close its tempo is to TYPICAL_BPM, from 1
+    // there down towards half an octave or more away
+    let weight = |lag: usize| {
+        let octaves = (bpm_of(lag as f64) / TYPICAL_BPM).log2();
+        0.5 + 0.5 * (-octaves * octaves).exp()
+    };
+    let scores: Vec<f64> = (min_lag - 1..=max_lag + 1).map(correlation).collect();
+    let Some(best) = (1..scores.len() - 1)
+        .filter(|&i| scores[i] >= scores[i - 1] && scores[i] >= scores[i + 1])
+        .max_by(|&a, &b| (scores[a] * weight(a + min_lag - 1)).total_cmp(&(scores[b] * weight(b + min_lag - 1))))
+    else {
+        return (TYPICAL_BPM, 0.0);
+    };
+
+    // Between the lags either side of the peak, for a tempo finer than one
+    // step
+    let (before, peak, after) = (scores[best - 1], scores[best], scores[best + 1]);
+    let curve = before - 2.0 * peak + after;
+    let shift = if curve < 0.0 { (0.5 * (before - after) / curve).clamp(-0.5, 0.5) } else { 0.0 };
+    let lag = (best + min_lag - 1) as f64 + shift;
+    (bpm_of(lag), peak.clamp(0.0, 1.0))
+}
+
+struct Section {
+    first: usize,
+    // One (bpm, confidence) per window
+    windows: Vec<(f64, f64)>,
+}
+
+impl Section {
+    // Confidence-weighted, so a window that barely found a beat doesn't pull
+    // the tempo
+    fn bpm(&self) -> f64 {
+        let weight: f64 = self.windows.iter().map(|w| w.1).sum();
+        if weight <= 0.0 {
+            return self.windows.iter().map(|w| w.0).sum::<f64>() / self.windows.len() as f64;
+        }
+        self.windows.iter().map(|w| w.0 * w.1).sum::<f64>() / weight
+    }
+
+    fn confidence(&self) -> f64 {
+        self.windows.iter().map(|w| w.1).sum::<f64>() / self.windows.len() as f64
+    }
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= TEMPO_TOLERANCE * a.max(b)
+}
+
+// Sections of steady tempo over `length` seconds, from per-window tempos
+fn segment(windows: &[(f64, f64)], length: f64) -> Vec<TempoSection> {
+    let mut sections: Vec<Section> = Vec::new();
+    for (i, window) in windows.iter().enumerate() {
+        match sections.last_mut() {
+            Some(section) if close(section.bpm(), window.0) => section.windows.push(*window),
+            _ => sections.push(Section { first: i, windows: vec![*window] }),
+        }
+    }
+
+    // Short sections join whichever neighbour is nearer in tempo, until
+    // none are left or only one section remains. Their windows keep their
+    // confidence but take the tempo of the section they join, so a fill
+    // doesn't pull it away from the sections either side.
+    while sections.len() > 1 {
+        let Some(short) = sections.iter().position(|s| s.windows.len() < MIN_SECTION_WINDOWS) else { break };
+        let bpm = sections[short].bpm();
+        let into_previous = match (short.checked_sub(1), sections.get(short + 1)) {
+            (Some(previous), Some(next)) => (sections[previous].bpm() - bpm).abs() <= (next.bpm() - bpm).abs(),
+            (Some(_), None) => true,
+            _ => false,
+        };
+        let removed = sections.remove(short);
+        let target = &mut sections[if into_previous { short - 1 } else { short }];
+        let target_bpm = target.bpm();
+        let folded = removed.windows.iter().map(|w| (target_bpm, w.1));
+        if into_previous {
+            target.windows.extend(folded);
+        } else {
+            target.first = removed.first;
+            target.windows.splice(0..0, folded);
+        }
+        // A merge can leave two neighbours at the same tempo
+        let mut i = 1;
+        while i < sections.len() {
+            if close(sections[i - 1].bpm(), sections[i].bpm()) {
+                let merged = sections.remove(i);
+                sections[i - 1].windows.extend(merged.windows);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    // Each section changes over halfway through the overlap of its first
+    // window with the previous one
+    let boundary = |window: usize| window as f64 * WINDOW_HOP_SECONDS + (WINDOW_SECONDS - WINDOW_HOP_SECONDS) / 2.0;
+    let count = sections.len();
+    sections
+        .iter()
+        .enumerate()
+        .map(|(i, section)| TempoSection {
+            start: if i == 0 { 0.0 } else { boundary(section.first) },
+            end: if i + 1 == count { length } else { boundary(sections[i + 1].first) },
+            bpm: (section.bpm() * 100.0).round() / 100.0,
+            confidence: section.confidence(),
+        })
+        .collect()
+}
+
+// Command to split a track (or start..end of it) into sections of steady
+// tempo, in order and covering it end to end
+#[tauri::command]
+pub async fn detect_tempo_sections(
+    file_path: String,
+    start: Option<f64>,
+    end: Option<f64>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<TempoSection>, String> {
+    if let (Some(start), Some(end)) = (start, end) {
+        if end <= start {
+            return Err(format!("Invalid range: end {} is not after start {}", end, start));
+        }
+    }
+    let ffmpeg_path = resolve_ffmpeg(&app_handle)?;
+    let rows = spectrum::spectrum(&ffmpeg_path, &file_path, start, end, ONSET_BANDS, ONSET_INTERVAL)?;
+    let onsets = onset_strength(&rows);
+    let length = rows.len() as f64 * ONSET_INTERVAL;
+    if length < WINDOW_SECONDS {
+        return Err(format!("At least {} seconds of audio are needed to find the tempo", WINDOW_SECONDS));
+    }
+
+    let window_len = (WINDOW_SECONDS / ONSET_INTERVAL) as usize;
+    let hop = (WINDOW_HOP_SECONDS / ONSET_INTERVAL) as usize;
+    let windows: Vec<(f64, f64)> = (0..)
+        .map(|i| i * hop)
+        .take_while(|from| from + window_len <= onsets.len().max(window_len))
+        .map(|from| window_tempo(&onsets[from..(from + window_len).min(onsets.len())]))
+        .collect();
+
+    let offset = start.unwrap_or(0.0);
+    Ok(segment(&windows, length)
+        .into_iter()
+        .map(|section| TempoSection { start: section.start + offset, end: section.end + offset, ..section })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows(tempos: &[(f64, usize)]) -> Vec<(f64, f64)> {
+        tempos.iter().flat_map(|&(bpm, count)| std::iter::repeat_n((bpm, 1.0), count)).collect()
+    }
+
+    #[test]
+    fn steady_tempo_is_one_section() {
+        let sections = segment(&windows(&[(120.0, 10)]), 44.0);
+        assert_eq!(sections, vec![TempoSection { start: 0.0, end: 44.0, bpm: 120.0, confidence: 1.0 }]);
+    }
+
+    #[test]
+    fn tempo_changes_split_sections_halfway_through_the_overlap() {
+        let sections = segment(&windows(&[(120.0, 4), (140.0, 4)]), 36.0);
+        assert_eq!(sections.len(), 2);
+        assert_eq!((sections[0].start, sections[0].end, sections[0].bpm), (0.0, 18.0, 120.0));
+        assert_eq!((sections[1].start, sections[1].end, sections[1].bpm), (18.0, 36.0, 140.0));
+    }
+
+    #[test]
+    fn small_drift_stays_in_one_section() {
+        let sections = segment(&[(120.0, 1.0), (121.0, 1.0), (119.5, 1.0), (120.5, 1.0)], 20.0);
+        assert_eq!(sections.len(), 1);
+        assert!((sections[0].bpm - 120.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn a_single_odd_window_is_folded_in() {
+        let sections = segment(&windows(&[(120.0, 4), (90.0, 1), (120.0, 4)]), 40.0);
+        assert_eq!(sections.len(), 1);
+        assert_eq!((sections[0].start, sections[0].end), (0.0, 40.0));
+    }
+
+    #[test]
+    fn low_confidence_windows_barely_pull_the_tempo() {
+        let sections = segment(&[(120.0, 1.0), (124.0, 0.0), (120.0, 1.0)], 16.0);
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].bpm, 120.0);
+        assert!((sections[0].confidence - 2.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,60 @@
+// GPU texture sharing with other visual apps on the same machine: Spout on
+// Windows, Syphon on macOS. Both hand over a texture the sender rendered, but
+// rVJ's preview is decoded by ffmpeg into JPEG frames in system memory, so
+// there is no texture to share, and sharing one would need the Spout and
+// Syphon SDKs, which aren't part of this build. Until the preview renders on
+// the GPU the commands are no-ops the UI can still offer: they start nothing
+// and say why, so it can point at NDI (ndi.rs), which reaches the same apps.
+
+use serde::Serialize;
+
+const MAX_SENDER_NAME_CHARS: usize = 256;
+
+// What a start command did. `started` is false when there is no sharing on
+// this platform or in this build, and `reason` says which.
+#[derive(Debug, Serialize, Clone)]
+pub struct TextureShareStatus {
+    pub started: bool,
+    pub reason: Option<String>,
+}
+
+impl TextureShareStatus {
+    fn unsupported(reason: &str) -> Self {
+        TextureShareStatus { started: false, reason: Some(reason.to_string()) }
+    }
+}
+
+fn validate_sender_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Sender name can't be empty".to_string());
+    }
+    if name.chars().count() > MAX_SENDER_NAME_CHARS || name.chars().any(char::is_control) {
+        return Err(format!(
+            "Sender name must be at most {} characters, without control characters",
+            MAX_SENDER_NAME_CHARS
+        ));
+    }
+    Ok(())
+}
+
+// Command to share the preview over Spout as sender `name`
+#[tauri::command]
+pub async fn start_spout_output(name: String) -> Result<TextureShareStatus, String> {
+    validate_sender_name(&name)?;
+    Ok(TextureShareStatus::unsupported(if cfg!(target_os = "windows") {
+        "Spout output isn't available in this build yet; use NDI output instead"
+    } else {
+        "Spout is only available on Windows"
+    }))
+}
+
+// Command to share the preview over Syphon as server `name`
+#[tauri::command]
+pub async fn start_syphon_output(name: String) -> Result<TextureShareStatus, String> {
+    validate_sender_name(&name)?;
+    Ok(TextureShareStatus::unsupported(if cfg!(target_os = "macos") {
+        "Syphon output isn't available in this build yet; use NDI output instead"
+    } else {
+        "Syphon is only available on macOS"
+    }))
+}
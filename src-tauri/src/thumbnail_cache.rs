@@ -0,0 +1,56 @@
+// On-disk cache of generated thumbnails, in the app's cache directory. An
+// entry is keyed by the source path, the frame's timestamp, the image format
+// and quality, and the source's size and modification time, so overwriting a
+// file with a new one of the same name misses the cache instead of showing
+// the old frame.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tauri::Manager;
+
+// Bumped whenever thumbnails are rendered differently, to retire old entries
+const CACHE_VERSION: u32 = 1;
+
+pub fn cache_dir(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    let dir = app_handle.path().app_cache_dir().ok()?.join("thumbnails");
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+// Where the thumbnail of `file_path` at `timestamp`, as an `extension` image
+// of `quality`, is cached. None if the source can't be read, in which case it
+// isn't cached at all.
+pub fn entry_path(
+    dir: &Path,
+    file_path: &str,
+    timestamp: f64,
+    extension: &str,
+    quality: Option<u32>,
+) -> Option<PathBuf> {
+    let metadata = std::fs::metadata(file_path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    let mut hasher = DefaultHasher::new();
+    CACHE_VERSION.hash(&mut hasher);
+    file_path.hash(&mut hasher);
+    timestamp.to_bits().hash(&mut hasher);
+    quality.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    modified.as_nanos().hash(&mut hasher);
+    Some(dir.join(format!("{:016x}.{}", hasher.finish(), extension)))
+}
+
+pub fn load(entry: &Path) -> Option<Vec<u8>> {
+    std::fs::read(entry).ok().filter(|png| !png.is_empty())
+}
+
+// Best effort: a thumbnail that can't be cached is still returned
+pub fn store(entry: &Path, png: &[u8]) {
+    // Written aside and renamed, so a concurrent lookup never reads half a file
+    let partial = entry.with_extension("partial");
+    if std::fs::write(&partial, png).is_ok() && std::fs::rename(&partial, entry).is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+}
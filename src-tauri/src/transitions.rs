@@ -0,0 +1,271 @@
+// Transitions between clips. Hard cuts are joined by the concat demuxer as
+// before; when any clip has a transition the trimmed intermediates are first
+// merged into one with an xfade/acrossfade graph, and that single file goes
+// through the usual final encode.
+
+use serde::{Deserialize, Serialize};
+
+// xfade's `transition=` styles. Serialized as xfade spells them.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitionType {
+    // What the timeline has always called a crossfade
+    #[serde(alias = "crossfade")]
+    Fade,
+    Dissolve,
+    FadeBlack,
+    FadeWhite,
+    WipeLeft,
+    WipeRight,
+    WipeUp,
+    WipeDown,
+    SlideLeft,
+    SlideRight,
+    SlideUp,
+    SlideDown,
+    CircleOpen,
+    CircleClose,
+    CircleCrop,
+    RectCrop,
+    Radial,
+    Pixelize,
+    ZoomIn,
+}
+
+impl TransitionType {
+    const ALL: [TransitionType; 19] = [
+        TransitionType::Fade,
+        TransitionType::Dissolve,
+        TransitionType::FadeBlack,
+        TransitionType::FadeWhite,
+        TransitionType::WipeLeft,
+        TransitionType::WipeRight,
+        TransitionType::WipeUp,
+        TransitionType::WipeDown,
+        TransitionType::SlideLeft,
+        TransitionType::SlideRight,
+        TransitionType::SlideUp,
+        TransitionType::SlideDown,
+        TransitionType::CircleOpen,
+        TransitionType::CircleClose,
+        TransitionType::CircleCrop,
+        TransitionType::RectCrop,
+        TransitionType::Radial,
+        TransitionType::Pixelize,
+        TransitionType::ZoomIn,
+    ];
+
+    // Name of the style in xfade's `transition=` option
+    fn xfade_name(self) -> &'static str {
+        match self {
+            TransitionType::Fade => "fade",
+            TransitionType::Dissolve => "dissolve",
+            TransitionType::FadeBlack => "fadeblack",
+            TransitionType::FadeWhite => "fadewhite",
+            TransitionType::WipeLeft => "wipeleft",
+            TransitionType::WipeRight => "wiperight",
+            TransitionType::WipeUp => "wipeup",
+            TransitionType::WipeDown => "wipedown",
+            TransitionType::SlideLeft => "slideleft",
+            TransitionType::SlideRight => "slideright",
+            TransitionType::SlideUp => "slideup",
+            TransitionType::SlideDown => "slidedown",
+            TransitionType::CircleOpen => "circleopen",
+            TransitionType::CircleClose => "circleclose",
+            TransitionType::CircleCrop => "circlecrop",
+            TransitionType::RectCrop => "rectcrop",
+            TransitionType::Radial => "radial",
+            TransitionType::Pixelize => "pixelize",
+            TransitionType::ZoomIn => "zoomin",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            TransitionType::Fade => "Crossfade",
+            TransitionType::Dissolve => "Dissolve",
+            TransitionType::FadeBlack => "Fade through black",
+            TransitionType::FadeWhite => "Fade through white",
+            TransitionType::WipeLeft => "Wipe left",
+            TransitionType::WipeRight => "Wipe right",
+            TransitionType::WipeUp => "Wipe up",
+            TransitionType::WipeDown => "Wipe down",
+            TransitionType::SlideLeft => "Slide left",
+            TransitionType::SlideRight => "Slide right",
+            TransitionType::SlideUp => "Slide up",
+            TransitionType::SlideDown => "Slide down",
+            TransitionType::CircleOpen => "Circle open",
+            TransitionType::CircleClose => "Circle close",
+            TransitionType::CircleCrop => "Circle crop",
+            TransitionType::RectCrop => "Rectangle crop",
+            TransitionType::Radial => "Radial",
+            TransitionType::Pixelize => "Pixelize",
+            TransitionType::ZoomIn => "Zoom in",
+        }
+    }
+}
+
+// Transition from the previous clip into this one; the two overlap for
+// `duration` seconds
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Transition {
+    #[serde(rename = "type")]
+    pub kind: TransitionType,
+    pub duration: f64,
+}
+
+// xfade's own limit
+const MAX_TRANSITION_DURATION: f64 = 60.0;
+
+impl Transition {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.duration > 0.0 && self.duration <= MAX_TRANSITION_DURATION) {
+            return Err(format!(
+                "Transition duration must be between 0 and {} seconds, got {}",
+                MAX_TRANSITION_DURATION, self.duration
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Each transition has to fit inside both clips it joins. A clip with
+// transitions at both ends needs room for the two, or the second would start
+// before the first has finished. `lengths` are the clips' timeline lengths.
+pub fn validate_fit(lengths: &[f64], transitions: &[Option<Transition>]) -> Result<(), String> {
+    let overlap = |i: usize| transitions.get(i).copied().flatten().filter(|_| i > 0).map_or(0.0, |t| t.duration);
+    for i in 1..lengths.len() {
+        let incoming = overlap(i);
+        if incoming > 0.0 && (incoming >= lengths[i - 1] || incoming >= lengths[i]) {
+            return Err(format!(
+                "Transition into clip {} ({}s) must be shorter than both clips it joins ({}s and {}s)",
+                i + 1,
+                incoming,
+                lengths[i - 1],
+                lengths[i]
+            ));
+        }
+    }
+    for (i, &length) in lengths.iter().enumerate() {
+        let (incoming, outgoing) = (overlap(i), overlap(i + 1));
+        if incoming > 0.0 && outgoing > 0.0 && incoming + outgoing > length {
+            return Err(format!(
+                "Clip {} ({}s) is too short for its transitions ({}s in, {}s out)",
+                i + 1,
+                length,
+                incoming,
+                outgoing
+            ));
+        }
+    }
+    Ok(())
+}
+
+// filter_complex joining the inputs 0..n (one per clip, `lengths` seconds
+// each) into [vout], and [aout] with `with_audio`. `transitions[i]` is the
+// transition into clip i; the first entry is ignored. Each xfade starts
+// where the joined stream so far ends, less the overlap.
+pub fn join_filter(lengths: &[f64], transitions: &[Option<Transition>], with_audio: bool) -> String {
+    let mut graph = Vec::new();
+    // xfade needs both inputs on the same timebase
+    for i in 0..lengths.len() {
+        graph.push(format!("[{i}:v]settb=AVTB[v{i}]"));
+    }
+    let (mut video, mut audio) = ("[v0]".to_string(), "[0:a]".to_string());
+    let mut joined_length = lengths[0];
+    for i in 1..lengths.len() {
+        let last = i + 1 == lengths.len();
+        let (video_out, audio_out) = if last {
+            ("[vout]".to_string(), "[aout]".to_string())
+        } else {
+            (format!("[vj{i}]"), format!("[aj{i}]"))
+        };
+        match transitions[i] {
+            Some(transition) => {
+                graph.push(format!(
+                    "{video}[v{i}]xfade=transition={}:duration={}:offset={}{video_out}",
+                    transition.kind.xfade_name(),
+                    transition.duration,
+                    joined_length - transition.duration
+                ));
+                if with_audio {
+                    graph.push(format!("{audio}[{i}:a]acrossfade=d={}{audio_out}", transition.duration));
+                }
+                joined_length += lengths[i] - transition.duration;
+            }
+            None => {
+                graph.push(format!("{video}[v{i}]concat=n=2:v=1:a=0{video_out}"));
+                if with_audio {
+                    graph.push(format!("{audio}[{i}:a]concat=n=2:v=0:a=1{audio_out}"));
+                }
+                joined_length += lengths[i];
+            }
+        }
+        video = video_out;
+        audio = audio_out;
+    }
+    graph.join(";")
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransitionOption {
+    // Value for a clip's `transition.type`
+    id: TransitionType,
+    label: String,
+}
+
+// Command listing the transition styles, for the transition picker
+#[tauri::command]
+pub async fn list_transitions() -> Result<Vec<TransitionOption>, String> {
+    Ok(TransitionType::ALL
+        .iter()
+        .map(|&kind| TransitionOption { id: kind, label: kind.label().to_string() })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(kind: TransitionType, duration: f64) -> Option<Transition> {
+        Some(Transition { kind, duration })
+    }
+
+    #[test]
+    fn xfade_offsets_follow_the_joined_length() {
+        let transitions = [None, transition(TransitionType::Fade, 2.0), transition(TransitionType::Dissolve, 1.5)];
+        assert_eq!(
+            join_filter(&[10.0, 8.0, 6.0], &transitions, true),
+            "[0:v]settb=AVTB[v0];[1:v]settb=AVTB[v1];[2:v]settb=AVTB[v2];\
+             [v0][v1]xfade=transition=fade:duration=2:offset=8[vj1];[0:a][1:a]acrossfade=d=2[aj1];\
+             [vj1][v2]xfade=transition=dissolve:duration=1.5:offset=14.5[vout];[aj1][2:a]acrossfade=d=1.5[aout]"
+        );
+    }
+
+    #[test]
+    fn cuts_add_the_whole_clip_to_later_offsets() {
+        let transitions = [None, None, transition(TransitionType::WipeLeft, 1.0)];
+        assert_eq!(
+            join_filter(&[10.0, 8.0, 6.0], &transitions, false),
+            "[0:v]settb=AVTB[v0];[1:v]settb=AVTB[v1];[2:v]settb=AVTB[v2];\
+             [v0][v1]concat=n=2:v=1:a=0[vj1];\
+             [vj1][v2]xfade=transition=wipeleft:duration=1:offset=17[vout]"
+        );
+    }
+
+    #[test]
+    fn transitions_must_fit_both_neighbours() {
+        let lengths = [10.0, 3.0, 6.0];
+        assert!(validate_fit(&lengths, &[None, transition(TransitionType::Fade, 2.0), None]).is_ok());
+        // Longer than the clip after it, then than the clip before it
+        let into_short = validate_fit(&[10.0, 3.0], &[None, transition(TransitionType::Fade, 4.0)]);
+        assert!(into_short.unwrap_err().starts_with("Transition into clip 2"));
+        let out_of_short = validate_fit(&[3.0, 10.0], &[None, transition(TransitionType::Fade, 4.0)]);
+        assert!(out_of_short.unwrap_err().starts_with("Transition into clip 2"));
+        // Each fits, but clip 2 can't hold both
+        let both_ends = [None, transition(TransitionType::Fade, 2.0), transition(TransitionType::Fade, 1.5)];
+        assert!(validate_fit(&lengths, &both_ends).unwrap_err().starts_with("Clip 2 (3s) is too short"));
+        // The first clip has no incoming transition, whatever it says
+        assert!(validate_fit(&lengths, &[transition(TransitionType::Fade, 30.0), None, None]).is_ok());
+    }
+}
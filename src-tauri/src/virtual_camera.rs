@@ -0,0 +1,301 @@
+// Virtual camera output, so video calls and OBS can take rVJ's preview as a
+// webcam (see frame_sink.rs). On Linux the frames go to a v4l2loopback
+// device through ffmpeg's v4l2 muxer. On Windows they go to OBS Studio's
+// virtual camera, which apps see as "OBS Virtual Camera" once OBS is
+// installed: ffmpeg converts the frames to NV12 and rVJ writes them into the
+// shared-memory queue the camera reads, as OBS itself does. macOS would need
+// a CoreMediaIO camera extension of its own, so there the commands report
+// that it isn't available.
+
+use std::process::Command;
+
+use crate::capabilities::CapabilitiesCache;
+use crate::frame_sink::SinkSlot;
+
+pub struct VirtualCamera(pub SinkSlot);
+
+impl Default for VirtualCamera {
+    fn default() -> Self {
+        VirtualCamera(SinkSlot::new("virtual-camera", "Virtual camera"))
+    }
+}
+
+// The first v4l2loopback device, which names itself "Dummy video device" by
+// default. Cameras the system really has are left alone.
+#[cfg(target_os = "linux")]
+fn find_loopback_device() -> Option<String> {
+    let mut devices: Vec<_> = std::fs::read_dir("/sys/class/video4linux")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let label = std::fs::read_to_string(entry.path().join("name")).ok()?;
+            let is_loopback = label.contains("Dummy video device") || label.to_ascii_lowercase().contains("loopback");
+            is_loopback.then(|| format!("/dev/{}", entry.file_name().to_string_lossy()))
+        })
+        .collect();
+    devices.sort();
+    devices.into_iter().next()
+}
+
+#[cfg(target_os = "linux")]
+fn start_camera(
+    app_handle: &tauri::AppHandle,
+    device: Option<String>,
+    slot: &SinkSlot,
+    capabilities: &CapabilitiesCache,
+) -> Result<String, String> {
+    let device = match device {
+        Some(device) if std::path::Path::new(&device).exists() => device,
+        Some(device) => return Err(format!("Virtual camera device not found: {}", device)),
+        None => find_loopback_device()
+            .ok_or("No virtual camera device found; load the v4l2loopback module (modprobe v4l2loopback)")?,
+    };
+    crate::capabilities::require_muxer(app_handle, capabilities, "v4l2", "Virtual camera output")?;
+    let mut cmd = Command::new(crate::resolve_ffmpeg(app_handle)?);
+    cmd.args(["-loglevel", "error"]);
+    cmd.args(crate::frame_sink::MJPEG_STDIN_ARGS);
+    // 4:2:0 is what calling apps accept most widely
+    cmd.args(["-fps_mode", "passthrough", "-pix_fmt", "yuv420p", "-f", "v4l2", &device]);
+    slot.start(cmd, device.clone())?;
+    Ok(device)
+}
+
+// OBS's virtual camera reads frames of this size, at about this rate; the
+// preview is letterboxed into it
+#[cfg(target_os = "windows")]
+const OBS_CAMERA_SIZE: (u32, u32) = (1280, 720);
+#[cfg(target_os = "windows")]
+const OBS_CAMERA_FPS: u64 = 30;
+
+#[cfg(target_os = "windows")]
+const OBS_CAMERA_NAME: &str = "OBS Virtual Camera";
+
+// The queue OBS's virtual camera (plugins/win-dshow in OBS Studio) reads:
+// a named file mapping holding a header and three NV12 frame slots, each
+// after a 32-byte frame header holding its timestamp. The writer fills the
+// slot after the last one written and then points the reader at it.
+#[cfg(target_os = "windows")]
+mod obs_queue {
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const NAME: &str = "OBSVirtualCamVideo";
+
+    const STATE_STARTING: u32 = 1;
+    const STATE_READY: u32 = 2;
+    const STATE_STOPPING: u32 = 3;
+
+    // Byte offsets in the header: write_idx, read_idx and state (u32s), the
+    // three slot offsets (u32s), the queue type (0, video), the frame width
+    // and height (u32s), then the frame interval in 100 ns units (u64, after
+    // 4 bytes' padding) and 32 reserved bytes
+    const WRITE_INDEX: usize = 0;
+    const READ_INDEX: usize = 4;
+    const STATE: usize = 8;
+    const OFFSETS: usize = 12;
+    const WIDTH: usize = 28;
+    const HEIGHT: usize = 32;
+    const INTERVAL: usize = 40;
+    const HEADER_SIZE: usize = 80;
+
+    const FRAME_HEADER_SIZE: usize = 32;
+    const ALIGNMENT: usize = 32;
+
+    const INVALID_HANDLE_VALUE: isize = -1;
+    const PAGE_READWRITE: u32 = 0x04;
+    const FILE_MAP_READ: u32 = 0x04;
+    const FILE_MAP_ALL_ACCESS: u32 = 0x000f_001f;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateFileMappingW(
+            file: isize,
+            attributes: *const c_void,
+            protect: u32,
+            size_high: u32,
+            size_low: u32,
+            name: *const u16,
+        ) -> isize;
+        fn OpenFileMappingW(access: u32, inherit: i32, name: *const u16) -> isize;
+        fn MapViewOfFile(mapping: isize, access: u32, offset_high: u32, offset_low: u32, bytes: usize) -> *mut c_void;
+        fn UnmapViewOfFile(address: *const c_void) -> i32;
+        fn CloseHandle(handle: isize) -> i32;
+    }
+
+    fn align(size: usize) -> usize {
+        size.div_ceil(ALIGNMENT) * ALIGNMENT
+    }
+
+    pub struct Queue {
+        mapping: isize,
+        view: *mut u8,
+        offsets: [usize; 3],
+        frame_size: usize,
+    }
+
+    // The view is only touched through the Queue that owns it
+    unsafe impl Send for Queue {}
+
+    impl Queue {
+        // Opens the queue for frames of `width`x`height`, `interval` 100 ns
+        // units apart. Fails when something, OBS included, already feeds the
+        // camera.
+        pub fn create(width: u32, height: u32, interval: u64) -> Result<Queue, String> {
+            let frame_size = width as usize * height as usize * 3 / 2;
+            let mut offsets = [0; 3];
+            let mut size = align(HEADER_SIZE);
+            for offset in &mut offsets {
+                *offset = size;
+                size = align(size + FRAME_HEADER_SIZE + frame_size);
+            }
+            let name: Vec<u16> = NAME.encode_utf16().chain(std::iter::once(0)).collect();
+            // SAFETY: plain Win32 calls on a NUL-terminated name; every handle
+            // and view is released on the paths that don't keep it
+            unsafe {
+                let existing = OpenFileMappingW(FILE_MAP_READ, 0, name.as_ptr());
+                if existing != 0 {
+                    CloseHandle(existing);
+                    return Err("The OBS virtual camera is already in use, e.g. by OBS itself".to_string());
+                }
+                let mapping = CreateFileMappingW(
+                    INVALID_HANDLE_VALUE,
+                    std::ptr::null(),
+                    PAGE_READWRITE,
+                    0,
+                    size as u32,
+                    name.as_ptr(),
+                );
+                if mapping == 0 {
+                    return Err(format!("Failed to open the OBS virtual camera: {}", std::io::Error::last_os_error()));
+                }
+                let view = MapViewOfFile(mapping, FILE_MAP_ALL_ACCESS, 0, 0, 0) as *mut u8;
+                if view.is_null() {
+                    let error = std::io::Error::last_os_error();
+                    CloseHandle(mapping);
+                    return Err(format!("Failed to open the OBS virtual camera: {}", error));
+                }
+                let queue = Queue { mapping, view, offsets, frame_size };
+                for (i, offset) in offsets.iter().enumerate() {
+                    queue.write_u32(OFFSETS + i * 4, *offset as u32);
+                }
+                queue.write_u32(WIDTH, width);
+                queue.write_u32(HEIGHT, height);
+                (queue.view.add(INTERVAL) as *mut u64).write_unaligned(interval);
+                queue.write_u32(STATE, STATE_STARTING);
+                Ok(queue)
+            }
+        }
+
+        fn field(&self, offset: usize) -> &AtomicU32 {
+            // SAFETY: every field offset is 4-byte aligned and inside the
+            // header, and the view outlives &self
+            unsafe { &*(self.view.add(offset) as *const AtomicU32) }
+        }
+
+        fn write_u32(&self, offset: usize, value: u32) {
+            self.field(offset).store(value, Ordering::Release);
+        }
+
+        pub fn frame_size(&self) -> usize {
+            self.frame_size
+        }
+
+        // Publishes one NV12 frame of frame_size() bytes
+        pub fn write(&mut self, frame: &[u8], timestamp: u64) {
+            let index = self.field(WRITE_INDEX).fetch_add(1, Ordering::AcqRel).wrapping_add(1);
+            let offset = self.offsets[index as usize % 3];
+            let len = frame.len().min(self.frame_size);
+            // SAFETY: the slot holds its frame header and frame_size bytes
+            unsafe {
+                (self.view.add(offset) as *mut u64).write_unaligned(timestamp);
+                std::ptr::copy_nonoverlapping(frame.as_ptr(), self.view.add(offset + FRAME_HEADER_SIZE), len);
+            }
+            self.write_u32(READ_INDEX, index);
+            self.write_u32(STATE, STATE_READY);
+        }
+    }
+
+    impl Drop for Queue {
+        fn drop(&mut self) {
+            // The camera goes back to its placeholder picture
+            self.write_u32(STATE, STATE_STOPPING);
+            // SAFETY: the view and mapping are this queue's, released once
+            unsafe {
+                UnmapViewOfFile(self.view as *const c_void);
+                CloseHandle(self.mapping);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn start_camera(
+    app_handle: &tauri::AppHandle,
+    _device: Option<String>,
+    slot: &SinkSlot,
+    _capabilities: &CapabilitiesCache,
+) -> Result<String, String> {
+    use std::io::Read;
+    use std::time::Instant;
+
+    let (width, height) = OBS_CAMERA_SIZE;
+    // Our own running camera holds the queue until it stops. The queue is
+    // opened before ffmpeg starts, so a camera in use fails the command.
+    slot.stop();
+    let mut queue = obs_queue::Queue::create(width, height, 10_000_000 / OBS_CAMERA_FPS)?;
+    let mut cmd = Command::new(crate::resolve_ffmpeg(app_handle)?);
+    cmd.args(["-loglevel", "error"]);
+    cmd.args(crate::frame_sink::MJPEG_STDIN_ARGS);
+    cmd.args([
+        "-fps_mode", "passthrough",
+        "-vf", &format!(
+            "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1",
+            w = width,
+            h = height
+        ),
+        "-pix_fmt", "nv12",
+        "-f", "rawvideo",
+        "pipe:1",
+    ]);
+    slot.start_with_output(cmd, OBS_CAMERA_NAME.to_string(), move |mut stdout| {
+        let started = Instant::now();
+        let mut frame = vec![0; queue.frame_size()];
+        // Ends when the output stops and ffmpeg closes its stdout; dropping
+        // the queue then releases the camera
+        while stdout.read_exact(&mut frame).is_ok() {
+            queue.write(&frame, started.elapsed().as_nanos() as u64);
+        }
+    })?;
+    Ok(OBS_CAMERA_NAME.to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn start_camera(
+    _app_handle: &tauri::AppHandle,
+    _device: Option<String>,
+    _slot: &SinkSlot,
+    _capabilities: &CapabilitiesCache,
+) -> Result<String, String> {
+    Err("Virtual camera output isn't available on this platform yet".to_string())
+}
+
+// Command to show the preview on a virtual camera and return its name. On
+// Linux `device` picks a v4l2loopback device such as "/dev/video10"; by
+// default the first one found is used. On Windows the picture goes to OBS's
+// virtual camera and `device` is ignored. `virtual-camera-stopped` reports it
+// going away on its own.
+#[tauri::command]
+pub async fn start_virtual_camera(
+    device: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, VirtualCamera>,
+    capabilities: tauri::State<'_, CapabilitiesCache>,
+) -> Result<String, String> {
+    start_camera(&app_handle, device, &state.0, &capabilities)
+}
+
+// Command to stop the virtual camera. Returns false if it wasn't running.
+#[tauri::command]
+pub async fn stop_virtual_camera(state: tauri::State<'_, VirtualCamera>) -> Result<bool, String> {
+    Ok(state.0.stop())
+}
@@ -0,0 +1,99 @@
+// Warnings ffmpeg prints during an export that still succeeds, e.g. a
+// deprecated pixel format or non-monotonous timestamps. They often explain a
+// subtle quality problem, so they are passed on to the user instead of being
+// dropped with the rest of the log.
+
+use std::collections::HashSet;
+
+// Makes ffmpeg tag each log line with its level, without changing which lines
+// it prints (the stats that progress falls back to included)
+pub const LOG_LEVEL_ARGS: [&str; 2] = ["-loglevel", "+level"];
+
+// A run with a systematic problem can warn on every frame
+const MAX_WARNINGS: usize = 50;
+
+// The message of a `[warning]` line, with the component that printed it:
+// "[mp4 @ 0x55d0c8] [warning] Non-monotonous DTS" gives "mp4: Non-monotonous DTS"
+fn parse_warning(line: &str) -> Option<String> {
+    let (context, message) = line.split_once("[warning] ")?;
+    let message = message.trim();
+    if message.is_empty() {
+        return None;
+    }
+    // The address after '@' differs between runs, so only the name is kept
+    let component = context
+        .trim()
+        .strip_prefix('[')
+        .and_then(|c| c.split(" @ ").next())
+        .map(|c| c.trim_end_matches([']', ' ']))
+        .filter(|c| !c.is_empty());
+    Some(match component {
+        Some(component) => format!("{}: {}", component, message),
+        None => message.to_string(),
+    })
+}
+
+// The same warning repeated with different timestamps or frame numbers counts
+// once
+fn dedup_key(warning: &str) -> String {
+    warning.chars().filter(|c| !c.is_ascii_digit()).collect()
+}
+
+#[derive(Default)]
+pub struct FfmpegWarnings {
+    seen: HashSet<String>,
+    list: Vec<String>,
+}
+
+impl FfmpegWarnings {
+    // Records the warning on `line`, if any. Returns it when it is new.
+    pub fn add(&mut self, line: &str) -> Option<String> {
+        let warning = parse_warning(line)?;
+        if self.list.len() >= MAX_WARNINGS || !self.seen.insert(dedup_key(&warning)) {
+            return None;
+        }
+        self.list.push(warning.clone());
+        Some(warning)
+    }
+
+    pub fn into_list(self) -> Vec<String> {
+        self.list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_warning_keeps_the_component_name() {
+        assert_eq!(
+            parse_warning("[mp4 @ 0x55d0c8] [warning] Non-monotonous DTS in output stream 0:1").as_deref(),
+            Some("mp4: Non-monotonous DTS in output stream 0:1")
+        );
+        assert_eq!(
+            parse_warning("[swscaler @ 0x7f00] [warning] deprecated pixel format used").as_deref(),
+            Some("swscaler: deprecated pixel format used")
+        );
+    }
+
+    #[test]
+    fn parse_warning_without_a_component() {
+        assert_eq!(parse_warning("[warning] Guessed channel layout").as_deref(), Some("Guessed channel layout"));
+    }
+
+    #[test]
+    fn parse_warning_skips_other_levels_and_empty_messages() {
+        assert_eq!(parse_warning("[mp4 @ 0x55d0c8] [info] Writing header"), None);
+        assert_eq!(parse_warning("[error] Invalid data found"), None);
+        assert_eq!(parse_warning("[mp4 @ 0x55d0c8] [warning]  "), None);
+    }
+
+    #[test]
+    fn repeated_warnings_count_once() {
+        let mut warnings = FfmpegWarnings::default();
+        assert!(warnings.add("[mp4 @ 0x1] [warning] Non-monotonous DTS; previous: 100, current: 90").is_some());
+        assert!(warnings.add("[mp4 @ 0x2] [warning] Non-monotonous DTS; previous: 200, current: 180").is_none());
+        assert_eq!(warnings.into_list().len(), 1);
+    }
+}
@@ -3,9 +3,17 @@ import { listen } from '@tauri-apps/api/event';
 import { TimelineClip } from '@/types';
 
 export type ExportProgress = {
+    task_id: string | null;
     percent: number;
 };
 
+type ExportFinished = {
+    task_id: string;
+    result: { output_path: string; warnings: string[] } | null;
+    error: string | null;
+    cancelled: boolean;
+};
+
 /**
  * Invokes the native Rust FFmpeg export command
  */
@@ -21,9 +29,23 @@ export async function exportVideoNative(
         throw new Error('Native export is only available in desktop mode');
     }
 
+    // The export runs as a background task; its events carry the task id
+    let taskId: string | null = null;
+    let settle: ((event: ExportFinished) => void) | null = null;
+    const early: ExportFinished[] = [];
+
     // Listen for progress events from Rust
     const unlisten = await listen<ExportProgress>('export-progress', (event) => {
-        onProgress(event.payload.percent);
+        if (taskId === null || event.payload.task_id === taskId) {
+            onProgress(event.payload.percent);
+        }
+    });
+    const unlistenFinished = await listen<ExportFinished>('export-finished', (event) => {
+        if (settle) {
+            settle(event.payload);
+        } else {
+            early.push(event.payload);
+        }
     });
 
     try {
@@ -34,17 +56,34 @@ export async function exportVideoNative(
             end_time: clip.endTime || clip.originalDuration || 0,
         }));
 
-        const result = await invoke<string>('export_video', {
+        const id = await invoke<string>('export_video', {
             clips: clipData,
             audioPath,
             outputPath,
         });
+        taskId = id;
 
-        return result;
+        const finished = await new Promise<ExportFinished>((resolve) => {
+            const done = early.find((event) => event.task_id === id);
+            if (done) {
+                resolve(done);
+                return;
+            }
+            settle = (event) => {
+                if (event.task_id === id) {
+                    resolve(event);
+                }
+            };
+        });
+        if (!finished.result) {
+            throw new Error(finished.error ?? 'Export failed');
+        }
+        return finished.result.output_path;
     } catch (error) {
         console.error('Native export failed:', error);
         throw error;
     } finally {
         unlisten();
+        unlistenFinished();
     }
 }
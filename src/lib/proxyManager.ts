@@ -123,8 +123,9 @@ export async function generateProxy(
     console.log(`🎬 PROXY: Generating ${quality} proxy for ${originalPath}`);
 
     try {
-        // Call Rust command to generate proxy via FFmpeg
-        await invoke('generate_proxy_video', {
+        // Call Rust command to generate proxy via FFmpeg. Sources that are
+        // already light come back as is, with `skipped` set.
+        const result = await invoke<{ path: string; skipped: boolean }>('generate_proxy_video', {
             inputPath: originalPath,
             outputPath: outputPath,
             width: settings.width,
@@ -134,10 +135,14 @@ export async function generateProxy(
 
         // Cache the result
         const cacheKey = `${originalPath}_${quality}`;
-        proxyCache.set(cacheKey, outputPath);
+        proxyCache.set(cacheKey, result.path);
 
-        console.log(`🎬 PROXY: Successfully generated proxy at ${outputPath}`);
-        return outputPath;
+        if (result.skipped) {
+            console.log(`🎬 PROXY: ${originalPath} is already light enough, using it directly`);
+        } else {
+            console.log(`🎬 PROXY: Successfully generated proxy at ${result.path}`);
+        }
+        return result.path;
     } catch (error) {
         console.error(`🎬 PROXY: Failed to generate proxy:`, error);
         throw error;